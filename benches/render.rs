@@ -0,0 +1,57 @@
+//! Measures how long the engine takes to render a held note at different buffer sizes. Run with
+//! `cargo bench` and compare the numbers before and after changing the graph.
+
+use std::{
+    hint::black_box,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use synthy::{engine::SynthyEngine, SynthyParams};
+
+const SAMPLE_RATE: f32 = 48_000.0;
+/// Seconds of audio rendered for every measurement
+const RENDER_LENGTH: f32 = 10.0;
+const BUFFER_SIZES: [usize; 5] = [32, 64, 128, 512, 1024];
+const NOTE: u8 = 60;
+const VELOCITY: u8 = 100;
+
+fn main() {
+    println!(
+        "{:>8} {:>12} {:>12} {:>10}",
+        "buffer", "ns/sample", "ns/buffer", "realtime"
+    );
+    for buffer_size in BUFFER_SIZES {
+        let (elapsed, samples) = render(buffer_size);
+        let per_sample = elapsed.as_nanos() as f64 / samples as f64;
+        println!(
+            "{:>8} {:>12.1} {:>12.0} {:>9.1}x",
+            buffer_size,
+            per_sample,
+            per_sample * buffer_size as f64,
+            samples as f64 / SAMPLE_RATE as f64 / elapsed.as_secs_f64(),
+        );
+    }
+}
+
+/// Render `RENDER_LENGTH` seconds of a held note in buffers of `buffer_size` samples, returning
+/// how long that took and how many samples were rendered
+fn render(buffer_size: usize) -> (Duration, usize) {
+    let mut engine = SynthyEngine::new(Arc::pin(SynthyParams::default()));
+    engine.initialize(SAMPLE_RATE, false);
+    let mut left = vec![0f32; buffer_size];
+    let mut right = vec![0f32; buffer_size];
+
+    // Render a second first so the measurement doesn't include the attack or any warmup
+    engine.note_on(NOTE, VELOCITY);
+    for _ in 0..(SAMPLE_RATE as usize / buffer_size) {
+        engine.render([&mut left, &mut right]);
+    }
+
+    let buffers = (RENDER_LENGTH * SAMPLE_RATE) as usize / buffer_size;
+    let start = Instant::now();
+    for _ in 0..buffers {
+        engine.render([&mut left, &mut right]);
+        black_box((&left, &right));
+    }
+    (start.elapsed(), buffers * buffer_size)
+}
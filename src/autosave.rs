@@ -0,0 +1,158 @@
+use crate::{
+    preset::{Preset, PRESET_EXTENSION},
+    SynthyParams,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How often every instance snapshots its patch
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// Autosaves that haven't been rewritten for this long belong to instances that are gone
+const STALE_AFTER: Duration = Duration::from_secs(30);
+
+/// Numbers the instances in this process so each gets its own autosave
+static NEXT_INSTANCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Where every instance keeps its autosave
+fn dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("synthy").join("autosave"))
+}
+
+/// Snapshots the patch to disk from a background thread and removes the snapshot again when
+/// dropped. A snapshot that outlives its instance means the host went down without unloading the
+/// plugin, so a later instance can offer to restore it.
+pub struct Autosave {
+    /// Dropping this wakes the thread up and stops it
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    path: PathBuf,
+}
+
+impl Autosave {
+    pub fn start(params: Pin<Arc<SynthyParams>>) -> Option<Self> {
+        let dir = dir()?;
+        fs::create_dir_all(&dir)
+            .map_err(|err| log::error!("failed to create {}: {}", dir.display(), err))
+            .ok()?;
+        remove_stale_temporaries(&dir);
+        // The start time keeps a later process that happens to get the same ID from overwriting
+        // an autosave that hasn't been restored yet
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let path = dir.join(format!(
+            "{}-{}-{}.{}",
+            started,
+            std::process::id(),
+            NEXT_INSTANCE.fetch_add(1, Ordering::Relaxed),
+            PRESET_EXTENSION
+        ));
+
+        let (stop, stop_receiver) = channel::<()>();
+        let thread_path = path.clone();
+        let thread = std::thread::Builder::new()
+            .name(String::from("synthy autosave"))
+            .spawn(move || loop {
+                // Rewritten every time, even when nothing changed, so the file's age tells
+                // whether its instance is still around
+                if let Err(err) = save(&Preset::capture(params.as_ref()), &thread_path) {
+                    log::warn!("failed to autosave to {}: {}", thread_path.display(), err);
+                }
+                match stop_receiver.recv_timeout(AUTOSAVE_INTERVAL) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    _ => break,
+                }
+            })
+            .map_err(|err| log::error!("failed to spawn the autosave thread: {}", err))
+            .ok()?;
+
+        Some(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+            path,
+        })
+    }
+}
+
+impl Drop for Autosave {
+    fn drop(&mut self) {
+        self.stop = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // Shutting down cleanly leaves nothing to restore
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Write to a temporary file first so a crash halfway through can't leave a broken snapshot
+fn save(preset: &Preset, path: &Path) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    preset.save(&temporary)?;
+    fs::rename(temporary, path)
+}
+
+/// Files with the given extension in `dir` that haven't been written to for a while, with when
+/// they were last written
+fn stale_files(dir: &Path, extension: &str) -> Vec<(PathBuf, SystemTime)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != extension {
+                return None;
+            }
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            let stale = modified.elapsed().ok()? > STALE_AFTER;
+            stale.then(|| (path, modified))
+        })
+        .collect()
+}
+
+/// Autosaves that were left behind by instances that didn't shut down cleanly, with when they
+/// were last written
+fn orphans() -> Vec<(PathBuf, SystemTime)> {
+    dir()
+        .map(|dir| stale_files(&dir, PRESET_EXTENSION))
+        .unwrap_or_default()
+}
+
+/// Delete the temporary files of instances that went down halfway through an autosave. Nothing
+/// can restore those, they'd only pile up.
+fn remove_stale_temporaries(dir: &Path) {
+    for (path, _) in stale_files(dir, "tmp") {
+        if let Err(err) = fs::remove_file(&path) {
+            log::warn!("failed to remove {}: {}", path.display(), err);
+        }
+    }
+}
+
+/// The most recent autosave left behind by an instance that didn't shut down cleanly
+pub fn find_orphan() -> Option<PathBuf> {
+    orphans()
+        .into_iter()
+        .max_by_key(|(_, modified)| *modified)
+        .map(|(path, _)| path)
+}
+
+/// Delete an autosave left behind by an instance that didn't shut down cleanly once it has been
+/// restored or declined. The others stay around to be offered one after the other.
+pub fn remove_orphan(path: &Path) {
+    if let Err(err) = fs::remove_file(path) {
+        log::warn!("failed to remove {}: {}", path.display(), err);
+    }
+}
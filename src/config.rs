@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::PathBuf, sync::RwLock};
+
+/// Name of the user's config file, inside synthy's folder in the user's config directory
+const CONFIG_FILE: &str = "config.json";
+
+fundsp::lazy_static::lazy_static! {
+    /// Read from disk the first time it's needed and shared by every instance in this process
+    static ref CONFIG: RwLock<Config> = RwLock::new(Config::load());
+}
+
+/// The editor's color scheme
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ThemeName {
+    Dark,
+    Light,
+}
+
+impl ThemeName {
+    pub const ALL: [Self; 2] = [Self::Dark, Self::Light];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Light => "light",
+        }
+    }
+}
+
+/// Defaults the user can set for every instance, stored in the user's config directory
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Size of the editor's contents in pixels per point
+    pub ui_scale: f32,
+    pub theme: ThemeName,
+    /// Where presets are saved from the editor
+    pub preset_dir: PathBuf,
+    /// Maximum length of the envelopes in new instances, in seconds
+    pub env_length: f32,
+    /// The most detailed log messages that get written, applied when the log gets set up
+    pub log_level: log::LevelFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ui_scale: 1.0,
+            theme: ThemeName::Dark,
+            preset_dir: dirs::data_dir()
+                .unwrap_or_default()
+                .join("synthy")
+                .join("presets"),
+            env_length: crate::DEFAULT_ENV_LENGTH,
+            log_level: log::LevelFilter::Info,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config file lives, if the platform has a config directory
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("synthy").join(CONFIG_FILE))
+    }
+
+    /// The config as it was last loaded or stored
+    pub fn current() -> Self {
+        CONFIG
+            .read()
+            .map(|config| config.clone())
+            .unwrap_or_default()
+    }
+
+    /// Read the config file, falling back to the defaults when it's missing or unreadable
+    fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Self::default(),
+        };
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+                log::warn!("ignoring config {}: {}", path.display(), err);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Make this the current config and write it to the config file
+    pub fn store(&self) -> io::Result<()> {
+        if let Ok(mut config) = CONFIG.write() {
+            *config = self.clone();
+        }
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no config directory on this platform",
+            )
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
@@ -0,0 +1,103 @@
+use nih_plug::util;
+
+/// Settings for the [`Compressor`], read once per block
+pub struct CompressorSettings {
+    pub threshold: f32,
+    pub ratio: f32,
+    /// Attack time in seconds
+    pub attack: f32,
+    /// Release time in seconds
+    pub release: f32,
+    /// Makeup gain in decibels
+    pub makeup: f32,
+    /// How much of the compressed signal is mixed in
+    pub mix: f32,
+    /// How far the detector looks ahead of the output in samples, this is also the latency
+    pub lookahead: usize,
+}
+
+/// The longest supported lookahead in seconds
+pub const MAX_LOOKAHEAD: f32 = 0.01;
+
+/// Time constant for changes to the mix, so bypassing the compressor doesn't click
+const MIX_SMOOTHING_TIME: f32 = 0.01;
+
+/// A feed-forward stereo compressor with linked detection, running on the rendered output. Gain
+/// reduction is smoothed in the decibel domain. With lookahead enabled the output is delayed so
+/// the gain reduction can start before transients hit.
+#[derive(Default)]
+pub struct Compressor {
+    /// Delay lines for the lookahead, allocated up front for the longest lookahead
+    delay: [Vec<f32>; 2],
+    write_pos: usize,
+    /// The current gain reduction in decibels
+    reduction: f32,
+    /// The current smoothed mix
+    mix: f32,
+    sample_rate: f32,
+}
+
+impl Compressor {
+    /// Reset the state and allocate the delay lines, this must not be called from the audio thread
+    pub fn reset(&mut self, sample_rate: f32) {
+        let len = (MAX_LOOKAHEAD * sample_rate).ceil() as usize + 1;
+        self.delay = [vec![0f32; len], vec![0f32; len]];
+        self.write_pos = 0;
+        self.reduction = 0f32;
+        self.mix = 1f32;
+        self.sample_rate = sample_rate;
+    }
+
+    /// Jump straight to a new mix instead of smoothing towards it
+    pub fn snap_mix(&mut self, mix: f32) {
+        self.mix = mix;
+    }
+
+    /// The lookahead in samples for a lookahead time in seconds
+    pub fn lookahead_samples(&self, lookahead: f32) -> usize {
+        ((lookahead.clamp(0f32, MAX_LOOKAHEAD) * self.sample_rate).round() as usize)
+            .min(self.delay[0].len().saturating_sub(1))
+    }
+
+    /// Compress a block in place and return the largest gain reduction in decibels
+    pub fn process(&mut self, output: [&mut [f32]; 2], settings: &CompressorSettings) -> f32 {
+        let coefficient = |time: f32| 1f32 - (-1f32 / (time.max(1e-4) * self.sample_rate)).exp();
+        let attack = coefficient(settings.attack);
+        let release = coefficient(settings.release);
+        let slope = 1f32 - 1f32 / settings.ratio.max(1f32);
+        let mix_smoothing = coefficient(MIX_SMOOTHING_TIME);
+
+        let len = self.delay[0].len();
+        if len == 0 {
+            return 0f32;
+        }
+        let lookahead = settings.lookahead.min(len - 1);
+
+        let [left, right] = output;
+        let mut max_reduction = 0f32;
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            // The detector sees the signal right away while the output gets delayed
+            self.delay[0][self.write_pos] = *l;
+            self.delay[1][self.write_pos] = *r;
+            let read_pos = (self.write_pos + len - lookahead) % len;
+            self.write_pos = (self.write_pos + 1) % len;
+
+            let level = util::gain_to_db(l.abs().max(r.abs()));
+            let target = (level - settings.threshold).max(0f32) * slope;
+            let coefficient = if target > self.reduction {
+                attack
+            } else {
+                release
+            };
+            self.reduction += (target - self.reduction) * coefficient;
+            max_reduction = max_reduction.max(self.reduction);
+
+            self.mix += (settings.mix - self.mix) * mix_smoothing;
+            let gain = util::db_to_gain(settings.makeup - self.reduction);
+            let gain = 1f32 + (gain - 1f32) * self.mix;
+            *l = self.delay[0][read_pos] * gain;
+            *r = self.delay[1][read_pos] * gain;
+        }
+        max_reduction
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::{_mm_getcsr, _mm_setcsr};
+
+/// Flush-to-zero bit in the MXCSR register
+#[cfg(target_arch = "x86_64")]
+const FTZ: u32 = 0x8000;
+/// Denormals-are-zero bit in the MXCSR register
+#[cfg(target_arch = "x86_64")]
+const DAZ: u32 = 0x0040;
+
+/// Values smaller than this are treated as silence
+const DENORMAL_THRESHOLD: f32 = 1e-15;
+
+/// Enables FTZ/DAZ for as long as the guard is alive and restores the previous floating point
+/// state when dropped. Does nothing on architectures other than x86_64.
+pub struct ScopedFtz {
+    #[cfg(target_arch = "x86_64")]
+    previous: u32,
+}
+
+impl ScopedFtz {
+    #[cfg(target_arch = "x86_64")]
+    #[allow(deprecated)]
+    pub fn enable() -> Self {
+        // SAFETY: SSE is always available on x86_64
+        let previous = unsafe { _mm_getcsr() };
+        unsafe { _mm_setcsr(previous | FTZ | DAZ) };
+        Self { previous }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn enable() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for ScopedFtz {
+    #[allow(deprecated)]
+    fn drop(&mut self) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            _mm_setcsr(self.previous)
+        };
+    }
+}
+
+/// Flush tiny values to zero so long release tails don't decay into denormals
+#[inline]
+pub fn flush_denormal(value: f32) -> f32 {
+    if value.abs() < DENORMAL_THRESHOLD {
+        0f32
+    } else {
+        value
+    }
+}
@@ -0,0 +1,69 @@
+use super::rng::{Rng, RngStream, DEFAULT_SEED};
+
+/// How often the drift picks a new random target, in seconds
+const RETARGET_INTERVAL: f32 = 0.7;
+/// Time constant of the smoothing towards the target, in seconds
+const SMOOTHING_TIME: f32 = 0.5;
+
+/// The maximum pitch drift in semitones at full drift amount
+pub const MAX_PITCH_DRIFT: f32 = 0.15;
+/// The maximum relative operator level drift at full drift amount
+pub const MAX_LEVEL_DRIFT: f32 = 0.1;
+
+/// Which value a drift channel is applied to
+#[derive(Clone, Copy)]
+pub enum DriftTarget {
+    Pitch,
+    OpALevel,
+    OpBLevel,
+}
+
+const TARGET_COUNT: usize = 3;
+
+/// Slowly wandering random values in `-1..=1` to take the edge off the perfectly stable digital
+/// sound. Runs at block rate.
+pub struct Drift {
+    rng: Rng,
+    current: [f32; TARGET_COUNT],
+    target: [f32; TARGET_COUNT],
+    timer: f32,
+}
+
+impl Default for Drift {
+    fn default() -> Self {
+        Self {
+            rng: Rng::new(DEFAULT_SEED, RngStream::Drift),
+            current: [0f32; TARGET_COUNT],
+            target: [0f32; TARGET_COUNT],
+            timer: 0f32,
+        }
+    }
+}
+
+impl Drift {
+    /// Restart from the initial state so renders are repeatable
+    pub fn reset(&mut self, seed: u32) {
+        *self = Self {
+            rng: Rng::new(seed, RngStream::Drift),
+            ..Self::default()
+        };
+    }
+
+    /// Advance all drift channels by `dt` seconds
+    pub fn next(&mut self, dt: f32) {
+        self.timer -= dt;
+        if self.timer <= 0f32 {
+            self.timer = RETARGET_INTERVAL;
+            self.target = std::array::from_fn(|_| self.rng.bipolar());
+        }
+
+        let smoothing = 1f32 - (-dt / SMOOTHING_TIME).exp();
+        for (current, target) in self.current.iter_mut().zip(self.target) {
+            *current += (target - *current) * smoothing;
+        }
+    }
+
+    pub fn get(&self, target: DriftTarget) -> f32 {
+        self.current[target as usize]
+    }
+}
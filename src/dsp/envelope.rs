@@ -0,0 +1,411 @@
+use super::rng::Rng;
+use serde::{Deserialize, Serialize};
+
+/// When an envelope runs relative to the note, stored next to the envelope's points
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+pub struct EnvelopeTiming {
+    /// Time in seconds between the start of the note and the start of the envelope
+    pub delay: f32,
+    /// Index of the point the envelope stops at for as long as the note is held
+    pub hold: Option<usize>,
+}
+
+impl EnvelopeTiming {
+    /// The position on the envelope's time axis `elapsed` seconds into a held note
+    pub fn gate_time(&self, points: &[(f32, f32)], elapsed: f32) -> f32 {
+        let time = (elapsed - self.delay).max(0f32);
+        match self.hold.and_then(|index| points.get(index)) {
+            Some((hold_time, _)) => time.min(*hold_time),
+            None => time,
+        }
+    }
+
+    /// Keep the hold point on the same point after a point got inserted at `index`
+    pub fn point_inserted(&mut self, index: usize) {
+        if let Some(hold) = &mut self.hold {
+            if *hold >= index {
+                *hold += 1;
+            }
+        }
+    }
+
+    /// Keep the hold point on the same point after the point at `index` got removed
+    pub fn point_removed(&mut self, index: usize) {
+        self.hold = match self.hold {
+            Some(hold) if hold == index => None,
+            Some(hold) if hold > index => Some(hold - 1),
+            hold => hold,
+        };
+    }
+}
+
+/// Which of an envelope's end points are pinned to a level of 0 while editing
+#[derive(Clone, Copy)]
+pub struct EndpointLocks {
+    /// Pin the first point to 0,0. Unlocked, the first point can still only move vertically.
+    pub start: bool,
+    /// Pin the last point's level to 0
+    pub end: bool,
+}
+
+impl Default for EndpointLocks {
+    fn default() -> Self {
+        Self {
+            start: true,
+            end: true,
+        }
+    }
+}
+
+/// The level of an envelope at `time`, or `None` when `time` lies outside of the envelope. Points
+/// past `max_length` get pulled back to it. `start` is a time and level the envelope jumped
+/// from, the segment containing that time starts at that level instead of its first point so
+/// the jump doesn't click.
+pub fn level(
+    points: &[(f32, f32)],
+    max_length: f32,
+    time: f32,
+    start: Option<(f32, f32)>,
+) -> Option<f32> {
+    // A time on a point belongs to the segment starting there, so a jump onto a point starts from
+    // the jump's level. Only the last segment includes its end.
+    let last = points.len().saturating_sub(2);
+    points.windows(2).enumerate().find_map(|(index, segment)| {
+        let (left_time, right_time) = (segment[0].0.min(max_length), segment[1].0.min(max_length));
+        if time < left_time || time > right_time || (time == right_time && index < last) {
+            return None;
+        }
+        let left_level = match start {
+            Some((start_time, level)) if start_time >= left_time && start_time < right_time => {
+                level
+            }
+            _ => segment[0].1,
+        };
+        let normalized = (time - left_time) / (right_time - left_time).max(f32::EPSILON);
+        Some(left_level + (segment[1].1 - left_level) * normalized)
+    })
+}
+
+/// Where a note is in its life as far as an envelope is concerned
+#[derive(Clone, Copy, Debug)]
+pub enum Gate {
+    /// The note has been held for `elapsed` seconds
+    Held { elapsed: f32 },
+    /// The note got released `elapsed` seconds ago. The envelope continues from `start` on its
+    /// time axis, with the release stretched by `scale`.
+    Released {
+        start: f32,
+        elapsed: f32,
+        scale: f32,
+    },
+}
+
+/// An envelope as both the audio thread and the editor evaluate it, so the shape that's drawn is
+/// always the one that's heard
+#[derive(Clone, Copy)]
+pub struct EnvelopeShape<'a> {
+    pub points: &'a [(f32, f32)],
+    /// Points past this length in seconds get pulled back to it
+    pub max_length: f32,
+    pub timing: EnvelopeTiming,
+}
+
+impl<'a> EnvelopeShape<'a> {
+    /// The position on the envelope's time axis for a note in the given state
+    pub fn time(&self, gate: Gate) -> f32 {
+        match gate {
+            Gate::Held { elapsed } => self.timing.gate_time(self.points, elapsed),
+            Gate::Released {
+                start,
+                elapsed,
+                scale,
+            } => start + elapsed / scale,
+        }
+    }
+
+    /// Where the release segment starts, the release is the segment between the last two points
+    pub fn release_start(&self) -> f32 {
+        self.points
+            .get(self.points.len().saturating_sub(2))
+            .map(|(time, _)| time.min(self.max_length))
+            .unwrap_or_default()
+    }
+
+    /// Whether `time` lies past the envelope's last point
+    pub fn is_finished(&self, time: f32) -> bool {
+        self.points
+            .last()
+            .map(|(last, _)| time > last.min(self.max_length))
+            .unwrap_or(true)
+    }
+
+    /// The level at `time` on the envelope's time axis, see [`level`]
+    pub fn level(&self, time: f32, start: Option<(f32, f32)>) -> Option<f32> {
+        level(self.points, self.max_length, time, start)
+    }
+
+    /// The position on the time axis and the level there for a note in the given state
+    pub fn value_at(&self, gate: Gate, start: Option<(f32, f32)>) -> (f32, Option<f32>) {
+        let time = self.time(gate);
+        (time, self.level(time, start))
+    }
+}
+
+/// A standard ADSR envelope that generates an envelope's points, for when the full editor isn't
+/// needed
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Adsr {
+    /// Whether the points are generated from these settings instead of being edited directly
+    pub enabled: bool,
+    /// Attack time in seconds
+    pub attack: f32,
+    /// Decay time in seconds
+    pub decay: f32,
+    /// Sustain level in `0..=1`
+    pub sustain: f32,
+    /// Release time in seconds
+    pub release: f32,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            attack: 0.01,
+            decay: 0.3,
+            sustain: 0.7,
+            release: 0.5,
+        }
+    }
+}
+
+impl Adsr {
+    /// The point the envelope sustains on
+    pub const SUSTAIN_POINT: usize = 2;
+
+    /// Settings for an envelope with these times in seconds and sustain level, with the ADSR
+    /// mode turned off so the points stay editable
+    pub const fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            enabled: false,
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    /// Random settings with the ADSR mode turned on. Short times are more likely than long ones
+    /// and the whole envelope always fits in the default envelope length.
+    pub fn random(rng: &mut Rng) -> Self {
+        let time = |rng: &mut Rng, min: f32, max: f32| min + (max - min) * rng.unipolar().powi(2);
+        Self {
+            enabled: true,
+            attack: time(rng, 0.001, 1.5),
+            decay: time(rng, 0.01, 2.0),
+            sustain: rng.unipolar(),
+            release: time(rng, 0.01, 3.0),
+        }
+    }
+
+    /// The envelope's points. The release is the segment between the last two points, so it
+    /// starts from the sustain point.
+    pub fn points(&self) -> Vec<(f32, f32)> {
+        let decay_end = self.attack + self.decay;
+        vec![
+            (0f32, 0f32),
+            (self.attack, 1f32),
+            (decay_end, self.sustain),
+            (decay_end + self.release, 0f32),
+        ]
+    }
+
+    /// Replace an envelope's points and hold point with the ones described by these settings
+    pub fn apply(&self, points: &mut Vec<(f32, f32)>, timing: &mut EnvelopeTiming) {
+        *points = self.points();
+        timing.hold = Some(Self::SUSTAIN_POINT);
+    }
+}
+
+/// Starting points for an envelope, every envelope starts out as [`Self::Default`]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EnvelopeTemplate {
+    Default,
+    /// A fast attack that decays away without sustaining
+    Percussive,
+    /// A slow swell that sustains and fades out slowly
+    Pad,
+    /// Fully on for as long as the note is held
+    OrganGate,
+}
+
+impl EnvelopeTemplate {
+    pub const ALL: [Self; 4] = [Self::Default, Self::Percussive, Self::Pad, Self::OrganGate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Percussive => "percussive",
+            Self::Pad => "pad",
+            Self::OrganGate => "organ gate",
+        }
+    }
+
+    /// The ADSR settings the template is made from, the default template isn't an ADSR
+    pub fn adsr(self) -> Option<Adsr> {
+        match self {
+            Self::Default => None,
+            // Decays to silence and stays there until the key is let go
+            Self::Percussive => Some(Adsr::new(0.002, 0.6, 0.0, 0.1)),
+            Self::Pad => Some(Adsr::new(1.5, 1.0, 0.8, 2.5)),
+            Self::OrganGate => Some(Adsr::new(0.005, 0.001, 1.0, 0.01)),
+        }
+    }
+
+    pub fn points(self) -> Vec<(f32, f32)> {
+        match self.adsr() {
+            Some(adsr) => adsr.points(),
+            None => vec![
+                (0f32, 0f32),
+                (0.5f32, 1.0f32),
+                (1.0f32, 0.7f32),
+                (2.0f32, 0.5f32),
+                (3.0f32, 0.0f32),
+            ],
+        }
+    }
+
+    /// The point the envelope stops at while the note is held
+    pub fn hold(self) -> Option<usize> {
+        self.adsr().map(|_| Adsr::SUSTAIN_POINT)
+    }
+
+    /// Replace an envelope's points and hold point with the template's, keeping its delay
+    pub fn apply(self, points: &mut Vec<(f32, f32)>, timing: &mut EnvelopeTiming) {
+        *points = self.points();
+        timing.hold = self.hold();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Attack to 1, decay to 0.5 and release to 0
+    const POINTS: [(f32, f32); 4] = [(0.0, 0.0), (1.0, 1.0), (2.0, 0.5), (4.0, 0.0)];
+
+    fn shape(max_length: f32, timing: EnvelopeTiming) -> EnvelopeShape<'static> {
+        EnvelopeShape {
+            points: &POINTS,
+            max_length,
+            timing,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn interpolates_between_points() {
+        let shape = shape(8.0, EnvelopeTiming::default());
+        assert_close(shape.level(0.5, None).unwrap(), 0.5);
+        assert_close(shape.level(1.5, None).unwrap(), 0.75);
+        assert_close(shape.level(3.0, None).unwrap(), 0.25);
+        assert_eq!(shape.level(4.5, None), None);
+        assert!(!shape.is_finished(4.0));
+        assert!(shape.is_finished(4.5));
+    }
+
+    #[test]
+    fn hold_stops_on_the_hold_point_while_held() {
+        let shape = shape(
+            8.0,
+            EnvelopeTiming {
+                delay: 0.0,
+                hold: Some(2),
+            },
+        );
+        let (time, level) = shape.value_at(Gate::Held { elapsed: 1.5 }, None);
+        assert_close(time, 1.5);
+        assert_close(level.unwrap(), 0.75);
+        let (time, level) = shape.value_at(Gate::Held { elapsed: 10.0 }, None);
+        assert_close(time, 2.0);
+        assert_close(level.unwrap(), 0.5);
+        assert!(!shape.is_finished(time));
+    }
+
+    #[test]
+    fn delay_postpones_the_envelope() {
+        let shape = shape(
+            8.0,
+            EnvelopeTiming {
+                delay: 0.5,
+                hold: None,
+            },
+        );
+        let (time, level) = shape.value_at(Gate::Held { elapsed: 0.25 }, None);
+        assert_close(time, 0.0);
+        assert_close(level.unwrap(), 0.0);
+        let (time, level) = shape.value_at(Gate::Held { elapsed: 1.0 }, None);
+        assert_close(time, 0.5);
+        assert_close(level.unwrap(), 0.5);
+    }
+
+    #[test]
+    fn release_from_mid_segment_continues_from_the_current_level() {
+        let shape = shape(8.0, EnvelopeTiming::default());
+        // Released 1.5 seconds in, halfway through the decay
+        let current = shape.level(1.5, None).unwrap();
+        let start = shape.release_start();
+        assert_close(start, 2.0);
+        let released = |elapsed, scale| Gate::Released {
+            start,
+            elapsed,
+            scale,
+        };
+
+        let (time, level) = shape.value_at(released(0.0, 1.0), Some((start, current)));
+        assert_close(time, 2.0);
+        assert_close(level.unwrap(), 0.75);
+        let (_, level) = shape.value_at(released(1.0, 1.0), Some((start, current)));
+        assert_close(level.unwrap(), 0.375);
+        // A scale of 2 makes the release twice as long
+        let (time, level) = shape.value_at(released(1.0, 2.0), Some((start, current)));
+        assert_close(time, 2.5);
+        assert_close(level.unwrap(), 0.5625);
+        let (time, _) = shape.value_at(released(2.5, 1.0), Some((start, current)));
+        assert!(shape.is_finished(time));
+    }
+
+    #[test]
+    fn points_past_max_length_get_clamped() {
+        let shape = shape(3.0, EnvelopeTiming::default());
+        // The release segment now runs from 2 to 3 seconds
+        assert_close(shape.level(2.5, None).unwrap(), 0.25);
+        assert_close(shape.level(3.0, None).unwrap(), 0.0);
+        assert_eq!(shape.level(3.5, None), None);
+        assert!(!shape.is_finished(3.0));
+        assert!(shape.is_finished(3.1));
+
+        let shape = self::shape(1.5, EnvelopeTiming::default());
+        assert_close(shape.release_start(), 1.5);
+        // Clamped points collapse into a single instant without dividing by zero
+        assert!(shape.level(1.5, None).unwrap().is_finite());
+    }
+
+    #[test]
+    fn start_level_only_replaces_the_segment_it_falls_in() {
+        let shape = shape(8.0, EnvelopeTiming::default());
+        // Retriggered while the previous note was at 0.6
+        let start = Some((0.0, 0.6));
+        assert_close(shape.level(0.0, start).unwrap(), 0.6);
+        assert_close(shape.level(0.5, start).unwrap(), 0.8);
+        assert_close(shape.level(1.5, start).unwrap(), 0.75);
+    }
+}
@@ -0,0 +1,127 @@
+use fundsp::{audionode::*, hacker32::*, Float};
+use std::{f64::consts::TAU, marker::PhantomData};
+
+/// The response of an [`EqBand`]
+#[derive(Clone, Copy, PartialEq)]
+pub enum EqShape {
+    LowShelf,
+    Peak,
+    HighShelf,
+}
+
+/// A single equalizer band using the biquads from Robert Bristow-Johnson's cookbook. The
+/// coefficients are only recalculated when the inputs change. The inputs are:
+///
+/// 0. The signal
+/// 1. The center or corner frequency
+/// 2. The Q
+/// 3. The gain in decibels
+#[derive(Clone)]
+pub struct EqBand<T> {
+    shape: EqShape,
+    /// The frequency, Q and gain the coefficients were calculated for
+    settings: [f64; 3],
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+    sample_rate: f64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> EqBand<T> {
+    pub fn new(shape: EqShape) -> Self {
+        Self {
+            shape,
+            settings: [f64::NAN; 3],
+            b: [1.0, 0.0, 0.0],
+            a: [0.0, 0.0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+            sample_rate: DEFAULT_SR,
+            _marker: PhantomData,
+        }
+    }
+
+    fn update(&mut self, freq: f64, q: f64, gain: f64) {
+        if self.settings == [freq, q, gain] {
+            return;
+        }
+        self.settings = [freq, q, gain];
+
+        let amp = 10f64.powf(gain / 40.0);
+        let w0 = TAU * freq.clamp(10.0, self.sample_rate * 0.45) / self.sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        let shelf = 2.0 * amp.sqrt() * alpha;
+        let (b, a) = match self.shape {
+            EqShape::Peak => (
+                [1.0 + alpha * amp, -2.0 * cos, 1.0 - alpha * amp],
+                [1.0 + alpha / amp, -2.0 * cos, 1.0 - alpha / amp],
+            ),
+            EqShape::LowShelf => (
+                [
+                    amp * ((amp + 1.0) - (amp - 1.0) * cos + shelf),
+                    2.0 * amp * ((amp - 1.0) - (amp + 1.0) * cos),
+                    amp * ((amp + 1.0) - (amp - 1.0) * cos - shelf),
+                ],
+                [
+                    (amp + 1.0) + (amp - 1.0) * cos + shelf,
+                    -2.0 * ((amp - 1.0) + (amp + 1.0) * cos),
+                    (amp + 1.0) + (amp - 1.0) * cos - shelf,
+                ],
+            ),
+            EqShape::HighShelf => (
+                [
+                    amp * ((amp + 1.0) + (amp - 1.0) * cos + shelf),
+                    -2.0 * amp * ((amp - 1.0) + (amp + 1.0) * cos),
+                    amp * ((amp + 1.0) + (amp - 1.0) * cos - shelf),
+                ],
+                [
+                    (amp + 1.0) - (amp - 1.0) * cos + shelf,
+                    2.0 * ((amp - 1.0) - (amp + 1.0) * cos),
+                    (amp + 1.0) - (amp - 1.0) * cos - shelf,
+                ],
+            ),
+        };
+        self.b = [b[0] / a[0], b[1] / a[0], b[2] / a[0]];
+        self.a = [a[1] / a[0], a[2] / a[0]];
+    }
+}
+
+impl<T: Float> AudioNode for EqBand<T> {
+    const ID: u64 = 1006;
+    type Sample = T;
+    type Inputs = U4;
+    type Outputs = U1;
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.x = [0.0; 2];
+        self.y = [0.0; 2];
+        if let Some(sample_rate) = sample_rate {
+            self.sample_rate = sample_rate;
+            self.settings = [f64::NAN; 3];
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        self.update(input[1].to_f64(), input[2].to_f64(), input[3].to_f64());
+
+        let x = input[0].to_f64();
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        Frame::from([T::from_f64(y)])
+    }
+}
+
+/// A single equalizer band, see [`EqBand`]
+pub fn eq_band(shape: EqShape) -> An<EqBand<f32>> {
+    An(EqBand::new(shape))
+}
@@ -0,0 +1,51 @@
+use fundsp::hacker32::DEFAULT_SR;
+
+/// Time in seconds to fade between silence and full level
+pub const FADE_TIME: f32 = 0.005;
+
+/// A linear fade to and from silence, so the output can be muted without clicking
+pub struct Fade {
+    gain: f32,
+    /// How much the gain changes per sample
+    step: f32,
+}
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self {
+            gain: 1f32,
+            step: 1f32 / (FADE_TIME * DEFAULT_SR as f32),
+        }
+    }
+}
+
+impl Fade {
+    pub fn reset(&mut self, sample_rate: f32) {
+        self.step = 1f32 / (FADE_TIME * sample_rate).max(1f32);
+    }
+
+    /// Fade a block in place towards full level when `open`, and towards silence otherwise
+    pub fn process(&mut self, channels: [&mut [f32]; 2], open: bool) {
+        let target = match open {
+            true => 1f32,
+            false => 0f32,
+        };
+        let [left, right] = channels;
+        if self.gain == target {
+            if !open {
+                left.fill(0f32);
+                right.fill(0f32);
+            }
+            return;
+        }
+
+        for (left, right) in left.iter_mut().zip(right.iter_mut()) {
+            self.gain = match open {
+                true => (self.gain + self.step).min(1f32),
+                false => (self.gain - self.step).max(0f32),
+            };
+            *left *= self.gain;
+            *right *= self.gain;
+        }
+    }
+}
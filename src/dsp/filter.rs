@@ -0,0 +1,281 @@
+use fundsp::{audionode::*, hacker32::*, Float};
+use nih_plug::prelude::*;
+use std::{
+    f64::consts::{PI, TAU},
+    marker::PhantomData,
+};
+
+/// How the two filters are connected. Changing this rebuilds the graph.
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum FilterRouting {
+    /// Only the first filter is used
+    #[name = "single"]
+    Single,
+    /// The second filter processes the first filter's output
+    #[name = "serial"]
+    Serial,
+    /// Both filters process the signal and their outputs are mixed by the balance
+    #[name = "parallel"]
+    Parallel,
+    /// The first filter processes the left channel and the second filter the right channel
+    #[name = "split"]
+    Split,
+}
+
+impl Default for FilterRouting {
+    fn default() -> Self {
+        Self::Single
+    }
+}
+
+impl FilterRouting {
+    /// The output gains of both filters for a balance in `0..=1`
+    pub fn gains(self, balance: f32) -> (f32, f32) {
+        match self {
+            Self::Single | Self::Serial => (1f32, 1f32),
+            Self::Parallel => (1f32 - balance, balance),
+            // Both channels stay at full level in the middle
+            Self::Split => (
+                ((1f32 - balance) * 2f32).min(1f32),
+                (balance * 2f32).min(1f32),
+            ),
+        }
+    }
+}
+
+/// Which part of the signal the filters process. Changing this rebuilds the graph.
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum FilterPlacement {
+    /// Only the synthesized voice is filtered, the external input passes through untouched
+    #[name = "voice"]
+    Voice,
+    /// Everything is filtered, including the external input
+    #[name = "bus"]
+    Bus,
+}
+
+impl Default for FilterPlacement {
+    fn default() -> Self {
+        Self::Bus
+    }
+}
+
+/// The response of an [`Svf`]
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum FilterMode {
+    #[name = "lowpass"]
+    Lowpass,
+    #[name = "highpass"]
+    Highpass,
+    #[name = "bandpass"]
+    Bandpass,
+    /// Parallel resonant peaks tuned to a vowel
+    #[name = "formant"]
+    Formant,
+}
+
+impl Default for FilterMode {
+    fn default() -> Self {
+        Self::Lowpass
+    }
+}
+
+/// A four pole ladder style lowpass filter with a soft clipper in the feedback path, so driving
+/// it saturates the resonance like an analog ladder. The inputs are:
+///
+/// 0. The signal
+/// 1. The cutoff frequency
+/// 2. Resonance in `0..=1`, self-oscillating near the top
+/// 3. Drive, the gain applied before the soft clipper
+#[derive(Clone)]
+pub struct Ladder<T> {
+    stages: [f64; 4],
+    sample_rate: f64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for Ladder<T> {
+    fn default() -> Self {
+        Self {
+            stages: [0.0; 4],
+            sample_rate: DEFAULT_SR,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Float> AudioNode for Ladder<T> {
+    const ID: u64 = 1004;
+    type Sample = T;
+    type Inputs = U4;
+    type Outputs = U1;
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.stages = [0.0; 4];
+        if let Some(sample_rate) = sample_rate {
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let cutoff = input[1].to_f64().clamp(10.0, self.sample_rate * 0.45);
+        let feedback = input[2].to_f64().clamp(0.0, 1.0) * 4.0;
+        let drive = input[3].to_f64().max(0.0);
+
+        let g = 1.0 - (-TAU * cutoff / self.sample_rate).exp();
+        // The drive pushes both the input and the resonance into the clipper
+        let x = ((input[0].to_f64() - feedback * self.stages[3]) * drive).tanh();
+        let mut previous = x;
+        for stage in self.stages.iter_mut() {
+            *stage += g * (previous - *stage);
+            previous = *stage;
+        }
+
+        // Make up for the passband level lost to the resonance, and for part of the drive so
+        // pushing it mostly adds saturation rather than volume
+        let output = self.stages[3] * (1.0 + feedback) / drive.max(1.0).sqrt();
+        Frame::from([T::from_f64(output)])
+    }
+}
+
+/// A driven ladder lowpass, see [`Ladder`]
+pub fn ladder() -> An<Ladder<f32>> {
+    An(Ladder::default())
+}
+
+/// Formant frequencies in Hz for the vowels a, e, i, o and u
+const VOWELS: [[f64; 3]; 5] = [
+    [800.0, 1150.0, 2900.0],
+    [350.0, 2000.0, 2800.0],
+    [270.0, 2140.0, 2950.0],
+    [450.0, 800.0, 2830.0],
+    [325.0, 700.0, 2700.0],
+];
+/// Bandwidths of the formants in Hz at the default resonance
+const FORMANT_BANDWIDTHS: [f64; 3] = [80.0, 90.0, 120.0];
+/// Relative levels of the formants
+const FORMANT_GAINS: [f64; 3] = [1.0, 0.5, 0.25];
+
+/// The integrator state of a single state variable filter
+#[derive(Clone, Copy, Default)]
+struct SvfCore {
+    ic1: f64,
+    ic2: f64,
+}
+
+impl SvfCore {
+    /// Process one sample with the trapezoidal integrators described by Zavalishin. `g` is the
+    /// prewarped cutoff and `k` the damping. Returns the lowpass, bandpass and highpass outputs.
+    #[inline]
+    fn tick(&mut self, x: f64, g: f64, k: f64) -> (f64, f64, f64) {
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        let v3 = x - self.ic2;
+        let v1 = a1 * self.ic1 + a2 * v3;
+        let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+        self.ic1 = 2.0 * v1 - self.ic1;
+        self.ic2 = 2.0 * v2 - self.ic2;
+        (v2, v1, x - k * v1 - v2)
+    }
+}
+
+/// A state variable filter that can switch between responses without a rebuild. The formant
+/// mode runs a bank of parallel bandpasses tuned to a vowel instead. The inputs are:
+///
+/// 0. The signal
+/// 1. The cutoff frequency, not used by the formant mode
+/// 2. Resonance in `0..=1`
+/// 3. The [`FilterMode`] as a number
+/// 4. The vowel for the formant mode, morphing through a, e, i, o and u over `0..=4`
+#[derive(Clone)]
+pub struct Svf<T> {
+    core: SvfCore,
+    formants: [SvfCore; 3],
+    sample_rate: f64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for Svf<T> {
+    fn default() -> Self {
+        Self {
+            core: SvfCore::default(),
+            formants: [SvfCore::default(); 3],
+            sample_rate: DEFAULT_SR,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Svf<T> {
+    #[inline]
+    fn prewarp(&self, freq: f64) -> f64 {
+        (PI * freq.clamp(10.0, self.sample_rate * 0.45) / self.sample_rate).tan()
+    }
+
+    #[inline]
+    fn formant(&mut self, x: f64, resonance: f64, vowel: f64) -> f64 {
+        let vowel = vowel.clamp(0.0, (VOWELS.len() - 1) as f64);
+        let from = vowel.floor() as usize;
+        let to = (from + 1).min(VOWELS.len() - 1);
+        let t = vowel - from as f64;
+        let mut output = 0.0;
+        for i in 0..self.formants.len() {
+            let freq = VOWELS[from][i] + (VOWELS[to][i] - VOWELS[from][i]) * t;
+            let bandwidth = FORMANT_BANDWIDTHS[i] * (1.5 - resonance);
+            let k = bandwidth / freq;
+            let g = self.prewarp(freq);
+            let (_, bandpass, _) = self.formants[i].tick(x, g, k);
+            // Scaling by the damping gives the bandpass a unity peak
+            output += bandpass * k * FORMANT_GAINS[i];
+        }
+        output
+    }
+}
+
+impl<T: Float> AudioNode for Svf<T> {
+    const ID: u64 = 1005;
+    type Sample = T;
+    type Inputs = U5;
+    type Outputs = U1;
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.core = SvfCore::default();
+        self.formants = [SvfCore::default(); 3];
+        if let Some(sample_rate) = sample_rate {
+            self.sample_rate = sample_rate;
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let x = input[0].to_f64();
+        let resonance = input[2].to_f64().clamp(0.0, 1.0);
+        let mode = input[3].to_f64().round() as usize;
+        if mode == FilterMode::Formant as usize {
+            return Frame::from([T::from_f64(self.formant(x, resonance, input[4].to_f64()))]);
+        }
+
+        let k = 2.0 - 1.96 * resonance;
+        let g = self.prewarp(input[1].to_f64());
+        let (lowpass, bandpass, highpass) = self.core.tick(x, g, k);
+        let output = match mode {
+            1 => highpass,
+            2 => bandpass,
+            _ => lowpass,
+        };
+        Frame::from([T::from_f64(output)])
+    }
+}
+
+/// A multimode state variable filter, see [`Svf`]
+pub fn svf() -> An<Svf<f32>> {
+    An(Svf::default())
+}
@@ -0,0 +1,72 @@
+use nih_plug::prelude::*;
+
+/// How the glide time is interpreted
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum GlideMode {
+    /// Every glide takes the glide time, no matter the interval
+    #[name = "constant time"]
+    ConstantTime,
+    /// The glide time is the time it takes to glide one octave
+    #[name = "constant rate"]
+    ConstantRate,
+}
+
+/// Slews the played pitch towards the target note. Pitches are in (fractional) MIDI notes so
+/// glides sound linear to the ear.
+#[derive(Default)]
+pub struct Glide {
+    current: f32,
+    target: f32,
+    /// Semitones per second
+    rate: f32,
+}
+
+impl Glide {
+    /// Start gliding towards `pitch`, or jump there straight away if `glide` is false
+    pub fn set_target(&mut self, pitch: f32, time: f32, mode: GlideMode, glide: bool) {
+        self.target = pitch;
+        if !glide || time <= 0f32 {
+            self.current = pitch;
+            self.rate = 0f32;
+            return;
+        }
+
+        self.rate = match mode {
+            GlideMode::ConstantTime => (self.target - self.current).abs() / time,
+            GlideMode::ConstantRate => 12f32 / time,
+        };
+    }
+
+    /// Advance the glide by `dt` seconds and get the current pitch
+    pub fn next(&mut self, dt: f32) -> f32 {
+        let step = self.rate * dt;
+        let distance = self.target - self.current;
+        if distance.abs() <= step {
+            self.current = self.target;
+        } else {
+            self.current += step.copysign(distance);
+        }
+        self.current
+    }
+
+    /// The current pitch as a fractional MIDI note
+    pub fn pitch(&self) -> f32 {
+        self.current
+    }
+
+    /// The pitch the glide is heading for, none once it got there
+    pub fn target(&self) -> Option<f32> {
+        (self.current != self.target).then(|| self.target)
+    }
+
+    /// The current frequency in Hz
+    pub fn freq(&self) -> f32 {
+        pitch_to_freq(self.current)
+    }
+}
+
+/// Convert a fractional MIDI note to a frequency in Hz
+#[inline]
+pub fn pitch_to_freq(pitch: f32) -> f32 {
+    440f32 * 2f32.powf((pitch - 69f32) / 12f32)
+}
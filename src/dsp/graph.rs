@@ -0,0 +1,244 @@
+use super::{
+    eq::{eq_band, EqShape},
+    filter::{ladder, svf, FilterMode, FilterPlacement, FilterRouting},
+    oscillator::phase_sine,
+    oversample::oversample,
+    resonator::resonator,
+    stereo::stereo_width,
+};
+use crate::Tag;
+use fundsp::hacker32::*;
+
+/// Delay between the left and right noise channels in seconds
+const NOISE_DECORRELATION: f64 = 0.011;
+/// Cutoff of the smoothing on the wet amount in Hz
+pub const WET_SMOOTHING: f64 = 100.;
+/// Cutoff of the smoothing on parameters that only update once per block in Hz
+pub const PARAM_SMOOTHING: f64 = 50.;
+/// Smoothing cutoff for blocks that should jump to new values, like right after loading a preset
+pub const NO_SMOOTHING: f64 = 20_000.;
+
+/// A fully built synth graph
+pub type Graph = Box<dyn AudioUnit32 + Send + Sync>;
+
+/// Everything that changes the structure of the graph. Any change to this requires a rebuild, so
+/// continuous parameters should be passed in through tags instead. Discrete parameters that would
+/// click when switched mid-note belong here too, the rebuild crossfades between the two graphs.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct GraphConfig {
+    pub filter_routing: FilterRouting,
+    pub filter_placement: FilterPlacement,
+    pub filter2_mode: FilterMode,
+    /// How many times the graph runs per output sample
+    pub oversampling: usize,
+    /// Whether the filters and the output stage run once on the mixed voices in a bus graph,
+    /// instead of in every voice
+    pub shared_filters: bool,
+    /// Whether this is the bus graph the voices get mixed into when they share their filters
+    pub bus: bool,
+}
+
+/// Construct the graph for the given configuration. This allocates, so it must never be called
+/// from the audio thread.
+#[allow(clippy::precedence)]
+pub fn build(config: &GraphConfig, sample_rate: f32) -> Graph {
+    let freq_tag = || tag(Tag::Freq as i64, 0.);
+    // Cutoff of the smoothing on the tags below that only update once per block, which opens up
+    // for blocks that should jump to new values
+    let smoothing_tag = || tag(Tag::Smoothing as i64, PARAM_SMOOTHING);
+    let cutoff_tag = || tag(Tag::FilterFreq as i64, 0.);
+    let q_tag = || tag(Tag::FilterQ as i64, 0.);
+    let drive_tag = || tag(Tag::FilterDrive as i64, 1.);
+    let filter2_cutoff_tag = || tag(Tag::Filter2Freq as i64, 20_000.);
+    let filter2_q_tag = || tag(Tag::Filter2Q as i64, 0.);
+    let filter2_vowel_tag = || (tag(Tag::Filter2Vowel as i64, 0.) | smoothing_tag()) >> lowpole();
+    let filter1_gain_tag = || tag(Tag::Filter1Gain as i64, 1.);
+    let filter2_gain_tag = || tag(Tag::Filter2Gain as i64, 1.);
+    let wet_tag = || tag(Tag::Wet as i64, 0.);
+    let time_tag = || tag(Tag::Time as i64, 0.);
+    let noise_amp_tag = || tag(Tag::NoiseAmp as i64, 0.);
+    let noise_width_tag = || tag(Tag::NoiseWidth as i64, 0.);
+    // The cutoff only updates once per block
+    let noise_cutoff_tag = || (tag(Tag::NoiseCutoff as i64, 2000.) | smoothing_tag()) >> lowpole();
+    let a_ratio_tag = || tag(Tag::OpARatio as i64, 0.);
+    let b_ratio_tag = || tag(Tag::OpBRatio as i64, 0.);
+    let a_mod_tag = || tag(Tag::OpAMod as i64, 0.);
+    let a_env_tag = || tag(Tag::OpAEnv as i64, 0.);
+    let b_env_tag = || tag(Tag::OpBEnv as i64, 0.);
+    let noise_env_tag = || tag(Tag::NoiseEnv as i64, 0.);
+    let env_tag = || tag(Tag::Env as i64, 0.) >> !declick();
+    let b_mod_tag = || tag(Tag::OpBMod as i64, 0.);
+    let a_b_mod_tag = || tag(Tag::OpAModB as i64, 0.);
+    let ring_mod_tag = || tag(Tag::RingMod as i64, 0.);
+    let sync_tag = || tag(Tag::Sync as i64, 0.);
+    let a_phase_tag = || tag(Tag::OpAPhase as i64, 0.);
+    let b_phase_tag = || tag(Tag::OpBPhase as i64, 0.);
+    let a_trigger_tag = || tag(Tag::OpATrigger as i64, 0.);
+    let b_trigger_tag = || tag(Tag::OpBTrigger as i64, 0.);
+    let pluck_tag = || tag(Tag::Pluck as i64, 0.);
+    let pluck_damping_tag = || tag(Tag::PluckDamping as i64, 0.);
+    let pluck_decay_tag = || tag(Tag::PluckDecay as i64, 0.);
+    let ext_fm_tag = || tag(Tag::ExtFm as i64, 0.);
+    let ext_level_tag = || tag(Tag::ExtLevel as i64, 0.);
+    let noise_fm_a_tag = || tag(Tag::NoiseFmA as i64, 0.);
+    let noise_fm_b_tag = || tag(Tag::NoiseFmB as i64, 0.);
+    // The operators track their own phase so they can start at an offset and restart on new
+    // notes. Operator b can also be hard synced to operator a's cycle.
+    let a_osc = || (pass() | dc(0.) | dc(0.) | a_phase_tag() | a_trigger_tag()) >> phase_sine();
+    let b_osc = || {
+        (pass() | freq_tag() * a_ratio_tag() | sync_tag() | b_phase_tag() | b_trigger_tag())
+            >> phase_sine()
+    };
+
+    let noise_channel = || (pass() | noise_cutoff_tag() | dc(0.75)) >> bandpass();
+    // The filtered noise following the noise envelope can frequency modulate the operators
+    let noise_fm = || (noise() >> noise_channel()) * noise_env_tag() * freq_tag();
+
+    // Operators
+    let a = || {
+        (freq_tag() * a_ratio_tag() + noise_fm() * noise_fm_a_tag())
+            >> a_env_tag() * a_osc() * freq_tag() * a_mod_tag() + freq_tag()
+    };
+    // Operator b takes the external input as an additional frequency modulator
+    let b = || {
+        (pass() * ext_fm_tag() * freq_tag()
+            + freq_tag() * b_ratio_tag()
+            + noise_fm() * noise_fm_b_tag())
+            >> b_env_tag() * b_osc() * freq_tag() * b_mod_tag() + freq_tag()
+    };
+    // The noise layer is stereo. The right channel is a delayed copy of the left channel, which
+    // for white noise is just as good as a second generator.
+    let n = noise()
+        >> split::<U2>()
+        >> (pass() | delay(NOISE_DECORRELATION))
+        >> (noise_channel() | noise_channel() | noise_width_tag())
+        >> stereo_width() * (noise_amp_tag() * noise_env_tag() >> split::<U2>());
+    // let ab = || a() >> b();
+
+    // The noise burst excites a comb tuned to the played note for plucked sounds
+    let pluck = (noise() * noise_env_tag() | freq_tag() | pluck_damping_tag() | pluck_decay_tag())
+        >> resonator() * pluck_tag();
+
+    // Ring modulation between the operators' raw sines
+    let ring = (freq_tag() * a_ratio_tag() >> a_osc())
+        * (freq_tag() * b_ratio_tag() >> b_osc())
+        * ring_mod_tag()
+        * env_tag();
+
+    // Everything from here on takes the external input summed to mono. Only the FM operators
+    // and the gated passthrough use it.
+    let fm = (a() | b()) >> (pass() + pass()) >> (sine() * env_tag());
+    let input = || (pass() + pass()) * dc(0.5);
+    // The noise layer joins the centered core in stereo
+    let voice =
+        || (input() >> (fm & (sink() | (ring & pluck))) >> split::<U2>()) & (sink() | sink() | n);
+    let through = input() >> pass() * ext_level_tag() * env_tag() >> split::<U2>();
+    // Voices that share their filters only render their own sound, everything after that runs in
+    // the bus graph
+    if config.shared_filters && !config.bus {
+        let mut graph: Graph = Box::new(oversample(voice(), config.oversampling));
+        graph.reset(Some(sample_rate as f64));
+        return graph;
+    }
+    // Every routing results in a different graph type, so each one gets assembled separately
+    let filter1 = || (pass() | cutoff_tag() | q_tag() | drive_tag()) >> ladder();
+    let filter2 = || {
+        (pass()
+            | filter2_cutoff_tag()
+            | filter2_q_tag()
+            | dc(config.filter2_mode as usize as f32)
+            | filter2_vowel_tag())
+            >> svf()
+    };
+    let mut graph = match config.filter_routing {
+        FilterRouting::Single => finish(voice, through, filter1() | filter1(), config),
+        FilterRouting::Serial => finish(
+            voice,
+            through,
+            (filter1() >> filter2()) | (filter1() >> filter2()),
+            config,
+        ),
+        FilterRouting::Parallel => {
+            let channel = || filter1() * filter1_gain_tag() & filter2() * filter2_gain_tag();
+            finish(voice, through, channel() | channel(), config)
+        }
+        FilterRouting::Split => finish(
+            voice,
+            through,
+            filter1() * filter1_gain_tag() | filter2() * filter2_gain_tag(),
+            config,
+        ),
+    };
+    graph.reset(Some(sample_rate as f64));
+    graph
+}
+
+/// Run the voice and the external input through the filters and apply the output stage. The bus
+/// graph takes the mixed voices and the external input as its four inputs instead of rendering a
+/// voice, and only oversamples the filters.
+#[allow(clippy::precedence)]
+fn finish<V, T, F>(
+    voice: impl FnOnce() -> An<V>,
+    through: An<T>,
+    filters: An<F>,
+    config: &GraphConfig,
+) -> Graph
+where
+    V: AudioNode<Sample = f32, Inputs = U2, Outputs = U2> + Send + Sync + 'static,
+    T: AudioNode<Sample = f32, Inputs = U2, Outputs = U2> + Send + Sync + 'static,
+    F: AudioNode<Sample = f32, Inputs = U2, Outputs = U2> + Send + Sync + 'static,
+{
+    let smoothing_tag = || tag(Tag::Smoothing as i64, PARAM_SMOOTHING);
+    // Expression only updates once per block, so smooth it to avoid zipper noise
+    let expression_tag = || (tag(Tag::Expression as i64, 1.) | smoothing_tag()) >> lowpole();
+    let band = |shape, freq: Tag, q: Tag, gain: Tag| {
+        (pass() | tag(freq as i64, 1000.) | tag(q as i64, 0.707) | tag(gain as i64, 0.))
+            >> eq_band(shape)
+    };
+    let eq = || {
+        band(
+            EqShape::LowShelf,
+            Tag::EqLowFreq,
+            Tag::EqLowQ,
+            Tag::EqLowGain,
+        ) >> band(EqShape::Peak, Tag::EqMidFreq, Tag::EqMidQ, Tag::EqMidGain)
+            >> band(
+                EqShape::HighShelf,
+                Tag::EqHighFreq,
+                Tag::EqHighQ,
+                Tag::EqHighGain,
+            )
+    };
+    // The wet amount is only updated once per block, smoothing it makes for a short crossfade
+    // when the effects get bypassed
+    let eq_wet_tag =
+        || (tag(Tag::EqWet as i64, 1.) | tag(Tag::WetSmoothing as i64, WET_SMOOTHING)) >> lowpole();
+    let eq_mix = || pass() * (dc(1.) - eq_wet_tag()) & eq() * eq_wet_tag();
+    // Width only updates once per block as well
+    let width_tag = || (tag(Tag::Width as i64, 1.) | smoothing_tag()) >> lowpole();
+    let output = || {
+        (eq_mix() | eq_mix())
+            >> (pass() | pass()) * (expression_tag() >> split::<U2>())
+            >> (declick() | declick())
+            >> (pass() | pass() | width_tag())
+            >> stereo_width()
+        // >> reverb_stereo(wet(), time());
+    };
+    let factor = config.oversampling;
+    match (config.bus, config.filter_placement) {
+        (false, FilterPlacement::Voice) => Box::new(oversample(
+            ((voice() >> filters) & through) >> output(),
+            factor,
+        )),
+        (false, FilterPlacement::Bus) => Box::new(oversample(
+            (voice() & through) >> filters >> output(),
+            factor,
+        )),
+        (true, FilterPlacement::Voice) => {
+            Box::new((oversample(filters, factor) + through) >> output())
+        }
+        (true, FilterPlacement::Bus) => {
+            Box::new(((pass() | pass()) + through) >> oversample(filters, factor) >> output())
+        }
+    }
+}
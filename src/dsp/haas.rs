@@ -0,0 +1,69 @@
+/// The longest delay in seconds
+pub const MAX_DELAY: f32 = 0.03;
+
+/// Time constant for changes to the delay time and mix, so sweeping them or toggling the delay
+/// doesn't click
+const SMOOTHING_TIME: f32 = 0.05;
+
+/// Settings for the [`Haas`] delay, read once per block
+pub struct HaasSettings {
+    pub enabled: bool,
+    /// Delay time in seconds
+    pub time: f32,
+    /// How much of the delayed signal replaces the right channel
+    pub amount: f32,
+}
+
+/// Delays the right channel by a few milliseconds so mono lines come across wider. The delay line
+/// keeps running while the delay is disabled, so enabling it never plays back stale audio.
+#[derive(Default)]
+pub struct Haas {
+    delay: Vec<f32>,
+    write_pos: usize,
+    /// The current smoothed delay time in samples
+    time: f32,
+    /// The current smoothed mix
+    mix: f32,
+    sample_rate: f32,
+}
+
+impl Haas {
+    /// Reset the state and allocate the delay line, this must not be called from the audio thread
+    pub fn reset(&mut self, sample_rate: f32) {
+        self.delay = vec![0f32; (MAX_DELAY * sample_rate).ceil() as usize + 2];
+        self.write_pos = 0;
+        self.time = 0f32;
+        self.mix = 0f32;
+        self.sample_rate = sample_rate;
+    }
+
+    /// Delay the right channel of a block in place
+    pub fn process(&mut self, right: &mut [f32], settings: &HaasSettings) {
+        let len = self.delay.len();
+        if len == 0 {
+            return;
+        }
+        let smoothing = 1f32 - (-1f32 / (SMOOTHING_TIME * self.sample_rate)).exp();
+        let time = settings.time.clamp(0f32, MAX_DELAY) * self.sample_rate;
+        let mix = match settings.enabled {
+            true => settings.amount,
+            false => 0f32,
+        };
+
+        for sample in right.iter_mut() {
+            self.delay[self.write_pos] = *sample;
+            self.time += (time - self.time) * smoothing;
+            self.mix += (mix - self.mix) * smoothing;
+
+            // Linear interpolation between the two samples around the delay time
+            let offset = self.time.floor();
+            let fraction = self.time - offset;
+            let newer = (self.write_pos + len - offset as usize) % len;
+            let older = (newer + len - 1) % len;
+            let delayed = self.delay[newer] + (self.delay[older] - self.delay[newer]) * fraction;
+            self.write_pos = (self.write_pos + 1) % len;
+
+            *sample += (delayed - *sample) * self.mix;
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::rng::{Rng, RngStream, DEFAULT_SEED};
+use nih_plug::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+/// The waveform of an [`Lfo`]
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum LfoShape {
+    #[name = "sine"]
+    Sine,
+    #[name = "triangle"]
+    Triangle,
+    #[name = "saw up"]
+    SawUp,
+    #[name = "saw down"]
+    SawDown,
+    #[name = "square"]
+    Square,
+    /// A new random value every cycle
+    #[name = "sample & hold"]
+    SampleHold,
+    /// Glides between a new random value every cycle
+    #[name = "smooth random"]
+    SmoothRandom,
+}
+
+impl LfoShape {
+    /// The value at `phase` in `0..1`, in `-1..=1`. The random shapes move from `from` to `to`
+    /// over the cycle.
+    pub fn value(self, phase: f32, pulse_width: f32, from: f32, to: f32) -> f32 {
+        match self {
+            Self::Sine => (phase * TAU).sin(),
+            Self::Triangle => 1f32 - 4f32 * ((phase + 0.25).fract() - 0.5).abs(),
+            Self::SawUp => phase * 2f32 - 1f32,
+            Self::SawDown => 1f32 - phase * 2f32,
+            Self::Square => {
+                if phase < pulse_width {
+                    1f32
+                } else {
+                    -1f32
+                }
+            }
+            Self::SampleHold => from,
+            Self::SmoothRandom => {
+                let t = 0.5 - (phase * PI).cos() * 0.5;
+                from + (to - from) * t
+            }
+        }
+    }
+
+    /// Whether the shape is made up of random values
+    pub fn is_random(self) -> bool {
+        matches!(self, Self::SampleHold | Self::SmoothRandom)
+    }
+}
+
+/// The parameters of an [`Lfo`]
+pub struct LfoSettings {
+    /// Cycles per second
+    pub rate: f32,
+    pub shape: LfoShape,
+    pub pulse_width: f32,
+    /// Shifts the waveform by a fraction of a cycle
+    pub phase_offset: f32,
+    /// Run a single cycle from every trigger and hold its last value, like an envelope
+    pub one_shot: bool,
+    /// Seconds it takes the output to fade in after a trigger
+    pub fade_in: f32,
+}
+
+/// An LFO that's free running or runs a single cycle per note. Like the vibrato this runs at
+/// block rate.
+pub struct Lfo {
+    phase: f32,
+    rng: Rng,
+    from: f32,
+    to: f32,
+    /// Seconds since the last trigger, for the fade in
+    since_trigger: f32,
+    /// Whether the one shot cycle has run its course
+    finished: bool,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self {
+            phase: 0f32,
+            rng: Rng::new(DEFAULT_SEED, RngStream::Lfo),
+            from: 0f32,
+            to: 0f32,
+            since_trigger: 0f32,
+            finished: false,
+        }
+    }
+}
+
+impl Lfo {
+    /// Restart from the initial state so renders are repeatable
+    pub fn reset(&mut self, seed: u32) {
+        *self = Self {
+            rng: Rng::new(seed, RngStream::Lfo),
+            ..Self::default()
+        };
+    }
+
+    /// Start the fade in over, and in one shot mode start a new cycle
+    pub fn trigger(&mut self, one_shot: bool) {
+        self.since_trigger = 0f32;
+        if one_shot {
+            self.phase = 0f32;
+            self.finished = false;
+            self.from = self.to;
+            self.to = self.rng.bipolar();
+        }
+    }
+
+    /// Advance the LFO by `dt` seconds and get its value in `-1..=1`
+    pub fn next(&mut self, dt: f32, settings: &LfoSettings) -> f32 {
+        self.since_trigger += dt;
+        let fade = match settings.fade_in > 0f32 {
+            true => (self.since_trigger / settings.fade_in).min(1f32),
+            false => 1f32,
+        };
+
+        // A finished one shot cycle holds the value at its end
+        let phase = match self.finished {
+            true => 1f32,
+            false => self.phase + settings.rate * dt,
+        };
+        let phase = match settings.one_shot {
+            true if phase >= 1f32 => {
+                self.finished = true;
+                self.phase = 1f32;
+                match 1f32 + settings.phase_offset {
+                    phase if phase > 1f32 => phase - 1f32,
+                    phase => phase,
+                }
+            }
+            _ => {
+                if phase >= 1f32 {
+                    self.from = self.to;
+                    self.to = self.rng.bipolar();
+                }
+                self.finished = false;
+                self.phase = phase.fract();
+                (self.phase + settings.phase_offset).fract()
+            }
+        };
+        let value = settings
+            .shape
+            .value(phase, settings.pulse_width, self.from, self.to);
+        value * fade
+    }
+}
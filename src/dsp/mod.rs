@@ -0,0 +1,38 @@
+pub mod compressor;
+pub mod denormal;
+pub mod drift;
+pub mod envelope;
+pub mod eq;
+pub mod fade;
+pub mod filter;
+pub mod glide;
+pub mod graph;
+pub mod haas;
+pub mod lfo;
+pub mod oscillator;
+pub mod oversample;
+pub mod preview;
+pub mod resonator;
+pub mod rng;
+pub mod safety;
+pub mod scratch;
+pub mod stereo;
+pub mod swap;
+pub mod variation;
+pub mod vibrato;
+
+pub use compressor::{Compressor, CompressorSettings};
+pub use denormal::{flush_denormal, ScopedFtz};
+pub use drift::{Drift, DriftTarget};
+pub use envelope::{Adsr, EndpointLocks, EnvelopeShape, EnvelopeTemplate, EnvelopeTiming, Gate};
+pub use fade::Fade;
+pub use filter::{FilterMode, FilterPlacement, FilterRouting};
+pub use glide::{pitch_to_freq, Glide, GlideMode};
+pub use graph::{Graph, GraphConfig};
+pub use haas::{Haas, HaasSettings};
+pub use lfo::{Lfo, LfoSettings, LfoShape};
+pub use oversample::Quality;
+pub use scratch::Scratch;
+pub use swap::{Crossfade, GraphBuilder};
+pub use variation::NoteVariation;
+pub use vibrato::Vibrato;
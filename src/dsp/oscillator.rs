@@ -0,0 +1,82 @@
+use fundsp::{audionode::*, hacker32::*, Float};
+use std::{f64::consts::TAU, marker::PhantomData};
+
+/// A sine oscillator that keeps track of its own phase and the phase of a master oscillator so
+/// it can be hard synced and restarted. The inputs are:
+///
+/// 0. The oscillator's frequency
+/// 1. The master oscillator's frequency
+/// 2. Whether hard sync is enabled (`> 0.5`)
+/// 3. The start phase in cycles
+/// 4. A trigger counter, the phase restarts at the start phase whenever this changes
+#[derive(Clone)]
+pub struct PhaseSine<T> {
+    phase: f64,
+    master_phase: f64,
+    trigger: f64,
+    sample_duration: f64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for PhaseSine<T> {
+    fn default() -> Self {
+        Self {
+            phase: 0.0,
+            master_phase: 0.0,
+            trigger: 0.0,
+            sample_duration: 1.0 / DEFAULT_SR,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Float> AudioNode for PhaseSine<T> {
+    const ID: u64 = 1001;
+    type Sample = T;
+    type Inputs = U5;
+    type Outputs = U1;
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        self.phase = 0.0;
+        self.master_phase = 0.0;
+        if let Some(sample_rate) = sample_rate {
+            self.sample_duration = 1.0 / sample_rate;
+        }
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let freq = input[0].to_f64();
+        let master_freq = input[1].to_f64();
+        let sync = input[2].to_f64() > 0.5;
+        let start_phase = input[3].to_f64();
+        let trigger = input[4].to_f64();
+
+        if trigger != self.trigger {
+            self.trigger = trigger;
+            self.phase = start_phase.rem_euclid(1.0);
+            self.master_phase = 0.0;
+        }
+
+        // Restart our cycle whenever the master oscillator wraps around
+        self.master_phase += master_freq * self.sample_duration;
+        if self.master_phase >= 1.0 || self.master_phase < 0.0 {
+            self.master_phase = self.master_phase.rem_euclid(1.0);
+            if sync {
+                self.phase = 0.0;
+            }
+        }
+
+        let value = (self.phase * TAU).sin();
+        self.phase = (self.phase + freq * self.sample_duration).rem_euclid(1.0);
+        Frame::from([T::from_f64(value)])
+    }
+}
+
+/// A restartable and hard-syncable sine oscillator, see [`PhaseSine`]
+pub fn phase_sine() -> An<PhaseSine<f32>> {
+    An(PhaseSine::default())
+}
@@ -0,0 +1,52 @@
+use super::LfoShape;
+use std::f32::consts::TAU;
+
+/// Fixed random values used to preview the random LFO shapes
+const PREVIEW_STEPS: [f32; 5] = [0.3, -0.6, 0.9, -0.2, 0.3];
+
+/// One cycle of the carrier when frequency modulated by a single operator at full envelope level,
+/// used for the operator previews in the editor
+pub fn fm_cycle(ratio: f32, modulation: f32, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / len as f32;
+            // The operator adds `freq * modulation * sin(ratio * phase)` to the carrier's
+            // frequency, so the carrier's phase picks up the integral of that
+            let modulator = if ratio > 0f32 {
+                -(modulation / ratio) * (TAU * ratio * t).cos()
+            } else {
+                0f32
+            };
+            (TAU * t + modulator).sin()
+        })
+        .collect()
+}
+
+/// The shape of an LFO for the editor. The random shapes are shown over a few cycles since a
+/// single cycle of them doesn't say much.
+pub fn lfo_cycle(shape: LfoShape, pulse_width: f32, unipolar: bool, len: usize) -> Vec<f32> {
+    let steps = PREVIEW_STEPS.len() - 1;
+    (0..len)
+        .map(|i| {
+            let t = i as f32 / len as f32;
+            let value = if shape.is_random() {
+                let step = (t * steps as f32).floor() as usize;
+                let phase = (t * steps as f32).fract();
+                shape.value(
+                    phase,
+                    pulse_width,
+                    PREVIEW_STEPS[step],
+                    PREVIEW_STEPS[step + 1],
+                )
+            } else {
+                shape.value(t, pulse_width, 0f32, 0f32)
+            };
+            // Unipolar shapes stay above the center line
+            if unipolar {
+                value * 0.5 + 0.5
+            } else {
+                value
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use fundsp::{audionode::*, hacker32::*, Float};
+use std::marker::PhantomData;
+
+/// The lowest frequency the resonator can be tuned to
+const MIN_FREQ: f64 = 20.0;
+
+/// A Karplus-Strong style tuned feedback comb. The inputs are:
+///
+/// 0. The excitation signal
+/// 1. The frequency the comb is tuned to
+/// 2. Damping, how much the high end gets filtered out on every pass
+/// 3. Decay, the feedback gain
+#[derive(Clone)]
+pub struct Resonator<T> {
+    buffer: Vec<f64>,
+    write_pos: usize,
+    lowpass: f64,
+    sample_rate: f64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Default for Resonator<T> {
+    fn default() -> Self {
+        Self {
+            buffer: vec![0.0; delay_len(DEFAULT_SR)],
+            write_pos: 0,
+            lowpass: 0.0,
+            sample_rate: DEFAULT_SR,
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// The length of a delay line that can be tuned down to the lowest frequency at `sample_rate`
+fn delay_len(sample_rate: f64) -> usize {
+    (sample_rate / MIN_FREQ).ceil() as usize + 2
+}
+
+impl<T> Resonator<T> {
+    /// Read from the delay line `delay` samples ago with linear interpolation
+    #[inline]
+    fn read(&self, delay: f64) -> f64 {
+        let len = self.buffer.len();
+        let delay = delay.clamp(1.0, (len - 2) as f64);
+        let whole = delay.floor() as usize;
+        let fraction = delay - whole as f64;
+        let a = self.buffer[(self.write_pos + len - whole) % len];
+        let b = self.buffer[(self.write_pos + len - whole - 1) % len];
+        a + (b - a) * fraction
+    }
+}
+
+impl<T: Float> AudioNode for Resonator<T> {
+    const ID: u64 = 1002;
+    type Sample = T;
+    type Inputs = U4;
+    type Outputs = U1;
+
+    fn reset(&mut self, sample_rate: Option<f64>) {
+        // The delay line is sized for the rate the graph runs at, oversampling included. Graphs
+        // only get reset while they're being built or initialized, never on the audio thread, so
+        // growing it here is fine.
+        if let Some(sample_rate) = sample_rate {
+            self.sample_rate = sample_rate;
+            let len = delay_len(sample_rate);
+            if len > self.buffer.len() {
+                self.buffer.resize(len, 0.0);
+            }
+        }
+        self.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+        self.write_pos = 0;
+        self.lowpass = 0.0;
+    }
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let excitation = input[0].to_f64();
+        let freq = input[1].to_f64().max(MIN_FREQ);
+        let damping = input[2].to_f64().clamp(0.0, 1.0);
+        let decay = input[3].to_f64().clamp(0.0, 0.999);
+
+        let delayed = self.read(self.sample_rate / freq);
+        self.lowpass += (1.0 - damping) * (delayed - self.lowpass);
+        let value = excitation + self.lowpass * decay;
+
+        self.buffer[self.write_pos] = value;
+        self.write_pos = (self.write_pos + 1) % self.buffer.len();
+        Frame::from([T::from_f64(value)])
+    }
+}
+
+/// A tuned Karplus-Strong resonator, see [`Resonator`]
+pub fn resonator() -> An<Resonator<f32>> {
+    An(Resonator::default())
+}
@@ -0,0 +1,51 @@
+/// The seed everything starts from until a different one is chosen
+pub const DEFAULT_SEED: u32 = 1;
+
+/// The random features that each get their own stream
+#[derive(Clone, Copy)]
+pub enum RngStream {
+    Drift,
+    Lfo,
+    Variation,
+    /// Randomizing a patch from the editor
+    Randomize,
+}
+
+/// A small xorshift generator. Every random feature draws from its own stream derived from a
+/// shared seed, so the same seed always gives the same render no matter which features are in
+/// use or how often each of them draws.
+#[derive(Clone, Copy)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32, stream: RngStream) -> Self {
+        // Scramble the seed and stream together so neighbouring seeds don't give similar
+        // sequences, xorshift can't start from 0
+        let mut state = seed ^ (stream as u32 + 1).wrapping_mul(0x9e37_79b9);
+        state = (state ^ (state >> 16)).wrapping_mul(0x85eb_ca6b);
+        state = (state ^ (state >> 13)).wrapping_mul(0xc2b2_ae35);
+        state ^= state >> 16;
+        Self {
+            state: state.max(1),
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// A random value in `0..=1`
+    pub fn unipolar(&mut self) -> f32 {
+        self.next_u32() as f32 / u32::MAX as f32
+    }
+
+    /// A random value in `-1..=1`
+    pub fn bipolar(&mut self) -> f32 {
+        (self.next_u32() as f32 / u32::MAX as f32) * 2f32 - 1f32
+    }
+}
@@ -0,0 +1,52 @@
+/// The largest combined modulation index of both operators in safe mode
+pub const MAX_INDEX: f32 = 4.0;
+/// The largest noise and external FM depth in safe mode
+pub const MAX_FM_DEPTH: f32 = 0.5;
+/// The largest amp level in safe mode. Several sources routed to the amp can otherwise push it
+/// well past 1.
+pub const MAX_AMP: f32 = 1.0;
+/// The largest compressor makeup gain in safe mode in decibels
+pub const MAX_MAKEUP: f32 = 6.0;
+/// The largest boost of an EQ band in safe mode in decibels
+pub const MAX_EQ_GAIN: f32 = 6.0;
+/// The largest output trim in safe mode in decibels, so the trims can only turn the output down
+pub const MAX_TRIM: f32 = 0.0;
+
+/// Everything that decides how hard the operators get driven and how loud the voice gets, collected
+/// right before it's written to the graph
+#[derive(Clone, Copy)]
+pub struct Drive {
+    /// Each operator's modulation index before its level is applied
+    pub mods: [f32; 2],
+    /// Each operator's level, the index the graph ends up using is the product of the two
+    pub levels: [f32; 2],
+    /// How much the noise modulates each operator's frequency
+    pub noise_fm: [f32; 2],
+    /// How much the external input modulates operator b's frequency
+    pub ext_fm: f32,
+    pub amp: f32,
+}
+
+impl Drive {
+    /// Cap everything to the safe range. The operators are scaled down together so the balance
+    /// between them stays the same.
+    pub fn limit(mut self) -> Self {
+        let index: f32 = self
+            .mods
+            .iter()
+            .zip(self.levels)
+            .map(|(index, level)| (index * level).abs())
+            .sum();
+        if index > MAX_INDEX {
+            for index_mod in &mut self.mods {
+                *index_mod *= MAX_INDEX / index;
+            }
+        }
+        for depth in &mut self.noise_fm {
+            *depth = depth.clamp(-MAX_FM_DEPTH, MAX_FM_DEPTH);
+        }
+        self.ext_fm = self.ext_fm.clamp(-MAX_FM_DEPTH, MAX_FM_DEPTH);
+        self.amp = self.amp.clamp(-MAX_AMP, MAX_AMP);
+        self
+    }
+}
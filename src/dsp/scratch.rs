@@ -0,0 +1,29 @@
+/// Preallocated stereo buffers for intermediate rendering on the audio thread. These are sized
+/// once in `initialize()` so `process()` never has to allocate.
+#[derive(Default)]
+pub struct Scratch {
+    pub left: Vec<f32>,
+    pub right: Vec<f32>,
+}
+
+impl Scratch {
+    /// Resize the buffers to hold `len` samples. Must only be called off the audio thread.
+    pub fn resize(&mut self, len: usize) {
+        self.left.resize(len, 0f32);
+        self.right.resize(len, 0f32);
+    }
+
+    /// The number of samples the buffers can hold
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Get both channels as slices of `len` samples
+    pub fn channels(&mut self, len: usize) -> [&mut [f32]; 2] {
+        [&mut self.left[..len], &mut self.right[..len]]
+    }
+}
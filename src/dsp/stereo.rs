@@ -0,0 +1,38 @@
+use fundsp::{audionode::*, hacker32::*, Float};
+use std::marker::PhantomData;
+
+/// Narrows or widens a stereo signal using mid/side processing. The inputs are:
+///
+/// 0. The left channel
+/// 1. The right channel
+/// 2. The width, 0 collapses the signal to mono, 1 leaves it untouched and anything above widens
+///    it
+#[derive(Clone, Default)]
+pub struct StereoWidth<T> {
+    _marker: PhantomData<T>,
+}
+
+impl<T: Float> AudioNode for StereoWidth<T> {
+    const ID: u64 = 1003;
+    type Sample = T;
+    type Inputs = U3;
+    type Outputs = U2;
+
+    fn reset(&mut self, _sample_rate: Option<f64>) {}
+
+    #[inline]
+    fn tick(
+        &mut self,
+        input: &Frame<Self::Sample, Self::Inputs>,
+    ) -> Frame<Self::Sample, Self::Outputs> {
+        let half = T::from_f64(0.5);
+        let mid = (input[0] + input[1]) * half;
+        let side = (input[0] - input[1]) * half * input[2];
+        Frame::from([mid + side, mid - side])
+    }
+}
+
+/// Mid/side stereo width control, see [`StereoWidth`]
+pub fn stereo_width() -> An<StereoWidth<f32>> {
+    An(StereoWidth::default())
+}
@@ -0,0 +1,173 @@
+use super::graph::{self, Graph, GraphConfig};
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the worker wakes up to drop graphs the audio thread is done with
+const RETIRE_INTERVAL: Duration = Duration::from_millis(100);
+/// Length of the crossfade between the old and the new graph in seconds
+pub const CROSSFADE_TIME: f32 = 0.01;
+/// How long offline rendering waits on the worker before giving up on it
+const BLOCKING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hands graphs between the builder thread and the audio thread. While playing in real time the
+/// audio thread only ever uses `try_lock()`, so it never blocks on the worker.
+#[derive(Default)]
+pub struct GraphSlot {
+    /// The last graph that was built along with the configuration it was built for
+    pending: Mutex<Option<(GraphConfig, Graph)>>,
+    retired: Mutex<Option<Graph>>,
+    /// Signalled when a graph was built
+    built: Condvar,
+    /// Signalled when the retired graph was dropped
+    cleared: Condvar,
+}
+
+impl GraphSlot {
+    /// Take the newly built graph for `config`, if it's ready. Graphs built for earlier
+    /// configurations are left for the worker to replace.
+    pub fn take(&self, config: GraphConfig) -> Option<Graph> {
+        let mut pending = self.pending.try_lock().ok()?;
+        match &*pending {
+            Some((built, _)) if *built == config => pending.take().map(|(_, graph)| graph),
+            _ => None,
+        }
+    }
+
+    /// Block until the graph for `config` has been built. This is for rendering offline, where
+    /// waiting is fine but the new graph has to be used from the same sample every time.
+    pub fn wait(&self, config: GraphConfig) -> Option<Graph> {
+        let pending = self.pending.lock().ok()?;
+        let (mut pending, _) = self
+            .built
+            .wait_timeout_while(
+                pending,
+                BLOCKING_TIMEOUT,
+                |pending| !matches!(pending, Some((built, _)) if *built == config),
+            )
+            .ok()?;
+        pending.take().map(|(_, graph)| graph)
+    }
+
+    /// Hand a graph that is no longer used back to the worker so it gets deallocated there. Gives
+    /// the graph back if the slot is currently in use.
+    pub fn retire(&self, graph: Graph) -> Result<(), Graph> {
+        match self.retired.try_lock() {
+            Ok(mut retired) if retired.is_none() => {
+                *retired = Some(graph);
+                Ok(())
+            }
+            _ => Err(graph),
+        }
+    }
+
+    /// Like [`Self::retire`], but waits for the worker to drop the previously retired graph
+    /// instead of failing. Only for rendering offline.
+    pub fn retire_blocking(&self, graph: Graph) -> Result<(), Graph> {
+        let retired = match self.retired.lock() {
+            Ok(retired) => retired,
+            Err(_) => return Err(graph),
+        };
+        match self
+            .cleared
+            .wait_timeout_while(retired, BLOCKING_TIMEOUT, |retired| retired.is_some())
+        {
+            Ok((mut retired, _)) if retired.is_none() => {
+                *retired = Some(graph);
+                Ok(())
+            }
+            _ => Err(graph),
+        }
+    }
+}
+
+/// Builds new graphs on a background thread whenever the structural configuration changes
+pub struct GraphBuilder {
+    pub slot: Arc<GraphSlot>,
+    requests: SyncSender<(GraphConfig, f32)>,
+    _worker: JoinHandle<()>,
+}
+
+impl GraphBuilder {
+    pub fn spawn() -> Self {
+        let slot = Arc::new(GraphSlot::default());
+        let (requests, receiver) = mpsc::sync_channel::<(GraphConfig, f32)>(1);
+        let worker_slot = slot.clone();
+        let worker = thread::spawn(move || loop {
+            match receiver.recv_timeout(RETIRE_INTERVAL) {
+                Ok((config, sample_rate)) => {
+                    let graph = graph::build(&config, sample_rate);
+                    if let Ok(mut pending) = worker_slot.pending.lock() {
+                        *pending = Some((config, graph));
+                    }
+                    worker_slot.built.notify_all();
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if let Ok(mut retired) = worker_slot.retired.lock() {
+                retired.take();
+            }
+            worker_slot.cleared.notify_all();
+        });
+
+        Self {
+            slot,
+            requests,
+            _worker: worker,
+        }
+    }
+
+    /// Request a rebuild. Returns false if the worker is still busy with a previous request, in
+    /// which case this should be retried on the next block.
+    pub fn request(&self, config: GraphConfig, sample_rate: f32) -> bool {
+        self.requests.try_send((config, sample_rate)).is_ok()
+    }
+
+    /// Request a rebuild, waiting for the worker to accept it. Only for rendering offline.
+    pub fn request_blocking(&self, config: GraphConfig, sample_rate: f32) -> bool {
+        self.requests.send((config, sample_rate)).is_ok()
+    }
+}
+
+/// The graph being faded out after a swap
+pub struct Crossfade {
+    pub old: Graph,
+    /// Samples left until the old graph is silent
+    pub remaining: usize,
+    pub len: usize,
+}
+
+impl Crossfade {
+    pub fn new(old: Graph, sample_rate: f32) -> Self {
+        let len = ((CROSSFADE_TIME * sample_rate) as usize).max(1);
+        Self {
+            old,
+            remaining: len,
+            len,
+        }
+    }
+
+    /// Mix the old graph's output in `old` into `new`, advancing the fade
+    pub fn mix(&mut self, new: [&mut [f32]; 2], old: [&mut [f32]; 2]) {
+        let start = self.remaining;
+        for (new, old) in new.into_iter().zip(old) {
+            let mut remaining = start;
+            for (new, old) in new.iter_mut().zip(old.iter()) {
+                let t = remaining as f32 / self.len as f32;
+                *new = *new * (1f32 - t) + *old * t;
+                remaining = remaining.saturating_sub(1);
+            }
+            self.remaining = remaining;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
@@ -0,0 +1,55 @@
+use super::rng::{Rng, RngStream, DEFAULT_SEED};
+
+/// The maximum random detune in semitones at full variation amount
+pub const MAX_DETUNE: f32 = 0.1;
+/// The maximum operator start phase offset in cycles at full variation amount
+pub const MAX_PHASE_OFFSET: f32 = 0.05;
+
+/// Small differences between consecutive notes so repeated notes don't sound identical. Every
+/// note gets a random detune, and the operators' start phases alternate between two offsets.
+pub struct NoteVariation {
+    rng: Rng,
+    /// Flips on every note to alternate the phase offset
+    round_robin: bool,
+    detune: f32,
+    phase_offset: f32,
+}
+
+impl Default for NoteVariation {
+    fn default() -> Self {
+        Self {
+            rng: Rng::new(DEFAULT_SEED, RngStream::Variation),
+            round_robin: false,
+            detune: 0f32,
+            phase_offset: 0f32,
+        }
+    }
+}
+
+impl NoteVariation {
+    /// Restart from the initial state so renders are repeatable
+    pub fn reset(&mut self, seed: u32) {
+        *self = Self {
+            rng: Rng::new(seed, RngStream::Variation),
+            ..Self::default()
+        };
+    }
+
+    /// Pick the variation for a new note, `amount` is in `0..=1`
+    pub fn next_note(&mut self, amount: f32) {
+        self.round_robin = !self.round_robin;
+        let direction = if self.round_robin { 1f32 } else { -1f32 };
+        self.phase_offset = direction * amount * MAX_PHASE_OFFSET;
+        self.detune = self.rng.bipolar() * amount * MAX_DETUNE;
+    }
+
+    /// The current note's detune in semitones
+    pub fn detune(&self) -> f32 {
+        self.detune
+    }
+
+    /// The current note's operator start phase offset in cycles
+    pub fn phase_offset(&self) -> f32 {
+        self.phase_offset
+    }
+}
@@ -0,0 +1,25 @@
+use std::f32::consts::TAU;
+
+/// Time it takes for the vibrato to reach full depth once the onset delay has passed
+const FADE_IN_TIME: f32 = 0.25;
+
+/// A sine LFO for pitch vibrato. This runs at block rate, which is plenty for vibrato speeds.
+#[derive(Default)]
+pub struct Vibrato {
+    phase: f32,
+}
+
+impl Vibrato {
+    /// Restart the LFO, called on new notes
+    pub fn reset(&mut self) {
+        self.phase = 0f32;
+    }
+
+    /// Advance the LFO by `dt` seconds and get the pitch offset in semitones. The vibrato stays
+    /// silent for `delay` seconds after the note started and then fades in.
+    pub fn next(&mut self, dt: f32, rate: f32, depth: f32, delay: f32, note_time: f32) -> f32 {
+        self.phase = (self.phase + rate * dt).fract();
+        let onset = ((note_time - delay) / FADE_IN_TIME).clamp(0f32, 1f32);
+        (self.phase * TAU).sin() * depth * onset
+    }
+}
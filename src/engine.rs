@@ -0,0 +1,1418 @@
+use crate::{
+    dsp, midi, modulation, telemetry, Note, SynthyParams, Tag, Velocity, ENV_COUNT, LEVEL_TAGS,
+    MAIN_ENV, MAX_RATIO,
+};
+use fundsp::hacker32::*;
+use nih_plug::prelude::*;
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+/// The note at which key tracking leaves the noise cutoff unchanged
+const NOISE_KEY_TRACK_CENTER: f32 = 60.0;
+/// Operator trigger counters wrap around at this value so they stay exact as floats
+const OP_TRIGGER_WRAP: u32 = 1 << 16;
+
+/// The most voices the voice count can be set to. Only the voices below the voice count get a
+/// graph, raising it builds the new voices' graphs in the background.
+pub const MAX_VOICES: usize = 32;
+
+/// The synth without the plugin around it. Notes go in, stereo audio comes out, and everything
+/// in between is driven by the parameters it was created with. This makes it possible to render
+/// patches from tests, the standalone binary or other audio projects.
+///
+/// [`SynthyEngine::initialize`] has to be called before rendering.
+pub struct SynthyEngine {
+    /// One entry for every possible voice, only the first `max_voices` and voices past them that
+    /// are still ringing out have a graph
+    voices: Vec<Option<Voice>>,
+    /// The configuration of the graph that was last requested from the builder, until a voice
+    /// takes it
+    requested: Option<dsp::GraphConfig>,
+    /// Rebuilds the graphs off the audio thread, spawned in `initialize()`
+    builder: Option<dsp::GraphBuilder>,
+    /// Runs the filters and the output stage once for all voices when the quality shares them
+    bus: Option<Bus>,
+    /// The previous graph of a voice or the bus while it's being faded out after a swap
+    crossfade: Option<(GraphOwner, dsp::Crossfade)>,
+    /// A faded out graph waiting to be handed back to the builder for deallocation
+    retired: Option<dsp::Graph>,
+    sample_rate: f32,
+    params: Pin<Arc<SynthyParams>>,
+    /// Where the performance controls and the output settings come from. These are the engine's
+    /// own parameters, except for a layer that follows the main patch.
+    controls: Pin<Arc<SynthyParams>>,
+    time: Duration,
+    /// Whether any note was played since the engine got initialized
+    enabled: bool,
+    /// The voice that started the most recent note, which is the one the editor shows
+    last_voice: usize,
+    /// Intermediate buffers for anything rendered outside of the output buffers
+    scratch: dsp::Scratch,
+    /// A copy of the input for the current block
+    input: dsp::Scratch,
+    /// A single voice's output for the current block
+    voice_output: dsp::Scratch,
+    /// The voices that share their filters mixed together, which is what goes into the bus
+    bus_input: dsp::Scratch,
+    /// Whether the engine is rendering offline, like when bouncing
+    offline: bool,
+    held_notes: midi::HeldNotes,
+    sostenuto: midi::Sostenuto,
+    /// Jump to the current parameter values in the next block instead of smoothing towards them
+    snap: bool,
+    lfo: dsp::Lfo,
+    drift: dsp::Drift,
+    variation: dsp::NoteVariation,
+    /// The seed the random modulators were last restarted with
+    seed: u32,
+    mod_sources: modulation::ModSources,
+    /// Everything the editor visualizes, filled in once per block
+    telemetry: Arc<telemetry::Telemetry>,
+    /// Whether the output goes through the stereo widener, the compressor and the output gains.
+    /// A layer leaves that to the engine it gets mixed into, so both get compressed together.
+    output_stage: bool,
+    haas: dsp::Haas,
+    compressor: dsp::Compressor,
+    /// Fades the output in and out when bypassing
+    bypass: dsp::Fade,
+}
+
+/// Everything that belongs to a single note: its own graph, envelopes, pitch and variation
+struct Voice {
+    audio: dsp::Graph,
+    /// The configuration `audio` was built with
+    graph_config: dsp::GraphConfig,
+    note: Option<NoteInfo>,
+    /// Whether the voice played a note since the engine got initialized. From then on its graph
+    /// keeps running so filters and the pluck ring out naturally.
+    enabled: bool,
+    /// The last value written to each envelope tag
+    env_levels: [f32; ENV_COUNT],
+    /// Velocity of the note after the velocity curve, scales the amp
+    velocity: f32,
+    glide: dsp::Glide,
+    vibrato: dsp::Vibrato,
+    /// The note's detune in semitones and operator start phase offset in cycles, picked by
+    /// [`dsp::NoteVariation`]
+    detune: f32,
+    phase_offset: f32,
+    /// Bumped on every note that restarts an operator's phase
+    op_triggers: [u32; 2],
+}
+
+/// The graph the voices that share their filters get mixed into
+struct Bus {
+    audio: dsp::Graph,
+    /// The configuration `audio` was built with
+    graph_config: dsp::GraphConfig,
+}
+
+/// Whose graph gets swapped
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GraphOwner {
+    Voice(usize),
+    Bus,
+}
+
+struct NoteInfo {
+    note: Note,
+    velocity: Velocity,
+    on: Duration,
+    /// Envelope levels at the moment this note retriggered a sounding note or got released. The
+    /// segment containing the envelope's start time starts from these instead of its first point
+    /// so fast playing doesn't click
+    start_levels: Option<[f32; ENV_COUNT]>,
+    start_times: [f32; ENV_COUNT],
+    release: Option<Release>,
+}
+
+/// Where a released note continues along the envelopes' time axis
+struct Release {
+    /// When the note was released
+    at: Duration,
+    /// The time of the first point of every envelope's release segment
+    starts: [f32; ENV_COUNT],
+    /// Stretches the release segment, a scale of 2 makes the release twice as long
+    scale: f32,
+}
+
+impl NoteInfo {
+    /// The state of the note at `now` as seen by the envelope at `index`
+    fn gate(&self, now: Duration, index: usize) -> dsp::Gate {
+        match &self.release {
+            Some(release) => dsp::Gate::Released {
+                start: release.starts[index],
+                elapsed: (now - release.at).as_secs_f32(),
+                scale: release.scale,
+            },
+            None => dsp::Gate::Held {
+                elapsed: (now - self.on).as_secs_f32(),
+            },
+        }
+    }
+}
+
+/// What the voice allocator needs to know about a voice
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum VoiceUse {
+    /// The voice doesn't have a graph yet
+    Missing,
+    Free,
+    /// Playing `note` since `on`, until its release is done
+    Playing {
+        note: Note,
+        on: Duration,
+        released: bool,
+    },
+}
+
+/// The voice out of `voices` that plays `note`. A note that's still sounding takes over its own
+/// voice, otherwise the note goes to a free voice. With every voice busy, the oldest released
+/// note gets cut off first and then the oldest held one. `None` when no voice has a graph.
+fn allocate(voices: &[VoiceUse], note: Note) -> Option<usize> {
+    let playing = |index: &usize| match voices[*index] {
+        VoiceUse::Playing { note, .. } => Some(note),
+        _ => None,
+    };
+    let indices = 0..voices.len();
+    indices
+        .clone()
+        .find(|index| playing(index) == Some(note))
+        .or_else(|| {
+            indices
+                .clone()
+                .find(|index| voices[*index] == VoiceUse::Free)
+        })
+        .or_else(|| {
+            indices
+                .filter_map(|index| match voices[index] {
+                    VoiceUse::Playing { on, released, .. } => Some((index, (!released, on))),
+                    _ => None,
+                })
+                .min_by_key(|(_, age)| *age)
+                .map(|(index, _)| index)
+        })
+}
+
+/// Write the plain parameter values to a graph's tags, modulation gets applied on top of these
+/// afterwards. The bus graph only uses the ones for the filters and the output stage.
+fn set_graph_params(audio: &mut dsp::Graph, params: &SynthyParams, snap: bool) {
+    let (wet_smoothing, smoothing) = match snap {
+        true => (dsp::graph::NO_SMOOTHING, dsp::graph::NO_SMOOTHING),
+        false => (dsp::graph::WET_SMOOTHING, dsp::graph::PARAM_SMOOTHING),
+    };
+    audio.set(Tag::WetSmoothing as i64, wet_smoothing);
+    audio.set(Tag::Smoothing as i64, smoothing);
+    audio.set(Tag::Width as i64, params.width.value as f64);
+
+    audio.set(Tag::OpARatio as i64, params.a_ratio() as f64);
+    audio.set(Tag::OpBRatio as i64, params.b_ratio() as f64);
+    audio.set(Tag::OpAModB as i64, params.a_mod_b.value as f64);
+    audio.set(Tag::NoiseAmp as i64, params.noise_amp.value as f64);
+    audio.set(Tag::NoiseWidth as i64, params.noise_width.value as f64);
+    audio.set(Tag::FilterFreq as i64, params.filter_freq.value as f64);
+    audio.set(Tag::FilterQ as i64, params.filter_q.value as f64);
+    audio.set(
+        Tag::FilterDrive as i64,
+        nih_plug::util::db_to_gain(params.filter_drive.value) as f64,
+    );
+    audio.set(Tag::Filter2Freq as i64, params.filter2_freq.value as f64);
+    audio.set(Tag::Filter2Q as i64, params.filter2_q.value as f64);
+    audio.set(Tag::Filter2Vowel as i64, params.filter2_vowel.value as f64);
+    for (tag, param) in [
+        (Tag::EqLowFreq, &params.eq_low_freq),
+        (Tag::EqLowQ, &params.eq_low_q),
+        (Tag::EqMidFreq, &params.eq_mid_freq),
+        (Tag::EqMidQ, &params.eq_mid_q),
+        (Tag::EqHighFreq, &params.eq_high_freq),
+        (Tag::EqHighQ, &params.eq_high_q),
+    ] {
+        audio.set(tag as i64, param.value as f64);
+    }
+    for (tag, gain) in [Tag::EqLowGain, Tag::EqMidGain, Tag::EqHighGain]
+        .into_iter()
+        .zip(params.eq_gains())
+    {
+        audio.set(tag as i64, gain as f64);
+    }
+    audio.set(
+        Tag::EqWet as i64,
+        (params.eq_mix.value * params.fx_amount()) as f64,
+    );
+    let (filter1_gain, filter2_gain) = params
+        .filter_routing
+        .value()
+        .gains(params.filter_balance.value);
+    audio.set(Tag::Filter1Gain as i64, filter1_gain as f64);
+    audio.set(Tag::Filter2Gain as i64, filter2_gain as f64);
+    audio.set(Tag::RingMod as i64, params.ring_mod.value as f64);
+    audio.set(Tag::Sync as i64, params.sync.value as u8 as f64);
+    audio.set(Tag::Pluck as i64, params.pluck.value as f64);
+    audio.set(Tag::PluckDamping as i64, params.pluck_damping.value as f64);
+    audio.set(Tag::PluckDecay as i64, params.pluck_decay.value as f64);
+    audio.set(Tag::ExtLevel as i64, params.ext_level.value as f64);
+}
+
+impl Voice {
+    fn new(audio: dsp::Graph, graph_config: dsp::GraphConfig) -> Self {
+        Self {
+            audio,
+            graph_config,
+            note: None,
+            enabled: false,
+            env_levels: [0f32; ENV_COUNT],
+            velocity: 0f32,
+            glide: dsp::Glide::default(),
+            vibrato: dsp::Vibrato::default(),
+            detune: 0f32,
+            phase_offset: 0f32,
+            op_triggers: [0; 2],
+        }
+    }
+
+    fn usage(&self) -> VoiceUse {
+        match &self.note {
+            Some(note) => VoiceUse::Playing {
+                note: note.note,
+                on: note.on,
+                released: note.release.is_some(),
+            },
+            None => VoiceUse::Free,
+        }
+    }
+
+    fn glide_to(&mut self, params: &SynthyParams, note: Note, glide: bool) {
+        self.glide.set_target(
+            note as f32,
+            params.glide_time.value,
+            params.glide_mode.value(),
+            glide,
+        );
+    }
+
+    /// Write the plain parameter values to the graph's tags, including the ones that are
+    /// specific to this voice
+    fn set_params(&mut self, params: &SynthyParams, snap: bool) {
+        set_graph_params(&mut self.audio, params, snap);
+        self.audio.set(
+            Tag::OpAPhase as i64,
+            (params.a_phase.value / 360.0 + self.phase_offset) as f64,
+        );
+        self.audio.set(
+            Tag::OpBPhase as i64,
+            (params.b_phase.value / 360.0 + self.phase_offset) as f64,
+        );
+        self.audio
+            .set(Tag::OpATrigger as i64, self.op_triggers[0] as f64);
+        self.audio
+            .set(Tag::OpBTrigger as i64, self.op_triggers[1] as f64);
+    }
+
+    /// The vibrato's pitch offset in semitones for the next block
+    fn next_vibrato(
+        &mut self,
+        params: &SynthyParams,
+        now: Duration,
+        block_time: f32,
+        mod_wheel: f32,
+        telemetry: Option<&telemetry::Telemetry>,
+    ) -> f32 {
+        let note_time = match &self.note {
+            Some(note) => (now - note.on).as_secs_f32(),
+            None => return 0f32,
+        };
+        let depth = params.vibrato_depth.value + mod_wheel * params.vibrato_mod_wheel.value;
+        if let Some(telemetry) = telemetry {
+            let modulated = (depth != params.vibrato_depth.value)
+                .then(|| params.vibrato_depth.preview_normalized(depth));
+            telemetry
+                .modulation
+                .set(modulation::ModTarget::VibratoDepth, modulated);
+        }
+
+        self.vibrato.next(
+            block_time,
+            params.vibrato_rate.value,
+            depth,
+            params.vibrato_delay.value,
+            note_time,
+        )
+    }
+
+    /// The noise filter's cutoff, following the played pitch and the noise envelope
+    fn noise_cutoff(&self, params: &SynthyParams, pitch: f32) -> f32 {
+        let octaves = (pitch - NOISE_KEY_TRACK_CENTER) / 12.0 * params.noise_key_track.value
+            + self.env_levels[2] * params.noise_filter_env.value;
+        (params.noise_cutoff.value * octaves.exp2()).clamp(20.0, 20_000.0)
+    }
+}
+
+/// Apply modulation offsets to a graph's parameters that can be modulated, overriding the plain
+/// values set at the start of the block
+fn modulate_graph(
+    audio: &mut dsp::Graph,
+    params: &SynthyParams,
+    offsets: &[f32; modulation::TARGET_COUNT],
+    telemetry: Option<&telemetry::Telemetry>,
+) {
+    use modulation::ModTarget;
+    // The ratios are modulated as a whole on the same scale as the coarse parameters
+    for (target, ratio, tag) in [
+        (ModTarget::OpARatio, params.a_ratio(), Tag::OpARatio),
+        (ModTarget::OpBRatio, params.b_ratio(), Tag::OpBRatio),
+    ] {
+        let offset = offsets[target as usize];
+        let modulated = (offset != 0f32).then(|| (ratio / MAX_RATIO + offset).clamp(0f32, 1f32));
+        if let Some(normalized) = modulated {
+            audio.set(tag as i64, (normalized * MAX_RATIO) as f64);
+        }
+        if let Some(telemetry) = telemetry {
+            telemetry.modulation.set(target, modulated);
+        }
+    }
+    for (target, param, tag) in [
+        (ModTarget::RingMod, &params.ring_mod, Tag::RingMod),
+        (ModTarget::Pluck, &params.pluck, Tag::Pluck),
+        (ModTarget::Vowel, &params.filter2_vowel, Tag::Filter2Vowel),
+    ] {
+        let offset = offsets[target as usize];
+        let modulated = (offset != 0f32)
+            .then(|| (param.preview_normalized(param.value) + offset).clamp(0f32, 1f32));
+        if let Some(normalized) = modulated {
+            audio.set(tag as i64, param.preview_plain(normalized) as f64);
+        }
+        if let Some(telemetry) = telemetry {
+            telemetry.modulation.set(target, modulated);
+        }
+    }
+}
+
+/// Darken the filters by scaling their cutoffs with the soft pedal's `soft_cutoff`
+fn soften_filters(audio: &mut dsp::Graph, params: &SynthyParams, soft_cutoff: f32) {
+    if soft_cutoff == 1f32 {
+        return;
+    }
+    for (tag, param) in [
+        (Tag::FilterFreq, &params.filter_freq),
+        (Tag::Filter2Freq, &params.filter2_freq),
+    ] {
+        audio.set(tag as i64, (param.value * soft_cutoff) as f64);
+    }
+}
+
+impl SynthyEngine {
+    pub fn new(params: Pin<Arc<SynthyParams>>) -> Self {
+        Self {
+            output_stage: true,
+            ..Self::layer(params.clone(), params)
+        }
+    }
+
+    /// An engine playing the patch in `params` that takes the mod wheel and pedals from
+    /// `controls`, so a layer responds to the same controls as the patch it plays along with. Its
+    /// output is meant to be mixed into that patch's engine with
+    /// [`render_layered`](Self::render_layered), which then applies the output stage.
+    pub fn layer(params: Pin<Arc<SynthyParams>>, controls: Pin<Arc<SynthyParams>>) -> Self {
+        Self {
+            voices: Vec::new(),
+            requested: None,
+            builder: None,
+            bus: None,
+            crossfade: None,
+            retired: None,
+            sample_rate: DEFAULT_SR as f32,
+            params,
+            controls,
+            time: Duration::default(),
+            enabled: false,
+            last_voice: 0,
+            scratch: dsp::Scratch::default(),
+            input: dsp::Scratch::default(),
+            voice_output: dsp::Scratch::default(),
+            bus_input: dsp::Scratch::default(),
+            offline: false,
+            held_notes: midi::HeldNotes::default(),
+            sostenuto: midi::Sostenuto::default(),
+            snap: false,
+            lfo: dsp::Lfo::default(),
+            drift: dsp::Drift::default(),
+            variation: dsp::NoteVariation::default(),
+            seed: dsp::rng::DEFAULT_SEED,
+            mod_sources: modulation::ModSources::default(),
+            telemetry: Arc::new(telemetry::Telemetry::default()),
+            output_stage: false,
+            haas: dsp::Haas::default(),
+            compressor: dsp::Compressor::default(),
+            bypass: dsp::Fade::default(),
+        }
+    }
+
+    /// Prepare for rendering at `sample_rate`. `offline` allows the engine to trade speed for
+    /// quality, like when bouncing.
+    pub fn initialize(&mut self, sample_rate: f32, offline: bool) {
+        self.sample_rate = sample_rate;
+        self.offline = offline;
+        // Built right here instead of on the worker, so a bounce uses its own quality from the
+        // first sample on. Anything left over from before gets dropped with it.
+        let graph_config = self.target_graph_config();
+        let max_voices = self.max_voices();
+        self.voices = (0..MAX_VOICES)
+            .map(|index| {
+                (index < max_voices).then(|| {
+                    let mut audio = dsp::graph::build(&graph_config, self.sample_rate);
+                    audio.reset(Some(self.sample_rate as f64));
+                    Voice::new(audio, graph_config)
+                })
+            })
+            .collect();
+        self.bus = graph_config.shared_filters.then(|| {
+            let graph_config = dsp::GraphConfig {
+                bus: true,
+                ..graph_config
+            };
+            Bus {
+                audio: dsp::graph::build(&graph_config, self.sample_rate),
+                graph_config,
+            }
+        });
+        self.last_voice = 0;
+        self.requested = None;
+        self.crossfade = None;
+        self.retired = None;
+        self.haas.reset(self.sample_rate);
+        self.compressor.reset(self.sample_rate);
+        self.bypass.reset(self.sample_rate);
+        self.scratch.resize(MAX_BUFFER_SIZE);
+        self.input.resize(MAX_BUFFER_SIZE);
+        self.voice_output.resize(MAX_BUFFER_SIZE);
+        self.bus_input.resize(MAX_BUFFER_SIZE);
+        if self.builder.is_none() {
+            self.builder = Some(dsp::GraphBuilder::spawn());
+        }
+    }
+
+    pub fn params(&self) -> &Pin<Arc<SynthyParams>> {
+        &self.params
+    }
+
+    /// Whether a note is still playing, including its release
+    pub fn is_sounding(&self) -> bool {
+        self.voices
+            .iter()
+            .flatten()
+            .any(|voice| voice.note.is_some())
+    }
+
+    /// Everything the editor visualizes, shared so it can be read from other threads
+    pub fn telemetry(&self) -> &Arc<telemetry::Telemetry> {
+        &self.telemetry
+    }
+
+    /// The latency of the output in samples, from the oversampling's decimation filter and the
+    /// compressor's lookahead. A layer gets compressed along with the engine it's mixed into, so
+    /// only its decimation filter counts.
+    pub fn latency_samples(&self) -> u32 {
+        let oversampling = self.target_graph_config().oversampling;
+        dsp::oversample::latency_samples(oversampling) + self.lookahead_samples() as u32
+    }
+
+    /// How far the compressor looks ahead in samples
+    fn lookahead_samples(&self) -> usize {
+        match self.output_stage {
+            true => self
+                .compressor
+                .lookahead_samples(self.controls.comp_lookahead.value / 1000.0),
+            false => 0,
+        }
+    }
+
+    /// Jump to the current parameter values in the next block instead of smoothing towards them,
+    /// for when they got changed all at once like when loading a preset
+    pub fn snap_params(&mut self) {
+        self.snap = true;
+    }
+
+    /// Restart the vibrato and the random modulators from the current seed, so renders starting
+    /// from here are deterministic
+    pub fn restart_modulators(&mut self) {
+        self.seed = self.params.seed.value as u32;
+        for voice in self.voices.iter_mut().flatten() {
+            voice.vibrato.reset();
+        }
+        self.lfo.reset(self.seed);
+        self.drift.reset(self.seed);
+        self.variation.reset(self.seed);
+    }
+
+    /// How many voices new notes can be played on
+    fn max_voices(&self) -> usize {
+        (self.params.max_voices.value as usize).clamp(1, MAX_VOICES)
+    }
+
+    pub fn note_on(&mut self, note: Note, velocity: Velocity) {
+        self.held_notes.push(note);
+        self.sostenuto.note_on(note);
+
+        let index = match self.max_voices() {
+            // With low or high note priority a new note doesn't always take over the sounding
+            // note
+            1 => {
+                let priority = self.params.note_priority.value();
+                let sounding =
+                    matches!(self.voices.first(), Some(Some(voice)) if voice.note.is_some());
+                if sounding && self.held_notes.select(priority) != Some(note) {
+                    return;
+                }
+                0
+            }
+            max_voices => {
+                let mut usage = [VoiceUse::Missing; MAX_VOICES];
+                for (usage, voice) in usage.iter_mut().zip(&self.voices) {
+                    if let Some(voice) = voice {
+                        *usage = voice.usage();
+                    }
+                }
+                match allocate(&usage[..max_voices], note) {
+                    Some(index) => index,
+                    None => return,
+                }
+            }
+        };
+
+        // Every note glides from the most recent one. In legato mode only glide when the
+        // previous note is still being held.
+        let from = match self.voices.get(self.last_voice) {
+            Some(Some(previous)) => previous.note.as_ref().map(|_| previous.glide.pitch()),
+            _ => None,
+        };
+        let legato = self.held_notes.as_slice().len() > 1;
+        let glide = from.is_some() && (legato || !self.params.legato_glide.value);
+        let voice = match self.voices.get_mut(index) {
+            Some(Some(voice)) => voice,
+            _ => return,
+        };
+        if let Some(from) = from {
+            voice
+                .glide
+                .set_target(from, 0f32, self.params.glide_mode.value(), false);
+        }
+        voice.glide_to(&self.params, note, glide);
+        if !glide {
+            voice.vibrato.reset();
+        }
+
+        // Operators that aren't free-running restart at their start phase
+        let retrigger = [self.params.a_retrigger.value, self.params.b_retrigger.value];
+        for (trigger, retrigger) in voice.op_triggers.iter_mut().zip(retrigger) {
+            if retrigger {
+                *trigger = (*trigger + 1) % OP_TRIGGER_WRAP;
+            }
+        }
+        self.variation.next_note(self.params.variation.value);
+        voice.detune = self.variation.detune();
+        voice.phase_offset = self.variation.phase_offset();
+        self.lfo.trigger(self.params.lfo_one_shot.value);
+        self.enabled = true;
+        voice.enabled = true;
+        self.last_voice = index;
+        for trace in &self.telemetry.envelope_traces {
+            trace.clear();
+        }
+
+        // Retriggering over a sounding note restarts the envelopes from wherever
+        // they currently are instead of snapping back to the first point
+        let start_levels = voice.note.as_ref().map(|_| voice.env_levels);
+
+        voice.note = Some(NoteInfo {
+            note,
+            velocity,
+            on: self.time,
+            start_levels,
+            start_times: [0f32; ENV_COUNT],
+            release: None,
+        });
+    }
+
+    pub fn note_off(&mut self, note: Note, velocity: Velocity) {
+        if self.sostenuto.defer(note) {
+            return;
+        }
+        self.held_notes.remove(note);
+
+        // Every envelope jumps to its release segment, the segment between its last two points,
+        // continuing from the current levels
+        let mut starts = [0f32; ENV_COUNT];
+        for (start, envelope) in starts.iter_mut().zip(self.params.envelopes()) {
+            if let (Ok(points), Ok(timing)) = (envelope.points.read(), envelope.timing.read()) {
+                *start = envelope.shape(&points, *timing).release_start();
+            }
+        }
+
+        let mono = self.max_voices() == 1;
+        let voices = self
+            .voices
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, voice)| Some((index, voice.as_mut()?)));
+        for (index, voice) in voices {
+            let current_note = match &mut voice.note {
+                Some(current_note) if current_note.note == note => current_note,
+                _ => continue,
+            };
+
+            // A single voice falls back to the next held note without retriggering, like
+            // classic mono synths
+            if mono && index == 0 {
+                let priority = self.params.note_priority.value();
+                if let Some(next) = self.held_notes.select(priority) {
+                    current_note.note = next;
+                    voice.glide_to(&self.params, next, true);
+                    continue;
+                }
+            }
+
+            current_note.start_times = starts;
+            current_note.start_levels = Some(voice.env_levels);
+            current_note.release = Some(Release {
+                at: self.time,
+                starts,
+                scale: midi::release_scale(velocity, self.params.release_velocity_scale.value),
+            });
+        }
+    }
+
+    /// Render the next `output.len()` samples without an input signal
+    pub fn render(&mut self, output: [&mut [f32]; 2]) {
+        let [left, right] = output;
+        for (left, right) in left
+            .chunks_mut(MAX_BUFFER_SIZE)
+            .zip(right.chunks_mut(MAX_BUFFER_SIZE))
+        {
+            self.render_block(left, right, false, None);
+        }
+    }
+
+    /// Render the next `channels.len()` samples in place, using what's in `channels` as the
+    /// input signal for the external input
+    pub fn process(&mut self, channels: [&mut [f32]; 2]) {
+        let [left, right] = channels;
+        for (left, right) in left
+            .chunks_mut(MAX_BUFFER_SIZE)
+            .zip(right.chunks_mut(MAX_BUFFER_SIZE))
+        {
+            self.render_block(left, right, true, None);
+        }
+    }
+
+    /// Render the next `channels.len()` samples like [`process`](Self::process) when
+    /// `has_input` is set and like [`render`](Self::render) otherwise, mixing in a layer's
+    /// output at `gains` for this engine and the layer before the output stage
+    pub fn render_layered(
+        &mut self,
+        channels: [&mut [f32]; 2],
+        has_input: bool,
+        layer: [&[f32]; 2],
+        gains: [f32; 2],
+    ) {
+        let [left, right] = channels;
+        let [layer_left, layer_right] = layer;
+        for ((left, right), layer) in left
+            .chunks_mut(MAX_BUFFER_SIZE)
+            .zip(right.chunks_mut(MAX_BUFFER_SIZE))
+            .zip(
+                layer_left
+                    .chunks(MAX_BUFFER_SIZE)
+                    .zip(layer_right.chunks(MAX_BUFFER_SIZE)),
+            )
+        {
+            self.render_block(left, right, has_input, Some(([layer.0, layer.1], gains)));
+        }
+    }
+
+    /// Render a single block of at most `MAX_BUFFER_SIZE` samples. Parameters, modulators and
+    /// envelopes are all updated once per block. `layer` is another engine's output for the same
+    /// block and the gains for this engine and that output.
+    fn render_block(
+        &mut self,
+        left: &mut [f32],
+        right: &mut [f32],
+        has_input: bool,
+        layer: Option<([&[f32]; 2], [f32; 2])>,
+    ) {
+        // Long release tails would otherwise end up in denormal territory and tank performance
+        let _ftz = dsp::ScopedFtz::enable();
+
+        // Picking a different seed restarts the random modulators
+        if self.params.seed.value as u32 != self.seed {
+            self.restart_modulators();
+        }
+
+        // Lifting the sostenuto pedal releases the notes it was holding on to
+        let released = self
+            .sostenuto
+            .set(self.controls.sostenuto.value, &self.held_notes);
+        for note in released.as_slice() {
+            self.note_off(*note, midi::PEDAL_RELEASE_VELOCITY);
+        }
+
+        let block_len = left.len();
+        let block_time = block_len as f32 / self.sample_rate;
+        self.update_graph();
+
+        self.mod_sources.mod_wheel = self.controls.mod_wheel.value;
+        self.mod_sources.expression = self.controls.expression.value;
+        self.drift.next(block_time);
+        // The LFO keeps running while muted so unmuting doesn't restart it
+        let lfo = self.next_lfo(block_time);
+        let lfo = match self.params.lfo_mute.value {
+            true => 0f32,
+            false => lfo,
+        };
+        self.telemetry.lfo.set(lfo);
+
+        // The input may share its buffers with the output, so it needs to be copied out before
+        // rendering
+        debug_assert!(block_len <= self.scratch.len());
+        let [input_left, input_right] = self.input.channels(block_len);
+        if has_input {
+            input_left.copy_from_slice(left);
+            input_right.copy_from_slice(right);
+        } else {
+            input_left.fill(0f32);
+            input_right.fill(0f32);
+        }
+
+        left.fill(0f32);
+        right.fill(0f32);
+        for channel in self.bus_input.channels(block_len) {
+            channel.fill(0f32);
+        }
+        // The shared filters get gated and modulated like the most recent voice's would be
+        let mut bus_amp = 0f32;
+        let mut bus_offsets = [0f32; modulation::TARGET_COUNT];
+        let max_voices = self.max_voices();
+        for index in 0..self.voices.len() {
+            // Voices past the voice count only keep going until their note is done
+            let voice = match &self.voices[index] {
+                Some(voice) => voice,
+                None => continue,
+            };
+            let fading = matches!(
+                &self.crossfade,
+                Some((owner, _)) if *owner == GraphOwner::Voice(index)
+            );
+            if !voice.enabled || (index >= max_voices && voice.note.is_none() && !fading) {
+                continue;
+            }
+            let shared = voice.graph_config.shared_filters;
+            let routed = self.render_voice(index, block_len, block_time, lfo);
+            let mix = match shared {
+                true => {
+                    bus_amp += routed.levels[3];
+                    if index == self.last_voice {
+                        bus_offsets = routed.offsets;
+                    }
+                    self.bus_input.channels(block_len)
+                }
+                false => [&mut *left, &mut *right],
+            };
+            for (channel, voice) in mix
+                .into_iter()
+                .zip([&self.voice_output.left, &self.voice_output.right])
+            {
+                for (sample, voice) in channel.iter_mut().zip(&voice[..block_len]) {
+                    *sample += voice;
+                }
+            }
+        }
+        if self.enabled && self.render_bus(block_len, bus_amp, &bus_offsets) {
+            for (channel, bus) in [&mut *left, &mut *right]
+                .into_iter()
+                .zip([&self.voice_output.left, &self.voice_output.right])
+            {
+                for (sample, bus) in channel.iter_mut().zip(&bus[..block_len]) {
+                    *sample += bus;
+                }
+            }
+        }
+        let active_voices = self
+            .voices
+            .iter()
+            .flatten()
+            .filter(|voice| voice.note.is_some())
+            .count();
+        self.telemetry.set_active_voices(active_voices);
+
+        if let Some((layer, [gain, layer_gain])) = layer {
+            for (channel, layer) in [&mut *left, &mut *right].into_iter().zip(layer) {
+                for (sample, layer) in channel.iter_mut().zip(layer) {
+                    *sample = *sample * gain + layer * layer_gain;
+                }
+            }
+        }
+
+        if self.enabled {
+            self.time += Duration::from_secs_f32(block_time);
+        }
+        // The layer needs the output stage too, even when it's the only one that played so far
+        if self.output_stage && (self.enabled || layer.is_some()) {
+            self.haas
+                .process(&mut *right, &self.controls.haas_settings());
+            let settings = self.controls.compressor_settings(self.lookahead_samples());
+            if self.snap {
+                self.compressor.snap_mix(settings.mix);
+            }
+            let reduction = self
+                .compressor
+                .process([&mut *left, &mut *right], &settings);
+            self.telemetry.gain_reduction.set(reduction);
+            for (channel, gain) in [&mut *left, &mut *right]
+                .into_iter()
+                .zip(self.controls.output_gains())
+            {
+                if gain != 1f32 {
+                    channel.iter_mut().for_each(|sample| *sample *= gain);
+                }
+            }
+            // It's an instrument, so bypassing it means silence
+            self.bypass
+                .process([&mut *left, &mut *right], !self.controls.bypass.value);
+            for (peak, channel) in self.telemetry.peaks.iter().zip([&*left, &*right]) {
+                peak.set(channel.iter().fold(0f32, |max, x| max.max(x.abs())));
+            }
+            self.telemetry.scope.push(left, right);
+        }
+        self.snap = false;
+    }
+
+    /// Update the voice at `index` for the next block and render it into `voice_output`. `lfo`
+    /// is the LFO's output, which every voice shares. Returns where the voice's modulation ended
+    /// up, for the bus when the voice shares its filters.
+    fn render_voice(
+        &mut self,
+        index: usize,
+        block_len: usize,
+        block_time: f32,
+        lfo: f32,
+    ) -> modulation::ModBus {
+        let params = &*self.params;
+        // Only the voice the editor shows reports what it's doing
+        let telemetry = (index == self.last_voice).then(|| &*self.telemetry);
+        let voice = match &mut self.voices[index] {
+            Some(voice) => voice,
+            None => return modulation::ModBus::default(),
+        };
+        voice.set_params(params, self.snap);
+
+        if let Some(note) = &voice.note {
+            voice.velocity = midi::velocity_response(note.velocity, params.velocity_curve.value);
+        }
+        voice
+            .audio
+            .set(Tag::Expression as i64, self.mod_sources.expression as f64);
+
+        let drift = params.drift.value;
+        let pitch = voice.glide.next(block_time)
+            + voice.next_vibrato(
+                params,
+                self.time,
+                block_time,
+                self.mod_sources.mod_wheel,
+                telemetry,
+            )
+            + voice.detune
+            + self.drift.get(dsp::DriftTarget::Pitch) * drift * dsp::drift::MAX_PITCH_DRIFT;
+        voice
+            .audio
+            .set(Tag::Freq as i64, dsp::pitch_to_freq(pitch) as f64);
+
+        // The note ends once the main envelope runs past its last point. A hold on the last
+        // point stops exactly on it, so that keeps the note going.
+        let envelopes = params.envelopes();
+        let main_env = &envelopes[MAIN_ENV];
+        let ended = match (&voice.note, main_env.points.read(), main_env.timing.read()) {
+            (Some(note), Ok(points), Ok(timing)) => {
+                let shape = main_env.shape(&points, *timing);
+                shape.is_finished(shape.time(note.gate(self.time, MAIN_ENV)))
+            }
+            _ => false,
+        };
+        if ended {
+            voice.note = None;
+        }
+        if let Some(telemetry) = telemetry {
+            match voice.note {
+                Some(_) => telemetry.set_pitch(Some(pitch), voice.glide.target()),
+                None => telemetry.set_pitch(None, None),
+            }
+        }
+
+        for (index, envelope) in envelopes.iter().enumerate() {
+            let mut position = None;
+            if let (Some(note), Ok(points), Ok(timing)) =
+                (&voice.note, envelope.points.read(), envelope.timing.read())
+            {
+                let start = note
+                    .start_levels
+                    .map(|levels| (note.start_times[index], levels[index]));
+                let (time, level) = envelope
+                    .shape(&points, *timing)
+                    .value_at(note.gate(self.time, index), start);
+                if let Some(level) = level {
+                    voice.env_levels[index] = dsp::flush_denormal(level);
+                    if let Some(telemetry) = telemetry {
+                        telemetry.envelope_traces[index].record(time, level);
+                    }
+                }
+                position = Some(time);
+            }
+            if let Some(telemetry) = telemetry {
+                telemetry.set_envelope_position(index, position);
+            }
+        }
+
+        // Route every envelope and the LFO to their destinations
+        let mut bus = modulation::ModBus::default();
+        for (index, envelope) in envelopes.iter().enumerate() {
+            let depth = envelope.depth.map(|depth| depth.value).unwrap_or(1f32);
+            let level = match envelope.mute.value {
+                true => 1f32,
+                false => modulation::apply_curve(voice.env_levels[index], envelope.curve.value),
+            };
+            bus.add(envelope.destination.value(), level * depth);
+        }
+        bus.add(params.lfo_destination.value(), lfo);
+        bus.scale_offsets(params.mod_amount.value);
+
+        // Operator levels wander a little with the drift amount
+        let level_drift =
+            |target| 1f32 + self.drift.get(target) * drift * dsp::drift::MAX_LEVEL_DRIFT;
+        bus.levels[0] *= level_drift(dsp::DriftTarget::OpALevel);
+        bus.levels[1] *= level_drift(dsp::DriftTarget::OpBLevel);
+
+        // Everything that can make the voice blow up goes through safe mode together
+        let (noise_fm_a, noise_fm_b) = params
+            .noise_fm_target
+            .value()
+            .depths(params.noise_fm_depth.value);
+        let mut drive = dsp::safety::Drive {
+            mods: [params.a_mod.value, params.b_mod.value],
+            levels: [bus.levels[0], bus.levels[1]],
+            noise_fm: [noise_fm_a, noise_fm_b],
+            ext_fm: params.ext_fm.value,
+            amp: bus.levels[3],
+        };
+        if params.safe_mode.value {
+            drive = drive.limit();
+        }
+        // Softly played notes and the soft pedal make the voice quieter, the soft pedal also
+        // makes it darker
+        let velocity_gain = midi::velocity_gain(voice.velocity, params.velocity_amp.value);
+        let (soft_gain, soft_cutoff) = midi::soft_pedal(
+            self.controls.soft_pedal.value,
+            params.soft_pedal_amount.value,
+        );
+        bus.levels[3] = drive.amp * velocity_gain * soft_gain;
+        soften_filters(&mut voice.audio, params, soft_cutoff);
+        for (tag, level) in LEVEL_TAGS.iter().zip(bus.levels) {
+            voice.audio.set(*tag as i64, level as f64);
+        }
+        for (tag, value) in [
+            (Tag::OpAMod, drive.mods[0]),
+            (Tag::OpBMod, drive.mods[1]),
+            (Tag::NoiseFmA, drive.noise_fm[0]),
+            (Tag::NoiseFmB, drive.noise_fm[1]),
+            (Tag::ExtFm, drive.ext_fm),
+        ] {
+            voice.audio.set(tag as i64, value as f64);
+        }
+        modulate_graph(&mut voice.audio, params, &bus.offsets, telemetry);
+        let noise_cutoff = voice.noise_cutoff(params, pitch);
+        voice
+            .audio
+            .set(Tag::NoiseCutoff as i64, noise_cutoff as f64);
+
+        // The graph runs in 32-bit so it can render straight into the voice's buffers
+        let input = [
+            &self.input.left[..block_len],
+            &self.input.right[..block_len],
+        ];
+        let mut output = self.voice_output.channels(block_len);
+        voice.audio.process(block_len, &input, &mut output);
+
+        // Mix the graph that's being replaced in until its crossfade is done
+        if let Some((owner, crossfade)) = &mut self.crossfade {
+            if *owner == GraphOwner::Voice(index) {
+                let mut old = self.scratch.channels(block_len);
+                crossfade.old.process(block_len, &input, &mut old);
+                crossfade.mix(output, old);
+                if crossfade.is_done() {
+                    self.retired = self.crossfade.take().map(|(_, crossfade)| crossfade.old);
+                }
+            }
+        }
+        bus
+    }
+
+    /// Run the mixed voices that share their filters and the input through the bus graph into
+    /// `voice_output`. `amp` is the sum of those voices' amp levels, which gates the input like
+    /// every voice's own amp does otherwise. Returns whether there's a bus to render.
+    fn render_bus(
+        &mut self,
+        block_len: usize,
+        amp: f32,
+        offsets: &[f32; modulation::TARGET_COUNT],
+    ) -> bool {
+        let params = &*self.params;
+        let bus = match &mut self.bus {
+            Some(bus) => bus,
+            None => return false,
+        };
+        set_graph_params(&mut bus.audio, params, self.snap);
+        bus.audio
+            .set(Tag::Expression as i64, self.mod_sources.expression as f64);
+        bus.audio.set(Tag::Env as i64, amp as f64);
+        let (_, soft_cutoff) = midi::soft_pedal(
+            self.controls.soft_pedal.value,
+            params.soft_pedal_amount.value,
+        );
+        soften_filters(&mut bus.audio, params, soft_cutoff);
+        modulate_graph(&mut bus.audio, params, offsets, None);
+
+        let input = [
+            &self.bus_input.left[..block_len],
+            &self.bus_input.right[..block_len],
+            &self.input.left[..block_len],
+            &self.input.right[..block_len],
+        ];
+        let mut output = self.voice_output.channels(block_len);
+        bus.audio.process(block_len, &input, &mut output);
+
+        if let Some((owner, crossfade)) = &mut self.crossfade {
+            if *owner == GraphOwner::Bus {
+                let mut old = self.scratch.channels(block_len);
+                crossfade.old.process(block_len, &input, &mut old);
+                crossfade.mix(output, old);
+                if crossfade.is_done() {
+                    self.retired = self.crossfade.take().map(|(_, crossfade)| crossfade.old);
+                }
+            }
+        }
+        true
+    }
+
+    /// Advance the LFO and get its output scaled by its depth
+    fn next_lfo(&mut self, block_time: f32) -> f32 {
+        let value = self.lfo.next(block_time, &self.params.lfo_settings());
+        let value = match self.params.lfo_unipolar.value {
+            true => value * 0.5 + 0.5,
+            false => value,
+        };
+        modulation::apply_curve(value, self.params.lfo_curve.value) * self.params.lfo_depth.value
+    }
+
+    /// The structure the graphs should have right now
+    fn target_graph_config(&self) -> dsp::GraphConfig {
+        let mut config = self.params.graph_config();
+        // Bounces aren't bound by real time, so they can always use the highest quality
+        if self.offline && self.params.offline_quality.value {
+            config.oversampling = dsp::Quality::High.oversampling();
+            config.shared_filters = dsp::Quality::High.shared_filters();
+        }
+        config
+    }
+
+    /// The voice that needs a new graph the most. Sounding voices come first so a change reaches
+    /// what's being heard before anything else, then the outdated voices below the voice count
+    /// and finally the ones that don't have a graph yet.
+    fn outdated_voice(&self, config: dsp::GraphConfig) -> Option<usize> {
+        let max_voices = self.max_voices();
+        self.voices
+            .iter()
+            .enumerate()
+            .filter_map(|(index, voice)| match voice {
+                Some(voice) if voice.graph_config == config => None,
+                Some(voice) if voice.note.is_some() => Some((index, 0)),
+                Some(_) if index < max_voices => Some((index, 1)),
+                None if index < max_voices => Some((index, 2)),
+                _ => None,
+            })
+            .min_by_key(|(_, rank)| *rank)
+            .map(|(index, _)| index)
+    }
+
+    /// An idle voice past the voice count, whose graph isn't needed anymore
+    fn surplus_voice(&self) -> Option<usize> {
+        let max_voices = self.max_voices();
+        self.voices
+            .iter()
+            .enumerate()
+            .skip(max_voices)
+            .find(|(_, voice)| matches!(voice, Some(voice) if voice.note.is_none()))
+            .map(|(index, _)| index)
+    }
+
+    /// The graph that needs to be rebuilt next and the configuration to build it with. The bus
+    /// comes before the voices, so voices that start sharing their filters have somewhere to go.
+    fn outdated_graph(&self, config: dsp::GraphConfig) -> Option<(GraphOwner, dsp::GraphConfig)> {
+        let bus_config = dsp::GraphConfig {
+            bus: true,
+            ..config
+        };
+        let bus_outdated = self.bus.as_ref().map(|bus| bus.graph_config) != Some(bus_config);
+        match config.shared_filters && bus_outdated {
+            true => Some((GraphOwner::Bus, bus_config)),
+            false => self
+                .outdated_voice(config)
+                .map(|index| (GraphOwner::Voice(index), config)),
+        }
+    }
+
+    /// Put a newly built graph in place for `owner`. Returns the graph it replaced, if any, and
+    /// whether that one was playing and needs to be faded out.
+    fn install(
+        &mut self,
+        owner: GraphOwner,
+        audio: dsp::Graph,
+        graph_config: dsp::GraphConfig,
+    ) -> Option<(dsp::Graph, bool)> {
+        match owner {
+            GraphOwner::Bus => match &mut self.bus {
+                Some(bus) => {
+                    bus.graph_config = graph_config;
+                    Some((std::mem::replace(&mut bus.audio, audio), self.enabled))
+                }
+                None => {
+                    self.bus = Some(Bus {
+                        audio,
+                        graph_config,
+                    });
+                    None
+                }
+            },
+            GraphOwner::Voice(index) => match &mut self.voices[index] {
+                Some(voice) => {
+                    voice.graph_config = graph_config;
+                    Some((std::mem::replace(&mut voice.audio, audio), voice.enabled))
+                }
+                None => {
+                    self.voices[index] = Some(Voice::new(audio, graph_config));
+                    None
+                }
+            },
+        }
+    }
+
+    /// Request a rebuild when the graphs' structure changed or voices are missing, and swap
+    /// finished graphs into the voices and the bus one at a time
+    fn update_graph(&mut self) {
+        let config = self.target_graph_config();
+        // Taken out for the duration so the swaps below can go through `self`
+        let builder = match self.builder.take() {
+            Some(builder) => builder,
+            None => return,
+        };
+        if self.offline {
+            // Bounces have to come out the same every time, so rather than crossfading whenever
+            // the worker happens to be done they wait for the new graphs and switch to them
+            // right away
+            while self.retired.is_none() {
+                let (owner, graph_config) = match self.outdated_graph(config) {
+                    Some(outdated) => outdated,
+                    None => break,
+                };
+                if !builder.request_blocking(graph_config, self.sample_rate) {
+                    break;
+                }
+                let graph = match builder.slot.wait(graph_config) {
+                    Some(graph) => graph,
+                    None => break,
+                };
+                if let Some((old, _)) = self.install(owner, graph, graph_config) {
+                    self.retired = builder.slot.retire_blocking(old).err();
+                }
+            }
+        } else {
+            match self.outdated_graph(config) {
+                Some((_, graph_config)) if self.requested != Some(graph_config) => {
+                    if builder.request(graph_config, self.sample_rate) {
+                        self.requested = Some(graph_config);
+                    }
+                }
+                // Only swap once the previous swap has been fully cleaned up
+                Some((owner, graph_config))
+                    if self.crossfade.is_none() && self.retired.is_none() =>
+                {
+                    if let Some(graph) = builder.slot.take(graph_config) {
+                        // A graph that never played has nothing to fade out
+                        match self.install(owner, graph, graph_config) {
+                            Some((old, true)) => {
+                                self.crossfade =
+                                    Some((owner, dsp::Crossfade::new(old, self.sample_rate)))
+                            }
+                            Some((old, false)) => self.retired = Some(old),
+                            None => {}
+                        }
+                        // The next outdated graph needs one of its own
+                        self.requested = None;
+                    }
+                }
+                Some(_) => {}
+                None => self.requested = None,
+            }
+        }
+
+        // Lowering the voice count hands the graphs of the voices past it back to the builder
+        // once they're done playing, one at a time like any other retired graph. The bus goes
+        // once no voice shares its filters anymore.
+        if self.crossfade.is_none() && self.retired.is_none() {
+            let shared = config.shared_filters
+                || self
+                    .voices
+                    .iter()
+                    .flatten()
+                    .any(|voice| voice.graph_config.shared_filters);
+            if let Some(index) = self.surplus_voice() {
+                self.retired = self.voices[index].take().map(|voice| voice.audio);
+            } else if !shared {
+                self.retired = self.bus.take().map(|bus| bus.audio);
+            }
+        }
+        if let Some(graph) = self.retired.take() {
+            self.retired = builder.slot.retire(graph).err();
+        }
+        self.builder = Some(builder);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::Preset;
+
+    const SAMPLE_RATE: f32 = 48_000.0;
+
+    fn playing(note: Note, on_ms: u64, released: bool) -> VoiceUse {
+        VoiceUse::Playing {
+            note,
+            on: Duration::from_millis(on_ms),
+            released,
+        }
+    }
+
+    /// Restore a patch with only `voices` and `sostenuto` differing from the init patch
+    fn set_params(params: &Pin<Arc<SynthyParams>>, voices: i32, sostenuto: bool) {
+        let preset = Preset {
+            params: [
+                (
+                    String::from("max_voices"),
+                    params.max_voices.preview_normalized(voices),
+                ),
+                (String::from("sostenuto"), sostenuto as u8 as f32),
+            ]
+            .into_iter()
+            .collect(),
+            fields: Default::default(),
+        };
+        preset.restore(params.as_ref());
+    }
+
+    fn engine(voices: i32) -> SynthyEngine {
+        let mut engine = SynthyEngine::new(Arc::pin(SynthyParams::default()));
+        set_params(&engine.params, voices, false);
+        engine.initialize(SAMPLE_RATE, false);
+        engine
+    }
+
+    fn render(engine: &mut SynthyEngine) {
+        let mut left = [0f32; 64];
+        let mut right = [0f32; 64];
+        engine.render([&mut left, &mut right]);
+    }
+
+    /// The note the voice at `index` is holding, released notes don't count
+    fn held_note(engine: &SynthyEngine, index: usize) -> Option<Note> {
+        match &engine.voices[index] {
+            Some(Voice {
+                note: Some(note), ..
+            }) if note.release.is_none() => Some(note.note),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn retriggers_the_voice_playing_the_same_note() {
+        let voices = [VoiceUse::Free, playing(60, 0, true), playing(62, 10, false)];
+        assert_eq!(allocate(&voices, 60), Some(1));
+        assert_eq!(allocate(&voices, 64), Some(0));
+    }
+
+    #[test]
+    fn steals_released_notes_before_held_ones_oldest_first() {
+        let voices = [
+            playing(60, 0, false),
+            playing(62, 20, true),
+            playing(64, 10, true),
+            VoiceUse::Missing,
+        ];
+        assert_eq!(allocate(&voices, 65), Some(2));
+
+        let voices = [playing(60, 10, false), playing(62, 0, false)];
+        assert_eq!(allocate(&voices, 65), Some(1));
+        assert_eq!(allocate(&[VoiceUse::Missing; 2], 65), None);
+    }
+
+    #[test]
+    fn lowering_the_voice_count_lets_held_notes_ring_out() {
+        let mut engine = engine(4);
+        for note in [60, 62, 64, 65] {
+            engine.note_on(note, 100);
+        }
+        set_params(&engine.params, 2, false);
+        render(&mut engine);
+
+        // New notes only take over the voices below the voice count
+        engine.note_on(67, 100);
+        assert_eq!(held_note(&engine, 0), Some(67));
+        assert_eq!(held_note(&engine, 1), Some(62));
+        assert_eq!(held_note(&engine, 2), Some(64));
+        assert_eq!(held_note(&engine, 3), Some(65));
+
+        // The voices past it only give up their graphs once they're done playing
+        assert_eq!(engine.surplus_voice(), None);
+        if let Some(voice) = &mut engine.voices[2] {
+            voice.note = None;
+        }
+        assert_eq!(engine.surplus_voice(), Some(2));
+        engine.update_graph();
+        assert!(engine.voices[2].is_none());
+        assert!(engine.voices[3].is_some());
+    }
+
+    #[test]
+    fn raising_the_voice_count_builds_the_missing_voices() {
+        let mut engine = engine(2);
+        assert!(engine.voices[2].is_none());
+        assert_eq!(engine.outdated_voice(engine.target_graph_config()), None);
+        set_params(&engine.params, 4, false);
+        assert_eq!(engine.outdated_voice(engine.target_graph_config()), Some(2));
+    }
+
+    #[test]
+    fn sostenuto_releases_caught_notes_once_lifted() {
+        let mut engine = engine(2);
+        engine.note_on(60, 100);
+        set_params(&engine.params, 2, true);
+        render(&mut engine);
+
+        // Notes played while the pedal is down aren't caught
+        engine.note_on(62, 100);
+        engine.note_off(60, 64);
+        engine.note_off(62, 64);
+        assert_eq!(held_note(&engine, 0), Some(60));
+        assert_eq!(held_note(&engine, 1), None);
+
+        set_params(&engine.params, 2, false);
+        render(&mut engine);
+        assert_eq!(held_note(&engine, 0), None);
+    }
+}
@@ -0,0 +1,38 @@
+//! The plugin's identity as seen by hosts. Every field can be overridden with an environment
+//! variable at build time so forks and branded builds can be installed side by side without
+//! clobbering each other's state:
+//!
+//! - `SYNTHY_NAME`
+//! - `SYNTHY_VENDOR`
+//! - `SYNTHY_URL`
+//! - `SYNTHY_EMAIL`
+//! - `SYNTHY_VST3_CLASS_ID`, exactly 16 ASCII characters
+
+pub const NAME: &str = env_or(option_env!("SYNTHY_NAME"), "synthy");
+pub const VENDOR: &str = env_or(option_env!("SYNTHY_VENDOR"), "rust audio");
+pub const URL: &str = env_or(option_env!("SYNTHY_URL"), "https://vaporsoft.net");
+pub const EMAIL: &str = env_or(option_env!("SYNTHY_EMAIL"), "myemail@example.com");
+pub const VST3_CLASS_ID: [u8; 16] = class_id(env_or(
+    option_env!("SYNTHY_VST3_CLASS_ID"),
+    "SynthyFm5c8e1d4b",
+));
+
+const fn env_or(value: Option<&'static str>, default: &'static str) -> &'static str {
+    match value {
+        Some(value) => value,
+        None => default,
+    }
+}
+
+/// Turn a 16 character string into a class ID, failing the build for any other length
+const fn class_id(id: &str) -> [u8; 16] {
+    let bytes = id.as_bytes();
+    assert!(bytes.len() == 16, "the VST3 class ID must be 16 bytes long");
+    let mut class_id = [0u8; 16];
+    let mut i = 0;
+    while i < 16 {
+        class_id[i] = bytes[i];
+        i += 1;
+    }
+    class_id
+}
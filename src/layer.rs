@@ -0,0 +1,67 @@
+use crate::Note;
+use nih_plug::prelude::*;
+
+/// How the main patch and the layer patch share the keyboard
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum LayerMode {
+    /// Only the main patch plays
+    Single,
+    /// Both patches play every note
+    Layer,
+    /// The main patch plays below the split key and the layer patch from the split key up
+    Split,
+}
+
+impl LayerMode {
+    /// Whether the main patch and the layer patch play `note`, in that order
+    pub fn routes(self, note: Note, split_key: i32) -> [bool; 2] {
+        match self {
+            Self::Single => [true, false],
+            Self::Layer => [true, true],
+            Self::Split => {
+                let upper = note as i32 >= split_key;
+                [!upper, upper]
+            }
+        }
+    }
+
+    /// Gains of the main patch and the layer patch. A balance of -1 only leaves the main patch, 1
+    /// only the layer patch and both play at full level in the middle. Without a layer the main
+    /// patch always plays at full level, while the layer's last note rings out.
+    pub fn gains(self, balance: f32) -> [f32; 2] {
+        let layer = (1.0 + balance).min(1.0);
+        match self {
+            Self::Single => [1.0, layer],
+            Self::Layer | Self::Split => [(1.0 - balance).min(1.0), layer],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_sends_the_split_key_and_up_to_the_layer() {
+        assert_eq!(LayerMode::Split.routes(59, 60), [true, false]);
+        assert_eq!(LayerMode::Split.routes(60, 60), [false, true]);
+        assert_eq!(LayerMode::Split.routes(0, 0), [false, true]);
+        assert_eq!(LayerMode::Split.routes(127, 127), [false, true]);
+        assert_eq!(LayerMode::Single.routes(60, 60), [true, false]);
+        assert_eq!(LayerMode::Layer.routes(0, 60), [true, true]);
+    }
+
+    #[test]
+    fn balance_fades_out_one_patch_at_a_time() {
+        for mode in [LayerMode::Layer, LayerMode::Split] {
+            assert_eq!(mode.gains(-1.0), [1.0, 0.0]);
+            assert_eq!(mode.gains(-0.5), [1.0, 0.5]);
+            assert_eq!(mode.gains(0.0), [1.0, 1.0]);
+            assert_eq!(mode.gains(1.0), [0.0, 1.0]);
+        }
+        // Without a layer the main patch stays at full level
+        assert_eq!(LayerMode::Single.gains(-1.0), [1.0, 0.0]);
+        assert_eq!(LayerMode::Single.gains(0.0), [1.0, 1.0]);
+        assert_eq!(LayerMode::Single.gains(1.0), [1.0, 1.0]);
+    }
+}
@@ -1,47 +1,96 @@
 #![feature(trait_alias)]
+pub mod autosave;
+pub mod config;
+pub mod dsp;
+pub mod engine;
+pub mod identity;
+pub mod layer;
+pub mod midi;
+pub mod modulation;
+pub mod preset;
+pub mod telemetry;
+pub mod transport;
+#[cfg(feature = "egui-ui")]
 pub mod ui;
+#[cfg(feature = "egui-ui")]
 pub mod widgets;
+#[cfg(feature = "egui-ui")]
+pub mod worker;
 
-use egui::Vec2;
-use fundsp::hacker::*;
-use nih_plug::{nih_export_vst3, prelude::*, util::midi_note_to_freq};
+use fundsp::hacker32::*;
+use nih_plug::{nih_export_vst3, prelude::*};
+#[cfg(feature = "egui-ui")]
 use nih_plug_egui::EguiState;
 use num_derive::FromPrimitive;
 use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
     pin::Pin,
-    sync::{Arc, RwLock},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+    },
 };
 
-type Note = u8;
-type Velocity = u8;
+pub type Note = u8;
+pub type Velocity = u8;
 type Stage = usize;
 
+/// Number of envelopes driving the graph (op a, op b, noise and the main amp envelope)
+const ENV_COUNT: usize = 4;
+/// Default maximum envelope length in seconds
+const DEFAULT_ENV_LENGTH: f32 = 8.0;
+/// Range of the maximum envelope length in seconds
+const ENV_LENGTH_RANGE: RangeInclusive<f32> = 1.0..=60.0;
+
 struct Synthy {
-    audio: Box<dyn AudioUnit64 + Send + Sync>,
-    sample_rate: f32,
+    engine: engine::SynthyEngine,
     params: Pin<Arc<SynthyParams>>,
-    time: Duration,
-    note: Option<NoteInfo>,
-    enabled: bool,
+    /// Plays the layer patch. Its parameters aren't exposed to the host, they're restored from
+    /// the main patch's `layer` field instead.
+    layer: engine::SynthyEngine,
+    /// The layer's output for the current block, mixed into the main output afterwards
+    layer_output: dsp::Scratch,
+    #[cfg(feature = "egui-ui")]
     editor: Arc<EguiState>,
-}
-
-struct NoteInfo {
-    note: Note,
-    velocity: Velocity,
-    on: Duration,
-    stage: usize,
+    /// Whether the host connected the optional input bus
+    has_input: bool,
+    /// Notes played from the editor
+    editor_notes: Arc<midi::NoteQueue>,
+    /// Set by the editor after changing parameters itself, like when loading a preset. The next
+    /// block jumps to the new values instead of smoothing towards them like it does for changes
+    /// from the user or automation.
+    snap_params: Arc<AtomicBool>,
+    transport: transport::TransportSync,
+    /// The latency last reported to the host in samples
+    latency: u32,
+    /// Started once the plugin gets initialized, so instances hosts only create to scan the
+    /// plugin leave nothing behind
+    autosave: Option<autosave::Autosave>,
 }
 
 pub struct SynthyEditor {}
 
+/// The editor's saved state. Builds without an editor keep whatever an editor saved as is, so
+/// the state survives a round trip through them.
+#[cfg(feature = "egui-ui")]
+pub type UiState = ui::UiState;
+#[cfg(not(feature = "egui-ui"))]
+pub type UiState = serde_json::Value;
+
 #[derive(Params)]
 pub struct SynthyParams {
     #[id = "a_mod"]
     pub a_mod: FloatParam,
-    #[id = "a_ratio"]
-    pub a_ratio: FloatParam,
+    /// Whole number part of the operator's frequency ratio, see [`op_ratio`]
+    #[id = "a_ratio_coarse"]
+    pub a_ratio_coarse: IntParam,
+    #[id = "a_ratio_fine"]
+    pub a_ratio_fine: FloatParam,
+    #[id = "a_phase"]
+    pub a_phase: FloatParam,
+    #[id = "a_retrigger"]
+    pub a_retrigger: BoolParam,
     #[persist = "a_env"]
     pub a_env: RwLock<Vec<(f32, f32)>>,
     #[persist = "b_env"]
@@ -50,18 +99,329 @@ pub struct SynthyParams {
     pub noise_env: RwLock<Vec<(f32, f32)>>,
     #[persist = "env"]
     pub env: RwLock<Vec<(f32, f32)>>,
+    /// The maximum length of each envelope in seconds
+    #[persist = "a_env_length"]
+    pub a_env_length: RwLock<f32>,
+    #[persist = "b_env_length"]
+    pub b_env_length: RwLock<f32>,
+    #[persist = "noise_env_length"]
+    pub noise_env_length: RwLock<f32>,
+    #[persist = "env_length"]
+    pub env_length: RwLock<f32>,
+    /// Onset delay and hold point of each envelope
+    #[persist = "a_env_timing"]
+    pub a_env_timing: RwLock<dsp::EnvelopeTiming>,
+    #[persist = "b_env_timing"]
+    pub b_env_timing: RwLock<dsp::EnvelopeTiming>,
+    #[persist = "noise_env_timing"]
+    pub noise_env_timing: RwLock<dsp::EnvelopeTiming>,
+    #[persist = "env_timing"]
+    pub env_timing: RwLock<dsp::EnvelopeTiming>,
+    /// Simplified ADSR settings that can generate each envelope
+    #[persist = "a_env_adsr"]
+    pub a_env_adsr: RwLock<dsp::Adsr>,
+    #[persist = "b_env_adsr"]
+    pub b_env_adsr: RwLock<dsp::Adsr>,
+    #[persist = "noise_env_adsr"]
+    pub noise_env_adsr: RwLock<dsp::Adsr>,
+    #[persist = "env_adsr"]
+    pub env_adsr: RwLock<dsp::Adsr>,
+    /// Editor settings that aren't part of the sound, so the editor reopens the way it was left
+    #[persist = "ui_state"]
+    pub ui_state: RwLock<UiState>,
+    /// Free text about the patch, like how to play it. Saved with presets.
+    #[persist = "notes"]
+    pub notes: RwLock<String>,
+    /// The second patch played along with this one depending on the layer mode. It's only ever
+    /// edited as a whole, by copying or swapping it with this patch.
+    #[persist = "layer"]
+    pub layer: RwLock<preset::Preset>,
+    /// How much of each envelope is applied. The operator envelopes can be inverted.
+    #[id = "a_env_depth"]
+    pub a_env_depth: FloatParam,
+    #[id = "b_env_depth"]
+    pub b_env_depth: FloatParam,
+    #[id = "noise_env_depth"]
+    pub noise_env_depth: FloatParam,
+    /// Where each envelope is routed to
+    #[id = "a_env_dest"]
+    pub a_env_destination: EnumParam<modulation::ModDestination>,
+    #[id = "b_env_dest"]
+    pub b_env_destination: EnumParam<modulation::ModDestination>,
+    #[id = "noise_env_dest"]
+    pub noise_env_destination: EnumParam<modulation::ModDestination>,
+    #[id = "env_dest"]
+    pub env_destination: EnumParam<modulation::ModDestination>,
+    /// A response curve applied to each envelope before its depth
+    #[id = "a_env_curve"]
+    pub a_env_curve: FloatParam,
+    #[id = "b_env_curve"]
+    pub b_env_curve: FloatParam,
+    #[id = "noise_env_curve"]
+    pub noise_env_curve: FloatParam,
+    #[id = "env_curve"]
+    pub env_curve: FloatParam,
+    /// Holds each envelope at its full level, keeping its route but taking out its movement
+    #[id = "a_env_mute"]
+    pub a_env_mute: BoolParam,
+    #[id = "b_env_mute"]
+    pub b_env_mute: BoolParam,
+    #[id = "noise_env_mute"]
+    pub noise_env_mute: BoolParam,
+    #[id = "env_mute"]
+    pub env_mute: BoolParam,
     #[id = "b_mod"]
     pub b_mod: FloatParam,
+    /// Whole number part of the operator's frequency ratio, see [`op_ratio`]
+    #[id = "b_ratio_coarse"]
+    pub b_ratio_coarse: IntParam,
+    #[id = "b_ratio_fine"]
+    pub b_ratio_fine: FloatParam,
+    /// The single ratio parameters from before the ratios were split, hidden from the host. They
+    /// only exist so projects saved with them can be migrated, see
+    /// [`SynthyParams::migrate_legacy_ratios`].
+    #[id = "a_ratio"]
+    pub a_ratio_legacy: FloatParam,
     #[id = "b_ratio"]
-    pub b_ratio: FloatParam,
+    pub b_ratio_legacy: FloatParam,
+    #[id = "b_phase"]
+    pub b_phase: FloatParam,
+    #[id = "b_retrigger"]
+    pub b_retrigger: BoolParam,
     #[id = "a_b_mod"]
     pub a_mod_b: FloatParam,
+    #[id = "ring_mod"]
+    pub ring_mod: FloatParam,
+    #[id = "sync"]
+    pub sync: BoolParam,
     #[id = "noise_amp"]
     pub noise_amp: FloatParam,
+    /// Stereo width of the noise layer, the FM core always stays centered
+    #[id = "noise_width"]
+    pub noise_width: FloatParam,
+    #[id = "noise_cutoff"]
+    pub noise_cutoff: FloatParam,
+    /// How much the noise filter follows the played pitch, 1 tracks it exactly
+    #[id = "noise_key_track"]
+    pub noise_key_track: FloatParam,
+    /// Noise filter modulation by the noise envelope in octaves
+    #[id = "noise_filter_env"]
+    pub noise_filter_env: FloatParam,
+    /// Frequency modulation of the operators by the filtered noise, independent of the noise
+    /// layer's own level
+    #[id = "noise_fm_depth"]
+    pub noise_fm_depth: FloatParam,
+    #[id = "noise_fm_target"]
+    pub noise_fm_target: EnumParam<modulation::NoiseFmTarget>,
+    #[id = "pluck"]
+    pub pluck: FloatParam,
+    #[id = "pluck_damping"]
+    pub pluck_damping: FloatParam,
+    #[id = "pluck_decay"]
+    pub pluck_decay: FloatParam,
+    #[id = "ext_fm"]
+    pub ext_fm: FloatParam,
+    #[id = "ext_level"]
+    pub ext_level: FloatParam,
     #[id = "filter_freq"]
     pub filter_freq: FloatParam,
     #[id = "filter_q"]
     pub filter_q: FloatParam,
+    /// Gain into the filter's soft clipper
+    #[id = "filter_drive"]
+    pub filter_drive: FloatParam,
+    #[id = "filter2_freq"]
+    pub filter2_freq: FloatParam,
+    #[id = "filter2_q"]
+    pub filter2_q: FloatParam,
+    #[id = "filter2_mode"]
+    pub filter2_mode: EnumParam<dsp::FilterMode>,
+    /// Morphs through the vowels a, e, i, o and u in the formant mode
+    #[id = "filter2_vowel"]
+    pub filter2_vowel: FloatParam,
+    #[id = "filter_routing"]
+    pub filter_routing: EnumParam<dsp::FilterRouting>,
+    /// Whether the filters only process the voice or the external input as well
+    #[id = "filter_placement"]
+    pub filter_placement: EnumParam<dsp::FilterPlacement>,
+    /// Balance between the two filters in the parallel and split routings
+    #[id = "filter_balance"]
+    pub filter_balance: FloatParam,
+    #[id = "eq_low_freq"]
+    pub eq_low_freq: FloatParam,
+    #[id = "eq_low_q"]
+    pub eq_low_q: FloatParam,
+    #[id = "eq_low_gain"]
+    pub eq_low_gain: FloatParam,
+    #[id = "eq_mid_freq"]
+    pub eq_mid_freq: FloatParam,
+    #[id = "eq_mid_q"]
+    pub eq_mid_q: FloatParam,
+    #[id = "eq_mid_gain"]
+    pub eq_mid_gain: FloatParam,
+    #[id = "eq_high_freq"]
+    pub eq_high_freq: FloatParam,
+    #[id = "eq_high_q"]
+    pub eq_high_q: FloatParam,
+    #[id = "eq_high_gain"]
+    pub eq_high_gain: FloatParam,
+    #[id = "eq_mix"]
+    pub eq_mix: FloatParam,
+    #[id = "comp_mix"]
+    pub comp_mix: FloatParam,
+    /// Trades CPU for less aliasing, this rebuilds the graph
+    #[id = "quality"]
+    pub quality: EnumParam<dsp::Quality>,
+    /// Render at the highest quality while bouncing, regardless of the quality setting
+    #[id = "offline_quality"]
+    pub offline_quality: BoolParam,
+    /// Mutes the output with a short fade. This is the plugin's bypass as far as the host is
+    /// concerned, so it stays out of presets.
+    #[id = "bypass"]
+    pub bypass: BoolParam,
+    /// Soft bypasses every effect to compare against the dry synth
+    #[id = "fx_bypass"]
+    pub fx_bypass: BoolParam,
+    /// Caps the modulation indices and output gain so randomizing or tweaking live can't blow up
+    #[id = "safe_mode"]
+    pub safe_mode: BoolParam,
+    /// Scales all parameter modulation, to hear a patch with less, more or no movement
+    #[id = "mod_amount"]
+    pub mod_amount: FloatParam,
+    #[id = "comp_threshold"]
+    pub comp_threshold: FloatParam,
+    /// A ratio of 1 turns the compressor off
+    #[id = "comp_ratio"]
+    pub comp_ratio: FloatParam,
+    #[id = "comp_attack"]
+    pub comp_attack: FloatParam,
+    #[id = "comp_release"]
+    pub comp_release: FloatParam,
+    #[id = "comp_makeup"]
+    pub comp_makeup: FloatParam,
+    /// Delays the output so the compressor can react ahead of transients. This adds latency.
+    #[id = "comp_lookahead"]
+    pub comp_lookahead: FloatParam,
+    /// Mid/side width of the output ahead of the compressor, below 1 narrows and above 1 widens
+    #[id = "width"]
+    pub width: FloatParam,
+    /// A short delay on the right channel for cheap stereo interest on mono lines
+    #[id = "haas"]
+    pub haas: BoolParam,
+    #[id = "haas_time"]
+    pub haas_time: FloatParam,
+    /// How much of the delayed signal replaces the right channel
+    #[id = "haas_amount"]
+    pub haas_amount: FloatParam,
+    /// Per channel output trims and phase inverts, for lining the synth up with stereo effects
+    #[id = "out_trim_left"]
+    pub out_trim_left: FloatParam,
+    #[id = "out_trim_right"]
+    pub out_trim_right: FloatParam,
+    #[id = "out_invert_left"]
+    pub out_invert_left: BoolParam,
+    #[id = "out_invert_right"]
+    pub out_invert_right: BoolParam,
+    /// 0 means omni, 1-16 listen to a single MIDI channel
+    #[id = "midi_channel"]
+    pub midi_channel: IntParam,
+    #[id = "key_low"]
+    pub key_low: IntParam,
+    #[id = "key_high"]
+    pub key_high: IntParam,
+    #[id = "velocity_low"]
+    pub velocity_low: IntParam,
+    #[id = "velocity_high"]
+    pub velocity_high: IntParam,
+    /// Bends the response to velocity, so the synth can be matched to different keyboards
+    #[id = "velocity_curve"]
+    pub velocity_curve: FloatParam,
+    /// How much quieter softly played notes are, after the velocity curve
+    #[id = "velocity_amp"]
+    pub velocity_amp: FloatParam,
+    /// How much the note-off velocity stretches or shortens the release
+    #[id = "release_velocity_scale"]
+    pub release_velocity_scale: FloatParam,
+    /// How many notes can play at once. A single voice plays monophonically, following the note
+    /// priority and the legato options.
+    #[id = "max_voices"]
+    pub max_voices: IntParam,
+    #[id = "note_priority"]
+    pub note_priority: EnumParam<midi::NotePriority>,
+    #[id = "glide_time"]
+    pub glide_time: FloatParam,
+    #[id = "glide_mode"]
+    pub glide_mode: EnumParam<dsp::GlideMode>,
+    #[id = "legato_glide"]
+    pub legato_glide: BoolParam,
+    #[id = "vibrato_rate"]
+    pub vibrato_rate: FloatParam,
+    #[id = "vibrato_depth"]
+    pub vibrato_depth: FloatParam,
+    #[id = "vibrato_delay"]
+    pub vibrato_delay: FloatParam,
+    #[id = "vibrato_mod_wheel"]
+    pub vibrato_mod_wheel: FloatParam,
+    #[id = "lfo_rate"]
+    pub lfo_rate: FloatParam,
+    #[id = "lfo_shape"]
+    pub lfo_shape: EnumParam<dsp::LfoShape>,
+    /// Only used by the square shape
+    #[id = "lfo_pulse_width"]
+    pub lfo_pulse_width: FloatParam,
+    #[id = "lfo_phase"]
+    pub lfo_phase: FloatParam,
+    #[id = "lfo_unipolar"]
+    pub lfo_unipolar: BoolParam,
+    /// Runs the LFO once per note, so it can be used as an extra envelope
+    #[id = "lfo_one_shot"]
+    pub lfo_one_shot: BoolParam,
+    #[id = "lfo_fade_in"]
+    pub lfo_fade_in: FloatParam,
+    #[id = "lfo_depth"]
+    pub lfo_depth: FloatParam,
+    /// A response curve applied to the LFO before its depth
+    #[id = "lfo_curve"]
+    pub lfo_curve: FloatParam,
+    #[id = "lfo_mute"]
+    pub lfo_mute: BoolParam,
+    #[id = "lfo_dest"]
+    pub lfo_destination: EnumParam<modulation::ModDestination>,
+    #[id = "drift"]
+    pub drift: FloatParam,
+    /// Random detune and alternating start phases for every new note
+    #[id = "variation"]
+    pub variation: FloatParam,
+    /// Where drift, the random LFO shapes and variation start from. The same seed always gives
+    /// the same render.
+    #[id = "seed"]
+    pub seed: IntParam,
+    #[id = "transport_restart"]
+    pub transport_restart: EnumParam<transport::TransportRestart>,
+    /// Exposed as a parameter so the host can map the mod wheel (CC1) to it
+    #[id = "mod_wheel"]
+    pub mod_wheel: FloatParam,
+    /// Exposed as a parameter so the host can map expression (CC11) to it
+    #[id = "expression"]
+    pub expression: FloatParam,
+    /// Exposed as a parameter so the host can map the sostenuto pedal (CC66) to it
+    #[id = "sostenuto"]
+    pub sostenuto: BoolParam,
+    /// Exposed as a parameter so the host can map the soft pedal (CC67) to it
+    #[id = "soft_pedal"]
+    pub soft_pedal: BoolParam,
+    /// How much quieter and darker the soft pedal makes the voice
+    #[id = "soft_pedal_amount"]
+    pub soft_pedal_amount: FloatParam,
+    /// Whether the layer patch plays along with this one
+    #[id = "layer_mode"]
+    pub layer_mode: EnumParam<layer::LayerMode>,
+    /// The lowest note the layer patch plays in split mode
+    #[id = "split_key"]
+    pub split_key: IntParam,
+    /// Mix between this patch and the layer patch
+    #[id = "layer_balance"]
+    pub layer_balance: FloatParam,
 }
 
 impl Default for SynthyParams {
@@ -76,8 +436,58 @@ impl Default for SynthyParams {
                 },
             )
             .with_value_to_string(formatters::f32_rounded(2)),
-            a_ratio: FloatParam::new("op a ratio", 1.0, FloatRange::Linear { min: 0.0, max: 8.0 })
-                .with_value_to_string(formatters::f32_rounded(2)),
+            a_ratio_coarse: IntParam::new(
+                "op a coarse",
+                1,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_RATIO as i32,
+                },
+            ),
+            a_ratio_fine: FloatParam::new(
+                "op a fine",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_FINE_RATIO,
+                    max: MAX_FINE_RATIO,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            a_phase: FloatParam::new(
+                "op a phase",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 360.0,
+                },
+            )
+            .with_unit("°")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            a_retrigger: BoolParam::new("op a retrigger", true),
+            a_env_depth: env_depth_param("op a env depth", -1.0),
+            b_env_depth: env_depth_param("op b env depth", -1.0),
+            noise_env_depth: env_depth_param("noise env depth", 0.0),
+            a_env_destination: EnumParam::new(
+                "op a env destination",
+                modulation::ModDestination::OpALevel,
+            ),
+            b_env_destination: EnumParam::new(
+                "op b env destination",
+                modulation::ModDestination::OpBLevel,
+            ),
+            noise_env_destination: EnumParam::new(
+                "noise env destination",
+                modulation::ModDestination::NoiseLevel,
+            ),
+            env_destination: EnumParam::new("env destination", modulation::ModDestination::Amp),
+            a_env_curve: route_curve_param("op a env curve"),
+            b_env_curve: route_curve_param("op b env curve"),
+            noise_env_curve: route_curve_param("noise env curve"),
+            env_curve: route_curve_param("env curve"),
+            a_env_mute: BoolParam::new("op a env mute", false),
+            b_env_mute: BoolParam::new("op b env mute", false),
+            noise_env_mute: BoolParam::new("noise env mute", false),
+            env_mute: BoolParam::new("env mute", false),
             b_mod: FloatParam::new(
                 "op b mod",
                 0.5,
@@ -87,8 +497,36 @@ impl Default for SynthyParams {
                 },
             )
             .with_value_to_string(formatters::f32_rounded(2)),
-            b_ratio: FloatParam::new("op b ratio", 2.0, FloatRange::Linear { min: 0.0, max: 8.0 })
-                .with_value_to_string(formatters::f32_rounded(2)),
+            b_ratio_coarse: IntParam::new(
+                "op b coarse",
+                2,
+                IntRange::Linear {
+                    min: 0,
+                    max: MAX_RATIO as i32,
+                },
+            ),
+            b_ratio_fine: FloatParam::new(
+                "op b fine",
+                0.0,
+                FloatRange::Linear {
+                    min: -MAX_FINE_RATIO,
+                    max: MAX_FINE_RATIO,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            a_ratio_legacy: legacy_ratio_param("op a ratio", LEGACY_RATIO_DEFAULTS[0]),
+            b_ratio_legacy: legacy_ratio_param("op b ratio", LEGACY_RATIO_DEFAULTS[1]),
+            b_phase: FloatParam::new(
+                "op b phase",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 360.0,
+                },
+            )
+            .with_unit("°")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            b_retrigger: BoolParam::new("op b retrigger", true),
             a_mod_b: FloatParam::new(
                 "op ab mod",
                 0.0,
@@ -98,8 +536,79 @@ impl Default for SynthyParams {
                 },
             )
             .with_value_to_string(formatters::f32_rounded(2)),
+            ring_mod: FloatParam::new("ring mod", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            sync: BoolParam::new("op b sync", false),
             noise_amp: FloatParam::new("noise amp", 0.0, FloatRange::Linear { min: 0.0, max: 0.5 })
                 .with_value_to_string(formatters::f32_rounded(2)),
+            noise_width: FloatParam::new(
+                "noise width",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            noise_cutoff: FloatParam::new(
+                "noise cutoff",
+                2000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            noise_key_track: FloatParam::new(
+                "noise key track",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            noise_filter_env: FloatParam::new(
+                "noise filter env",
+                0.0,
+                FloatRange::Linear {
+                    min: -4.0,
+                    max: 4.0,
+                },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            noise_fm_depth: FloatParam::new(
+                "noise fm depth",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            noise_fm_target: EnumParam::new("noise fm target", modulation::NoiseFmTarget::OpA),
+            pluck: FloatParam::new("pluck", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            pluck_damping: FloatParam::new(
+                "pluck damping",
+                0.3,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            pluck_decay: FloatParam::new(
+                "pluck decay",
+                0.98,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 0.999,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(3)),
+            ext_fm: FloatParam::new(
+                "ext fm",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 10.0,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            ext_level: FloatParam::new("ext level", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
             filter_freq: FloatParam::new(
                 "cutoff",
                 25_000.0,
@@ -111,93 +620,889 @@ impl Default for SynthyParams {
             .with_value_to_string(formatters::f32_rounded(2)),
             filter_q: FloatParam::new("resonance", 0.2, FloatRange::Linear { min: 0.0, max: 1.0 })
                 .with_value_to_string(formatters::f32_rounded(2)),
-            a_env: RwLock::new(vec![
-                (0f32, 0f32),
-                (0.5f32, 1.0f32),
-                (1.0f32, 0.7f32),
-                (2.0f32, 0.5f32),
-                (3.0f32, 0.0f32),
-            ]),
-            b_env: RwLock::new(vec![
-                (0f32, 0f32),
-                (0.5f32, 1.0f32),
-                (1.0f32, 0.7f32),
-                (2.0f32, 0.5f32),
-                (3.0f32, 0.0f32),
-            ]),
-            noise_env: RwLock::new(vec![
-                (0f32, 0f32),
-                (0.5f32, 1.0f32),
-                (1.0f32, 0.7f32),
-                (2.0f32, 0.5f32),
-                (3.0f32, 0.0f32),
-            ]),
-            env: RwLock::new(vec![
-                (0f32, 0f32),
-                (0.5f32, 1.0f32),
-                (1.0f32, 0.7f32),
-                (2.0f32, 0.5f32),
-                (3.0f32, 0.0f32),
-            ]),
+            filter_drive: FloatParam::new(
+                "drive",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            filter2_freq: FloatParam::new(
+                "cutoff 2",
+                1000.0,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 20_000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            filter2_q: FloatParam::new(
+                "resonance 2",
+                0.2,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            filter2_mode: EnumParam::new("filter 2 mode", dsp::FilterMode::Highpass),
+            filter2_vowel: FloatParam::new("vowel", 0.0, FloatRange::Linear { min: 0.0, max: 4.0 })
+                .with_value_to_string(Arc::new(|value| {
+                    let vowels = ["a", "e", "i", "o", "u"];
+                    vowels[(value.round() as usize).min(vowels.len() - 1)].to_string()
+                })),
+            filter_routing: EnumParam::new("filter routing", dsp::FilterRouting::Single),
+            filter_placement: EnumParam::new("filter placement", dsp::FilterPlacement::Bus),
+            filter_balance: FloatParam::new(
+                "filter balance",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            eq_low_freq: eq_freq_param("eq low freq", 100.0),
+            eq_low_q: eq_q_param("eq low q"),
+            eq_low_gain: eq_gain_param("eq low gain"),
+            eq_mid_freq: eq_freq_param("eq mid freq", 1000.0),
+            eq_mid_q: eq_q_param("eq mid q"),
+            eq_mid_gain: eq_gain_param("eq mid gain"),
+            eq_high_freq: eq_freq_param("eq high freq", 8000.0),
+            eq_high_q: eq_q_param("eq high q"),
+            eq_high_gain: eq_gain_param("eq high gain"),
+            comp_lookahead: FloatParam::new(
+                "comp lookahead",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: dsp::compressor::MAX_LOOKAHEAD * 1000.0,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            eq_mix: FloatParam::new("eq mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            comp_mix: FloatParam::new("comp mix", 1.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            quality: EnumParam::new("quality", dsp::Quality::default()),
+            offline_quality: BoolParam::new("high quality bounce", true),
+            bypass: BoolParam::new("bypass", false).make_bypass(),
+            fx_bypass: BoolParam::new("fx bypass", false),
+            mod_amount: FloatParam::new(
+                "mod amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(Arc::new(|value| format!("{:.0}", value * 100.0))),
+            safe_mode: BoolParam::new("safe mode", false),
+            comp_threshold: FloatParam::new(
+                "comp threshold",
+                -12.0,
+                FloatRange::Linear {
+                    min: -48.0,
+                    max: 0.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            comp_ratio: FloatParam::new(
+                "comp ratio",
+                1.0,
+                FloatRange::Linear {
+                    min: 1.0,
+                    max: 20.0,
+                },
+            )
+            .with_unit(":1")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            comp_attack: FloatParam::new(
+                "comp attack",
+                10.0,
+                FloatRange::Linear {
+                    min: 0.1,
+                    max: 200.0,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            comp_release: FloatParam::new(
+                "comp release",
+                150.0,
+                FloatRange::Linear {
+                    min: 5.0,
+                    max: 2000.0,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            comp_makeup: FloatParam::new(
+                "comp makeup",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            width: FloatParam::new("width", 1.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            haas: BoolParam::new("haas", false),
+            haas_time: FloatParam::new(
+                "haas time",
+                12.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: dsp::haas::MAX_DELAY * 1000.0,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::f32_rounded(1)),
+            haas_amount: FloatParam::new(
+                "haas amount",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            out_trim_left: trim_param("out trim left"),
+            out_trim_right: trim_param("out trim right"),
+            out_invert_left: BoolParam::new("out invert left", false),
+            out_invert_right: BoolParam::new("out invert right", false),
+            midi_channel: IntParam::new("midi channel", 0, IntRange::Linear { min: 0, max: 16 })
+                .with_value_to_string(Arc::new(|value| match value {
+                    0 => String::from("omni"),
+                    channel => channel.to_string(),
+                })),
+            key_low: IntParam::new("key low", 0, IntRange::Linear { min: 0, max: 127 }),
+            key_high: IntParam::new("key high", 127, IntRange::Linear { min: 0, max: 127 }),
+            velocity_low: IntParam::new("velocity low", 0, IntRange::Linear { min: 0, max: 127 }),
+            velocity_high: IntParam::new(
+                "velocity high",
+                127,
+                IntRange::Linear { min: 0, max: 127 },
+            ),
+            velocity_curve: FloatParam::new(
+                "velocity curve",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| match value {
+                value if value < -0.005 => format!("soft {:.0}", -value * 100.0),
+                value if value > 0.005 => format!("hard {:.0}", value * 100.0),
+                _ => String::from("linear"),
+            })),
+            velocity_amp: FloatParam::new(
+                "velocity to amp",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(Arc::new(|value| format!("{:.0}", value * 100.0))),
+            release_velocity_scale: FloatParam::new(
+                "release velocity",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(Arc::new(|value| format!("{:.0}", value * 100.0))),
+            max_voices: IntParam::new(
+                "voices",
+                1,
+                IntRange::Linear {
+                    min: 1,
+                    max: engine::MAX_VOICES as i32,
+                },
+            ),
+            note_priority: EnumParam::new("note priority", midi::NotePriority::Last),
+            glide_time: FloatParam::new("glide", 0.0, FloatRange::Linear { min: 0.0, max: 2.0 })
+                .with_unit(" s")
+                .with_value_to_string(formatters::f32_rounded(2)),
+            glide_mode: EnumParam::new("glide mode", dsp::GlideMode::ConstantTime),
+            legato_glide: BoolParam::new("legato glide", false),
+            vibrato_rate: FloatParam::new(
+                "vibrato rate",
+                5.0,
+                FloatRange::Linear {
+                    min: 0.1,
+                    max: 12.0,
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            vibrato_depth: FloatParam::new(
+                "vibrato depth",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit(" st")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            vibrato_delay: FloatParam::new(
+                "vibrato delay",
+                0.0,
+                FloatRange::Linear { min: 0.0, max: 4.0 },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            vibrato_mod_wheel: FloatParam::new(
+                "vibrato mod wheel",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit(" st")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            lfo_rate: FloatParam::new(
+                "lfo rate",
+                2.0,
+                FloatRange::Linear {
+                    min: 0.01,
+                    max: 20.0,
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            lfo_shape: EnumParam::new("lfo shape", dsp::LfoShape::Sine),
+            lfo_pulse_width: FloatParam::new(
+                "lfo pulse width",
+                0.5,
+                FloatRange::Linear {
+                    min: 0.05,
+                    max: 0.95,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            lfo_phase: FloatParam::new(
+                "lfo phase",
+                0.0,
+                FloatRange::Linear {
+                    min: 0.0,
+                    max: 360.0,
+                },
+            )
+            .with_unit("°")
+            .with_value_to_string(formatters::f32_rounded(0)),
+            lfo_unipolar: BoolParam::new("lfo unipolar", false),
+            lfo_one_shot: BoolParam::new("lfo one shot", false),
+            lfo_fade_in: FloatParam::new(
+                "lfo fade in",
+                0.0,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 10.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" s")
+            .with_value_to_string(formatters::f32_rounded(2)),
+            lfo_depth: FloatParam::new(
+                "lfo depth",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            lfo_curve: route_curve_param("lfo curve"),
+            lfo_mute: BoolParam::new("lfo mute", false),
+            lfo_destination: EnumParam::new(
+                "lfo destination",
+                modulation::ModDestination::OpARatio,
+            ),
+            drift: FloatParam::new("drift", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            variation: FloatParam::new("variation", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            seed: IntParam::new(
+                "seed",
+                dsp::rng::DEFAULT_SEED as i32,
+                IntRange::Linear { min: 1, max: 9999 },
+            ),
+            transport_restart: EnumParam::new(
+                "transport restart",
+                transport::TransportRestart::Off,
+            ),
+            mod_wheel: FloatParam::new("mod wheel", 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(formatters::f32_rounded(2)),
+            expression: FloatParam::new(
+                "expression",
+                1.0,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            sostenuto: BoolParam::new("sostenuto", false),
+            soft_pedal: BoolParam::new("soft pedal", false),
+            soft_pedal_amount: FloatParam::new(
+                "soft pedal amount",
+                0.5,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(Arc::new(|value| format!("{:.0}", value * 100.0))),
+            layer_mode: EnumParam::new("layer mode", layer::LayerMode::Single),
+            split_key: IntParam::new("split key", 60, IntRange::Linear { min: 0, max: 127 }),
+            layer_balance: FloatParam::new(
+                "layer balance",
+                0.0,
+                FloatRange::Linear {
+                    min: -1.0,
+                    max: 1.0,
+                },
+            )
+            .with_value_to_string(formatters::f32_rounded(2)),
+            a_env: RwLock::new(dsp::EnvelopeTemplate::Default.points()),
+            b_env: RwLock::new(dsp::EnvelopeTemplate::Default.points()),
+            noise_env: RwLock::new(dsp::EnvelopeTemplate::Default.points()),
+            env: RwLock::new(dsp::EnvelopeTemplate::Default.points()),
+            a_env_length: RwLock::new(DEFAULT_ENV_LENGTH),
+            b_env_length: RwLock::new(DEFAULT_ENV_LENGTH),
+            noise_env_length: RwLock::new(DEFAULT_ENV_LENGTH),
+            env_length: RwLock::new(DEFAULT_ENV_LENGTH),
+            a_env_timing: RwLock::default(),
+            b_env_timing: RwLock::default(),
+            noise_env_timing: RwLock::default(),
+            env_timing: RwLock::default(),
+            a_env_adsr: RwLock::default(),
+            b_env_adsr: RwLock::default(),
+            noise_env_adsr: RwLock::default(),
+            env_adsr: RwLock::default(),
+            ui_state: RwLock::default(),
+            notes: RwLock::default(),
+            layer: RwLock::default(),
+        }
+    }
+}
+
+fn eq_freq_param(name: &'static str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Skewed {
+            min: 20.0,
+            max: 20_000.0,
+            factor: FloatRange::skew_factor(-2.0),
+        },
+    )
+    .with_unit(" Hz")
+    .with_value_to_string(formatters::f32_rounded(0))
+}
+
+fn eq_q_param(name: &'static str) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.707,
+        FloatRange::Linear {
+            min: 0.1,
+            max: 10.0,
+        },
+    )
+    .with_value_to_string(formatters::f32_rounded(2))
+}
+
+fn eq_gain_param(name: &'static str) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.0,
+        FloatRange::Linear {
+            min: -18.0,
+            max: 18.0,
+        },
+    )
+    .with_unit(" dB")
+    .with_value_to_string(formatters::f32_rounded(1))
+}
+
+fn trim_param(name: &'static str) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.0,
+        FloatRange::Linear {
+            min: -24.0,
+            max: 12.0,
+        },
+    )
+    .with_unit(" dB")
+    .with_value_to_string(formatters::f32_rounded(1))
+}
+
+/// The highest coarse operator ratio
+pub const MAX_RATIO: f32 = 8.0;
+/// How far the fine ratio reaches in either direction
+const MAX_FINE_RATIO: f32 = 0.99;
+
+/// An operator's frequency ratio from its coarse and fine parameters
+pub fn op_ratio(coarse: &IntParam, fine: &FloatParam) -> f32 {
+    (coarse.value as f32 + fine.value).max(0.0)
+}
+
+/// The defaults of the single ratio parameters, which match the defaults of the coarse and fine
+/// parameters that replaced them
+const LEGACY_RATIO_DEFAULTS: [f32; 2] = [1.0, 2.0];
+
+/// One of the hidden single ratio parameters, which went linearly from 0 to the maximum ratio
+fn legacy_ratio_param(name: &'static str, default: f32) -> FloatParam {
+    FloatParam::new(
+        name,
+        default,
+        FloatRange::Linear {
+            min: 0.0,
+            max: MAX_RATIO,
+        },
+    )
+    .hide()
+}
+
+/// Split a single ratio into coarse and fine values, used to migrate presets from before the
+/// split
+pub fn split_ratio(ratio: f32) -> (i32, f32) {
+    let coarse = ratio.round().clamp(0.0, MAX_RATIO);
+    (
+        coarse as i32,
+        (ratio - coarse).clamp(-MAX_FINE_RATIO, MAX_FINE_RATIO),
+    )
+}
+
+/// Everything belonging to one envelope
+pub struct EnvelopeParams<'a> {
+    /// Shown in the editor and used to identify its widget state
+    pub name: &'static str,
+    pub points: &'a RwLock<Vec<(f32, f32)>>,
+    pub length: &'a RwLock<f32>,
+    pub timing: &'a RwLock<dsp::EnvelopeTiming>,
+    pub adsr: &'a RwLock<dsp::Adsr>,
+    /// The main envelope always applies at full depth
+    pub depth: Option<&'a FloatParam>,
+    pub destination: &'a EnumParam<modulation::ModDestination>,
+    pub curve: &'a FloatParam,
+    pub mute: &'a BoolParam,
+    /// The amp and noise envelopes have to start and end silent, the operator envelopes don't
+    pub locks: dsp::EndpointLocks,
+}
+
+impl<'a> EnvelopeParams<'a> {
+    /// The envelope as it gets evaluated, from its points and timing read out of their locks
+    pub fn shape<'p>(
+        &self,
+        points: &'p [(f32, f32)],
+        timing: dsp::EnvelopeTiming,
+    ) -> dsp::EnvelopeShape<'p> {
+        dsp::EnvelopeShape {
+            points,
+            max_length: self.max_length(),
+            timing,
+        }
+    }
+
+    /// The longest the envelope can run in seconds
+    pub fn max_length(&self) -> f32 {
+        env_length(self.length)
+    }
+}
+
+/// Index of the main envelope in [`SynthyParams::envelopes`], which decides when a note ends
+const MAIN_ENV: usize = ENV_COUNT - 1;
+
+/// The graph tags driven by modulators routed to [`modulation::Routing::Level`]
+const LEVEL_TAGS: [Tag; modulation::LEVEL_COUNT] =
+    [Tag::OpAEnv, Tag::OpBEnv, Tag::NoiseEnv, Tag::Env];
+
+/// Read an envelope's maximum length, falling back to the default if the lock is unavailable
+fn env_length(length: &RwLock<f32>) -> f32 {
+    length
+        .read()
+        .map(|length| *length)
+        .unwrap_or(DEFAULT_ENV_LENGTH)
+}
+
+/// An envelope depth shown as a percentage, starting at full depth
+fn env_depth_param(name: &'static str, min: f32) -> FloatParam {
+    FloatParam::new(name, 1.0, FloatRange::Linear { min, max: 1.0 })
+        .with_unit("%")
+        .with_value_to_string(Arc::new(|value| format!("{:.0}", value * 100.0)))
+}
+
+/// A modulation route's response curve, from soft through linear to exponential
+fn route_curve_param(name: &'static str) -> FloatParam {
+    FloatParam::new(
+        name,
+        0.0,
+        FloatRange::Linear {
+            min: -1.0,
+            max: 1.0,
+        },
+    )
+    .with_value_to_string(formatters::f32_rounded(2))
+}
+
+impl SynthyParams {
+    pub fn compressor_settings(&self, lookahead: usize) -> dsp::CompressorSettings {
+        dsp::CompressorSettings {
+            threshold: self.comp_threshold.value,
+            ratio: self.comp_ratio.value,
+            attack: self.comp_attack.value / 1000.0,
+            release: self.comp_release.value / 1000.0,
+            makeup: match self.safe_mode.value {
+                true => self.comp_makeup.value.min(dsp::safety::MAX_MAKEUP),
+                false => self.comp_makeup.value,
+            },
+            mix: self.comp_mix.value * self.fx_amount(),
+            lookahead,
+        }
+    }
+
+    pub fn lfo_settings(&self) -> dsp::LfoSettings {
+        dsp::LfoSettings {
+            rate: self.lfo_rate.value,
+            shape: self.lfo_shape.value(),
+            pulse_width: self.lfo_pulse_width.value,
+            phase_offset: self.lfo_phase.value / 360.0,
+            one_shot: self.lfo_one_shot.value,
+            fade_in: self.lfo_fade_in.value,
+        }
+    }
+
+    pub fn haas_settings(&self) -> dsp::HaasSettings {
+        dsp::HaasSettings {
+            enabled: self.haas.value,
+            time: self.haas_time.value / 1000.0,
+            amount: self.haas_amount.value,
+        }
+    }
+
+    /// The gain applied to each output channel from its trim and phase invert, safe mode only
+    /// lets the trims turn the output down
+    pub fn output_gains(&self) -> [f32; 2] {
+        [
+            (&self.out_trim_left, &self.out_invert_left),
+            (&self.out_trim_right, &self.out_invert_right),
+        ]
+        .map(|(trim, invert)| {
+            let trim = match self.safe_mode.value {
+                true => trim.value.min(dsp::safety::MAX_TRIM),
+                false => trim.value,
+            };
+            let gain = nih_plug::util::db_to_gain(trim);
+            match invert.value {
+                true => -gain,
+                false => gain,
+            }
+        })
+    }
+
+    /// The gains of the EQ's low, mid and high bands in decibels, capped in safe mode
+    pub fn eq_gains(&self) -> [f32; 3] {
+        [&self.eq_low_gain, &self.eq_mid_gain, &self.eq_high_gain].map(|gain| {
+            match self.safe_mode.value {
+                true => gain.value.min(dsp::safety::MAX_EQ_GAIN),
+                false => gain.value,
+            }
+        })
+    }
+
+    /// Scales every effect's mix, this is 0 when the effects are bypassed
+    pub fn fx_amount(&self) -> f32 {
+        match self.fx_bypass.value {
+            true => 0f32,
+            false => 1f32,
+        }
+    }
+
+    /// The pool of envelopes, in the same order as their levels in [`engine::SynthyEngine`]
+    pub fn envelopes(&self) -> [EnvelopeParams<'_>; ENV_COUNT] {
+        [
+            EnvelopeParams {
+                name: "op a envelope",
+                points: &self.a_env,
+                length: &self.a_env_length,
+                timing: &self.a_env_timing,
+                adsr: &self.a_env_adsr,
+                depth: Some(&self.a_env_depth),
+                destination: &self.a_env_destination,
+                curve: &self.a_env_curve,
+                mute: &self.a_env_mute,
+                locks: dsp::EndpointLocks {
+                    start: false,
+                    end: false,
+                },
+            },
+            EnvelopeParams {
+                name: "op b envelope",
+                points: &self.b_env,
+                length: &self.b_env_length,
+                timing: &self.b_env_timing,
+                adsr: &self.b_env_adsr,
+                depth: Some(&self.b_env_depth),
+                destination: &self.b_env_destination,
+                curve: &self.b_env_curve,
+                mute: &self.b_env_mute,
+                locks: dsp::EndpointLocks {
+                    start: false,
+                    end: false,
+                },
+            },
+            EnvelopeParams {
+                name: "noise envelope",
+                points: &self.noise_env,
+                length: &self.noise_env_length,
+                timing: &self.noise_env_timing,
+                adsr: &self.noise_env_adsr,
+                depth: Some(&self.noise_env_depth),
+                destination: &self.noise_env_destination,
+                curve: &self.noise_env_curve,
+                mute: &self.noise_env_mute,
+                locks: dsp::EndpointLocks {
+                    start: true,
+                    end: true,
+                },
+            },
+            EnvelopeParams {
+                name: "envelope",
+                points: &self.env,
+                length: &self.env_length,
+                timing: &self.env_timing,
+                adsr: &self.env_adsr,
+                depth: None,
+                destination: &self.env_destination,
+                curve: &self.env_curve,
+                mute: &self.env_mute,
+                locks: dsp::EndpointLocks {
+                    start: true,
+                    end: true,
+                },
+            },
+        ]
+    }
+
+    pub fn a_ratio(&self) -> f32 {
+        op_ratio(&self.a_ratio_coarse, &self.a_ratio_fine)
+    }
+
+    pub fn b_ratio(&self) -> f32 {
+        op_ratio(&self.b_ratio_coarse, &self.b_ratio_fine)
+    }
+
+    /// Use the user's defaults for the settings that aren't parameters
+    pub fn apply_config(&self, config: &config::Config) {
+        let length = config
+            .env_length
+            .clamp(*ENV_LENGTH_RANGE.start(), *ENV_LENGTH_RANGE.end());
+        for env_length in [
+            &self.a_env_length,
+            &self.b_env_length,
+            &self.noise_env_length,
+            &self.env_length,
+        ] {
+            if let Ok(mut env_length) = env_length.write() {
+                *env_length = length;
+            }
+        }
+    }
+
+    /// Move the ratios of projects saved before the ratios were split over to the coarse and fine
+    /// parameters. The host restores those ratios into the hidden legacy parameters, which are
+    /// reset afterwards so this only happens once. Presets are migrated when they're applied.
+    pub fn migrate_legacy_ratios(&self) {
+        let ptrs: HashMap<&str, ParamPtr> = self.param_map().into_iter().collect();
+        let params = [
+            (
+                &self.a_ratio_legacy,
+                &self.a_ratio_coarse,
+                &self.a_ratio_fine,
+            ),
+            (
+                &self.b_ratio_legacy,
+                &self.b_ratio_coarse,
+                &self.b_ratio_fine,
+            ),
+        ];
+        for (((legacy_id, coarse_id, fine_id), (legacy, coarse, fine)), default) in
+            preset::LEGACY_RATIOS
+                .into_iter()
+                .zip(params)
+                .zip(LEGACY_RATIO_DEFAULTS)
+        {
+            if legacy.value == default {
+                continue;
+            }
+            let (coarse_value, fine_value) = split_ratio(legacy.value);
+            for (id, normalized) in [
+                (coarse_id, coarse.preview_normalized(coarse_value)),
+                (fine_id, fine.preview_normalized(fine_value)),
+                (legacy_id, legacy.preview_normalized(default)),
+            ] {
+                if let Some(ptr) = ptrs.get(id) {
+                    unsafe { ptr.set_normalized_value(normalized) };
+                }
+            }
+        }
+    }
+
+    /// Whether a NoteOn passes the MIDI channel, key range and velocity range filters
+    pub fn accepts_note(&self, channel: u8, note: Note, velocity: Velocity) -> bool {
+        let midi_channel = self.midi_channel.value;
+        (midi_channel == 0 || midi_channel == channel as i32 + 1)
+            && (self.key_low.value..=self.key_high.value).contains(&(note as i32))
+            && (self.velocity_low.value..=self.velocity_high.value).contains(&(velocity as i32))
+    }
+
+    /// The structural configuration of the graph described by the current parameters
+    pub fn graph_config(&self) -> dsp::GraphConfig {
+        dsp::GraphConfig {
+            filter_routing: self.filter_routing.value(),
+            filter_placement: self.filter_placement.value(),
+            filter2_mode: self.filter2_mode.value(),
+            oversampling: self.quality.value().oversampling(),
+            shared_filters: self.quality.value().shared_filters(),
+            bus: false,
         }
     }
 }
 
 impl Default for Synthy {
-    #[allow(clippy::precedence)]
     fn default() -> Self {
-        let params = Arc::pin(SynthyParams::default());
-
-        let freq_tag = || tag(Tag::Freq as i64, 0.);
-        let cutoff_tag = || tag(Tag::FilterFreq as i64, 0.);
-        let q_tag = || tag(Tag::FilterQ as i64, 0.);
-        let wet_tag = || tag(Tag::Wet as i64, 0.);
-        let time_tag = || tag(Tag::Time as i64, 0.);
-        let noise_amp_tag = || tag(Tag::NoiseAmp as i64, 0.);
-        let a_ratio_tag = || tag(Tag::OpARatio as i64, 0.);
-        let b_ratio_tag = || tag(Tag::OpBRatio as i64, 0.);
-        let a_mod_tag = || tag(Tag::OpAMod as i64, 0.);
-        let a_env_tag = || tag(Tag::OpAEnv as i64, 0.);
-        let b_env_tag = || tag(Tag::OpBEnv as i64, 0.);
-        let noise_env_tag = || tag(Tag::NoiseEnv as i64, 0.);
-        let env_tag = || tag(Tag::Env as i64, 0.) >> !declick();
-        let b_mod_tag = || tag(Tag::OpBMod as i64, 0.);
-        let a_b_mod_tag = || tag(Tag::OpAModB as i64, 0.);
-
-        let op = |ratio, modulation, envelope| {
-            freq_tag() * ratio >> envelope * sine() * freq_tag() * modulation + freq_tag()
-        };
-
-        // Operators
-        let a = || op(a_ratio_tag(), a_mod_tag(), a_env_tag());
-        let b = || op(b_ratio_tag(), b_mod_tag(), b_env_tag());
-        let n = || noise() >> bandpass_hz(2000., 0.75) * noise_amp_tag() * noise_env_tag();
-        // let ab = || a() >> b();
-
-        let gen = ((a() & b()) >> (sine() * env_tag())) & n();
-        let mix = // = (saw_hz(500.) ^ cutoff_tag() ^ q_tag()) >> lowpass();
-         gen >> declick() >> split::<U2>();
-        // >> reverb_stereo(wet(), time());
+        let params = SynthyParams::default();
+        // Only new instances get the user's defaults, a restored project brings its own and the
+        // params' own defaults stay the same everywhere
+        params.apply_config(&config::Config::current());
+        let params = Arc::pin(params);
+        let layer_params = Arc::pin(SynthyParams::default());
 
         Self {
-            audio: Box::new(mix) as Box<dyn AudioUnit64 + Send + Sync>,
-            sample_rate: Default::default(),
-            time: Duration::default(),
-            note: None,
-            enabled: false,
+            engine: engine::SynthyEngine::new(params.clone()),
+            layer: engine::SynthyEngine::layer(layer_params, params.clone()),
+            layer_output: dsp::Scratch::default(),
             params,
+            #[cfg(feature = "egui-ui")]
             editor: EguiState::from_size(600, 600),
+            has_input: false,
+            editor_notes: Arc::new(midi::NoteQueue::default()),
+            snap_params: Arc::new(AtomicBool::new(false)),
+            transport: transport::TransportSync::default(),
+            latency: 0,
+            autosave: None,
+        }
+    }
+}
+
+impl Synthy {
+    /// Start a note on the patches the layer mode routes it to
+    fn note_on(&mut self, note: Note, velocity: Velocity) {
+        let [main, layer] = self
+            .params
+            .layer_mode
+            .value()
+            .routes(note, self.params.split_key.value);
+        if main {
+            self.engine.note_on(note, velocity);
+        }
+        if layer {
+            self.layer.note_on(note, velocity);
+        }
+    }
+
+    /// Release a note on both patches, so changing the layer mode or the split key while playing
+    /// can't leave notes hanging
+    fn note_off(&mut self, note: Note, velocity: Velocity) {
+        self.engine.note_off(note, velocity);
+        self.layer.note_off(note, velocity);
+    }
+
+    /// Render the layer patch for the current block into `layer_output`, using the same input as
+    /// the main patch. Returns false when the layer is off and has nothing left to play.
+    fn render_layer(&mut self, left: &[f32], right: &[f32]) -> bool {
+        let single = self.params.layer_mode.value() == layer::LayerMode::Single;
+        if single && !self.layer.is_sounding() {
+            return false;
+        }
+        let [layer_left, layer_right] = self.layer_output.channels(left.len());
+        match self.has_input {
+            true => {
+                layer_left.copy_from_slice(left);
+                layer_right.copy_from_slice(right);
+                self.layer.process([&mut *layer_left, &mut *layer_right]);
+            }
+            false => self.layer.render([layer_left, layer_right]),
+        }
+        true
+    }
+
+    /// Play or release a note from the host, going through the note filters
+    fn handle_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::NoteOn {
+                channel,
+                note,
+                velocity,
+                ..
+            } => {
+                let accepted = self.params.accepts_note(channel, note, velocity);
+                self.engine.telemetry().midi_log.push(telemetry::MidiEvent {
+                    on: true,
+                    channel,
+                    note,
+                    velocity,
+                    accepted,
+                });
+                if accepted {
+                    self.note_on(note, velocity)
+                }
+            }
+            // Note offs are never filtered so changing the filters while playing can't leave
+            // notes hanging. Notes that were never accepted are ignored anyway.
+            NoteEvent::NoteOff {
+                channel,
+                note,
+                velocity,
+                ..
+            } => {
+                self.engine.telemetry().midi_log.push(telemetry::MidiEvent {
+                    on: false,
+                    channel,
+                    note,
+                    velocity,
+                    accepted: true,
+                });
+                self.note_off(note, velocity)
+            }
+        }
+    }
+
+    /// Render both patches into `left` and `right` in place, the input bus shares its buffers
+    /// with the output
+    fn render_span(&mut self, left: &mut [f32], right: &mut [f32]) {
+        // Rendered first since the main engine overwrites the input. The layer gets mixed in
+        // before the main engine's output stage, so both share the compressor.
+        let layered = self.render_layer(left, right);
+        match (layered, self.has_input) {
+            (true, has_input) => {
+                let gains = self
+                    .params
+                    .layer_mode
+                    .value()
+                    .gains(self.params.layer_balance.value);
+                let layer = [
+                    &self.layer_output.left[..left.len()],
+                    &self.layer_output.right[..left.len()],
+                ];
+                self.engine
+                    .render_layered([&mut *left, &mut *right], has_input, layer, gains);
+            }
+            (false, true) => self.engine.process([&mut *left, &mut *right]),
+            (false, false) => self.engine.render([&mut *left, &mut *right]),
         }
     }
 }
 
 impl Plugin for Synthy {
-    const NAME: &'static str = "synthy";
-    const VENDOR: &'static str = "rust audio";
-    const URL: &'static str = "https://vaporsoft.net";
-    const EMAIL: &'static str = "myemail@example.com";
+    const NAME: &'static str = identity::NAME;
+    const VENDOR: &'static str = identity::VENDOR;
+    const URL: &'static str = identity::URL;
+    const EMAIL: &'static str = identity::EMAIL;
     const VERSION: &'static str = "0.0.1";
-    const DEFAULT_NUM_INPUTS: u32 = 0;
+    const DEFAULT_NUM_INPUTS: u32 = 2;
     const DEFAULT_NUM_OUTPUTS: u32 = 2;
     const ACCEPTS_MIDI: bool = true;
 
@@ -205,186 +1510,66 @@ impl Plugin for Synthy {
         self.params.as_ref()
     }
 
+    fn accepts_bus_config(&self, config: &BusConfig) -> bool {
+        // The input bus is optional, the synth works just as well without it
+        config.num_output_channels == 2
+            && (config.num_input_channels == 0 || config.num_input_channels == 2)
+    }
+
     fn process(&mut self, buffer: &mut Buffer, context: &mut impl ProcessContext) -> ProcessStatus {
-        for (_offset, mut block) in buffer.iter_blocks(MAX_BUFFER_SIZE) {
-            self.audio
-                .set(Tag::OpAMod as i64, self.params.a_mod.value as f64);
-            self.audio
-                .set(Tag::OpBMod as i64, self.params.b_mod.value as f64);
-            self.audio
-                .set(Tag::OpARatio as i64, self.params.a_ratio.value as f64);
-            self.audio
-                .set(Tag::OpBRatio as i64, self.params.b_ratio.value as f64);
-            self.audio
-                .set(Tag::OpAModB as i64, self.params.a_mod_b.value as f64);
-            self.audio
-                .set(Tag::NoiseAmp as i64, self.params.noise_amp.value as f64);
-            self.audio
-                .set(Tag::FilterFreq as i64, self.params.filter_freq.value as f64);
-            self.audio
-                .set(Tag::FilterQ as i64, self.params.filter_q.value as f64);
-
-            let midi = context.next_midi_event();
-            if let Some(event) = midi {
-                match event {
-                    NoteEvent::NoteOn { note, velocity, .. } => {
-                        self.enabled = true;
-                        self.audio
-                            .set(Tag::Freq as i64, midi_note_to_freq(note) as f64);
-
-                        self.note = Some(NoteInfo {
-                            note,
-                            velocity,
-                            on: self.time,
-                            stage: 0,
-                        });
-                    }
-                    NoteEvent::NoteOff { note, velocity, .. } => {
-                        if let Some(current_note) = &mut self.note {
-                            let params = self.params.env.read().unwrap();
-                            if current_note.note == note {
-                                current_note.velocity = velocity;
-                                current_note.stage = params.len() - 2;
-                                if let Ok(params) = self.params.env.read() {
-                                    // TODO: figure out how to offset the time here
-                                    // current_note.on = Duration::from_secs_f32(
-
-                                    //     // current_note.on.as_secs_f32()
-                                    //     //     - (params.last().unwrap().0
-                                    //     //         - params
-                                    //     //             .get(params.len() - 2)
-                                    //     //             .unwrap()
-                                    //     //             .0
-                                    //     //             .min(0f32))),
-                                    // );
-                                }
-                            }
-                        }
-                        if Some(note) == self.note.as_ref().map(|x| x.note) {}
-                    }
-                }
-            }
+        // Restart the modulators on playback start or bar boundaries so bounces are deterministic
+        let restart_mode = self.params.transport_restart.value();
+        if self.transport.update(context.transport(), restart_mode) {
+            self.engine.restart_modulators();
+            self.layer.restart_modulators();
+        }
+        self.engine
+            .telemetry()
+            .set_tempo(context.transport().tempo.map(|tempo| tempo as f32));
 
-            // Calculate main env notes on and off
-            if let Ok(envelope) = self.params.env.read() {
-                if let Some(note) = &mut self.note {
-                    let relative_time = self.time - note.on;
-                    // increase the point counter if more than the next point
-                    if let Some(next_point) = envelope.get(note.stage + 1) {
-                        if relative_time.as_secs_f32() >= next_point.0 {
-                            note.stage += 1;
-                        }
-                    }
-                    if note.stage == envelope.len() {
-                        // We have reached the end of the envelope. Trigger a note off
-                        self.note = None;
-                    }
+        let latency = self.engine.latency_samples();
+        if latency != self.latency {
+            self.latency = latency;
+            context.set_latency_samples(latency);
+        }
+
+        if self.snap_params.swap(false, Ordering::Relaxed) {
+            self.engine.snap_params();
+            self.layer.snap_params();
+        }
+        // Every note starts on its own sample, so each block gets rendered in pieces that end
+        // wherever the next event is due
+        let mut next_event = context.next_midi_event();
+        for (block_start, mut block) in buffer.iter_blocks(MAX_BUFFER_SIZE) {
+            let mut channels = block.iter_mut();
+            let (left, right) = match (channels.next(), channels.next(), channels.next()) {
+                (Some(left), Some(right), None) => (left, right),
+                _ => return ProcessStatus::Error("unexpected number of channels"),
+            };
+            // Notes from the editor skip the filters, they're meant for auditioning the patch
+            while let Some(event) = self.editor_notes.pop() {
+                match event {
+                    midi::QueuedNote::On { note, velocity } => self.note_on(note, velocity),
+                    midi::QueuedNote::Off { note, velocity } => self.note_off(note, velocity),
                 }
             }
 
-            // lerp between the two points based on note stage
-            let mut set_env = |param: &RwLock<Vec<(f32, f32)>>, tag| {
-                if let Some(note) = &self.note {
-                    let relative_time = self.time - note.on;
-                    if let Ok(envelope) = param.read() {
-                        if let (Some(left), Some(right)) =
-                            (envelope.get(note.stage), envelope.get(note.stage + 1))
-                        {
-                            let normalized =
-                                (relative_time.as_secs_f32() - left.0) / (right.0 - left.0);
-                            let val = lerp(left.1, right.1, normalized);
-                            self.audio.set(tag as i64, val as f64);
-                        }
+            let block_len = left.len();
+            let mut start = 0;
+            while start < block_len {
+                while let Some(event) = next_event {
+                    if event.timing() as usize > block_start + start {
+                        break;
                     }
+                    self.handle_event(event);
+                    next_event = context.next_midi_event();
                 }
-            };
-
-            set_env(&self.params.a_env, Tag::OpAEnv);
-            set_env(&self.params.b_env, Tag::OpBEnv);
-            set_env(&self.params.noise_env, Tag::NoiseEnv);
-            set_env(&self.params.env, Tag::Env);
-
-            // if let Some(note) = &midi {
-            //     if let NoteEvent::NoteOn { note, velocity, .. } = note {
-            //         self.audio
-            //             .set(Tag::Freq as i64, midi_note_to_freq(*note) as f64);
-            //         self.enabled = true;
-            //         self.note = Some(NoteInfo {
-            //             note: *note,
-            //             velocity: *velocity,
-            //             on: self.time,
-            //             stage: 0,
-            //         });
-            //     }
-            // }
-
-            // // get the envelope amplitude at this position in time
-            // let mut set_env = |param: &RwLock<Vec<(f32, f32)>>, tag| {
-            //     if let Ok(env_amp) = param.read() {
-            //         if let Some(note) = &mut self.note {
-            //             // check if we need to bump the note index
-            //             let relative_time = self.time - note.on;
-            //             let next_point = env_amp
-            //                 .get(note.stage + 1)
-            //                 .unwrap_or_else(|| env_amp.last().unwrap());
-            //             if relative_time.as_secs_f32() >= next_point.0 {
-            //                 // Increment the stage
-            //                 note.stage += 1;
-            //             }
-
-            //             // set to release envelope
-            //             if let Some(NoteEvent::NoteOff {
-            //                 timing,
-            //                 channel,
-            //                 note: current_note,
-            //                 velocity,
-            //             }) = midi
-            //             {
-            //                 if current_note == note.note {
-            //                     // set to second to last for release
-            //                     note.stage = env_amp.len() - 2;
-            //                 }
-            //             }
-
-            //             let left = env_amp
-            //                 .get(note.stage)
-            //                 .unwrap_or_else(|| env_amp.last().unwrap());
-            //             let right = env_amp
-            //                 .get(note.stage + 1)
-            //                 .unwrap_or_else(|| env_amp.last().unwrap());
-
-            //             let normalized =
-            //                 (relative_time.as_secs_f32() - left.0) / (right.0 - left.0);
-
-            //             let val = lerp(left.1, right.1, normalized);
-            //             self.audio.set(tag as i64, val as f64);
-            //         }
-            //     }
-            // };
-
-            //
-            //
-            //
-            //
-
-            let mut left_tmp = [0f64; MAX_BUFFER_SIZE];
-            let mut right_tmp = [0f64; MAX_BUFFER_SIZE];
-
-            if self.enabled {
-                self.time += Duration::from_secs_f32(MAX_BUFFER_SIZE as f32 / self.sample_rate);
-                self.audio
-                    .process(MAX_BUFFER_SIZE, &[], &mut [&mut left_tmp, &mut right_tmp]);
-            }
-
-            for (index, channel) in block.iter_mut().enumerate() {
-                let new_channel = match index {
-                    0 => left_tmp,
-                    1 => right_tmp,
-                    _ => return ProcessStatus::Error("unexpected number of channels"),
+                let end = match next_event {
+                    Some(event) => (event.timing() as usize - block_start).min(block_len),
+                    None => block_len,
                 };
-                for (sample_index, sample) in channel.iter_mut().enumerate() {
-                    *sample = new_channel[sample_index] as f32;
-                }
+                self.render_span(&mut left[start..end], &mut right[start..end]);
+                start = end;
             }
         }
 
@@ -393,9 +1578,9 @@ impl Plugin for Synthy {
 
     fn initialize(
         &mut self,
-        _bus_config: &BusConfig,
+        bus_config: &BusConfig,
         buffer_config: &BufferConfig,
-        _context: &mut impl ProcessContext,
+        context: &mut impl ProcessContext,
     ) -> bool {
         // Set up logs, adapted from code from DGriffin91
         // MIT: https://github.com/DGriffin91/egui_baseview_test_vst2/blob/main/LICENSE
@@ -405,19 +1590,55 @@ impl Plugin for Synthy {
         let log_config = ::simplelog::ConfigBuilder::new()
             .set_time_to_local(true)
             .build();
-        simplelog::WriteLogger::init(simplelog::LevelFilter::Info, log_config, log_file).ok();
+        simplelog::WriteLogger::init(config::Config::current().log_level, log_config, log_file)
+            .ok();
         log_panics::init();
         log::info!("init");
-        self.sample_rate = buffer_config.sample_rate;
+        // nih-plug initializes the plugin again after the host restored a project
+        self.params.migrate_legacy_ratios();
+        self.engine.initialize(
+            buffer_config.sample_rate,
+            buffer_config.process_mode == ProcessMode::Offline,
+        );
+        if let Ok(layer) = self.params.layer.read() {
+            layer.restore(self.layer.params().as_ref());
+        }
+        self.layer.initialize(
+            buffer_config.sample_rate,
+            buffer_config.process_mode == ProcessMode::Offline,
+        );
+        self.layer_output.resize(MAX_BUFFER_SIZE);
+        self.latency = self.engine.latency_samples();
+        context.set_latency_samples(self.latency);
+        self.has_input = bus_config.num_input_channels == 2;
+        if self.autosave.is_none() {
+            self.autosave = autosave::Autosave::start(self.params.clone());
+        }
         true
     }
 
+    #[cfg(feature = "egui-ui")]
     fn editor(&self) -> Option<Box<dyn Editor>> {
         let params = self.params.clone();
+        let layer_params = self.layer.params().clone();
+        let telemetry = self.engine.telemetry().clone();
+        let editor_notes = self.editor_notes.clone();
+        let snap_params = self.snap_params.clone();
         nih_plug_egui::create_egui_editor(
             self.editor.clone(),
-            (),
-            move |egui_ctx, setter, _state| ui::ui(egui_ctx, params.clone(), setter),
+            ui::EditorState::new(),
+            move |egui_ctx, setter, state| {
+                ui::ui(
+                    egui_ctx,
+                    params.clone(),
+                    layer_params.as_ref(),
+                    &telemetry,
+                    &editor_notes,
+                    &snap_params,
+                    setter,
+                    state,
+                )
+            },
         )
     }
 }
@@ -439,10 +1660,46 @@ pub enum Tag {
     FilterFreq,
     FilterQ,
     NoiseAmp,
+    RingMod,
+    Sync,
+    OpAPhase,
+    OpBPhase,
+    OpATrigger,
+    OpBTrigger,
+    Pluck,
+    PluckDamping,
+    PluckDecay,
+    ExtFm,
+    ExtLevel,
+    Expression,
+    NoiseCutoff,
+    NoiseWidth,
+    FilterDrive,
+    Filter2Freq,
+    Filter2Q,
+    Filter1Gain,
+    Filter2Gain,
+    Filter2Vowel,
+    EqLowFreq,
+    EqLowQ,
+    EqLowGain,
+    EqMidFreq,
+    EqMidQ,
+    EqMidGain,
+    EqHighFreq,
+    EqHighQ,
+    EqHighGain,
+    EqWet,
+    NoiseFmA,
+    NoiseFmB,
+    WetSmoothing,
+    Width,
+    /// Cutoff of the smoothing on the parameters that only update once per block
+    Smoothing,
 }
 
 impl Vst3Plugin for Synthy {
-    const VST3_CLASS_ID: [u8; 16] = *b"1234567891234567";
+    const VST3_CLASS_ID: [u8; 16] = identity::VST3_CLASS_ID;
     const VST3_CATEGORIES: &'static str = "Instrument|Synth";
 }
 
@@ -0,0 +1,275 @@
+use crate::{modulation, Note, Velocity};
+use nih_plug::prelude::*;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// The number of distinct MIDI notes
+const NOTE_COUNT: usize = 128;
+/// How many notes the editor can queue up before the audio thread gets to them
+const NOTE_QUEUE_LEN: usize = 64;
+/// The note-off velocity of notes released by lifting a pedal, which doesn't have one
+pub const PEDAL_RELEASE_VELOCITY: Velocity = 64;
+/// How much the soft pedal lowers the level at its full amount
+const SOFT_PEDAL_LEVEL: f32 = 0.5;
+/// How many octaves the soft pedal lowers the filters at its full amount
+const SOFT_PEDAL_DARKEN: f32 = 1.0;
+
+/// Map a velocity to `0..=1` through a response curve in `-1..=1`. Negative curves are softer so
+/// gentle playing already covers most of the range, positive curves need harder playing.
+pub fn velocity_response(velocity: Velocity, curve: f32) -> f32 {
+    modulation::apply_curve(velocity as f32 / 127f32, curve)
+}
+
+/// The gain of a note whose curved velocity is `velocity`, for a velocity sensitivity in `0..=1`.
+/// At the full amount the gain follows the velocity, without any the velocity is ignored.
+pub fn velocity_gain(velocity: f32, amount: f32) -> f32 {
+    1f32 - amount * (1f32 - velocity)
+}
+
+/// How much a note-off velocity stretches the release, for an amount in `0..=1`. At the full
+/// amount the gentlest lift doubles the release and the quickest snap-off halves it. Keyboards
+/// without release velocity send 64, which leaves the release as it is.
+pub fn release_scale(velocity: Velocity, amount: f32) -> f32 {
+    (amount * (1f32 - velocity as f32 / 63.5)).exp2()
+}
+
+/// The gain and the filter frequency multiplier for the soft pedal at `amount`
+pub fn soft_pedal(down: bool, amount: f32) -> (f32, f32) {
+    match down {
+        true => (
+            1f32 - amount * SOFT_PEDAL_LEVEL,
+            (-amount * SOFT_PEDAL_DARKEN).exp2(),
+        ),
+        false => (1f32, 1f32),
+    }
+}
+
+/// Decides which held note sounds in mono mode
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+/// The notes that are currently held down, oldest first. This has a fixed capacity so it can be
+/// updated on the audio thread.
+pub struct HeldNotes {
+    notes: [Note; NOTE_COUNT],
+    len: usize,
+}
+
+impl Default for HeldNotes {
+    fn default() -> Self {
+        Self {
+            notes: [0; NOTE_COUNT],
+            len: 0,
+        }
+    }
+}
+
+impl HeldNotes {
+    pub fn push(&mut self, note: Note) {
+        self.remove(note);
+        if self.len < NOTE_COUNT {
+            self.notes[self.len] = note;
+            self.len += 1;
+        }
+    }
+
+    /// Remove a note from the stack, returning whether it was held
+    pub fn remove(&mut self, note: Note) -> bool {
+        match self.as_slice().iter().position(|held| *held == note) {
+            Some(index) => {
+                self.notes.copy_within(index + 1..self.len, index);
+                self.len -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn as_slice(&self) -> &[Note] {
+        &self.notes[..self.len]
+    }
+
+    pub fn contains(&self, note: Note) -> bool {
+        self.as_slice().contains(&note)
+    }
+
+    /// The held note that should be sounding for the given priority
+    pub fn select(&self, priority: NotePriority) -> Option<Note> {
+        let notes = self.as_slice().iter().copied();
+        match priority {
+            NotePriority::Last => self.as_slice().last().copied(),
+            NotePriority::Low => notes.min(),
+            NotePriority::High => notes.max(),
+        }
+    }
+}
+
+/// The sostenuto pedal. Pressing it catches the notes that are held at that moment, and their
+/// note offs are held back until it's lifted. Notes played while it's down aren't affected.
+#[derive(Default)]
+pub struct Sostenuto {
+    down: bool,
+    caught: HeldNotes,
+    /// Caught notes whose keys have been let go
+    deferred: HeldNotes,
+}
+
+impl Sostenuto {
+    /// Update the pedal, returning the notes that should be released now that it's lifted
+    pub fn set(&mut self, down: bool, held: &HeldNotes) -> HeldNotes {
+        if down == self.down {
+            return HeldNotes::default();
+        }
+        self.down = down;
+        self.caught.clear();
+        if down {
+            for note in held.as_slice() {
+                self.caught.push(*note);
+            }
+        }
+        std::mem::take(&mut self.deferred)
+    }
+
+    /// Called on every note on, a deferred note that's played again is held by its key again
+    pub fn note_on(&mut self, note: Note) {
+        self.deferred.remove(note);
+    }
+
+    /// Called on every note off, returns whether the pedal holds on to the note instead
+    pub fn defer(&mut self, note: Note) -> bool {
+        let caught = self.down && self.caught.contains(note);
+        if caught {
+            self.deferred.push(note);
+        }
+        caught
+    }
+}
+
+/// A note played from the editor rather than the host
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum QueuedNote {
+    On { note: Note, velocity: Velocity },
+    Off { note: Note, velocity: Velocity },
+}
+
+impl QueuedNote {
+    /// Pack the note into a single word so it can be stored in an atomic
+    fn to_bits(self) -> u32 {
+        let (on, note, velocity) = match self {
+            Self::On { note, velocity } => (1, note, velocity),
+            Self::Off { note, velocity } => (0, note, velocity),
+        };
+        on << 16 | (note as u32) << 8 | velocity as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        let note = (bits >> 8) as Note;
+        let velocity = bits as Velocity;
+        match bits >> 16 {
+            0 => Self::Off { note, velocity },
+            _ => Self::On { note, velocity },
+        }
+    }
+}
+
+/// A bounded single producer, single consumer queue for sending notes from the editor to the audio
+/// thread. Neither side ever blocks or allocates.
+pub struct NoteQueue {
+    slots: [AtomicU32; NOTE_QUEUE_LEN],
+    /// Total number of notes read so far
+    read: AtomicUsize,
+    /// Total number of notes written so far
+    write: AtomicUsize,
+}
+
+impl Default for NoteQueue {
+    fn default() -> Self {
+        Self {
+            slots: std::array::from_fn(|_| AtomicU32::new(0)),
+            read: AtomicUsize::new(0),
+            write: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl NoteQueue {
+    /// Queue a note, called from the editor. Returns false and drops the note when the queue is
+    /// full.
+    pub fn push(&self, note: QueuedNote) -> bool {
+        let write = self.write.load(Ordering::Relaxed);
+        if write.wrapping_sub(self.read.load(Ordering::Acquire)) >= NOTE_QUEUE_LEN {
+            return false;
+        }
+        self.slots[write % NOTE_QUEUE_LEN].store(note.to_bits(), Ordering::Relaxed);
+        self.write.store(write.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Take the oldest queued note, called from the audio thread
+    pub fn pop(&self) -> Option<QueuedNote> {
+        let read = self.read.load(Ordering::Relaxed);
+        if read == self.write.load(Ordering::Acquire) {
+            return None;
+        }
+        let bits = self.slots[read % NOTE_QUEUE_LEN].load(Ordering::Relaxed);
+        self.read.store(read.wrapping_add(1), Ordering::Release);
+        Some(QueuedNote::from_bits(bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn held(notes: &[Note]) -> HeldNotes {
+        let mut held = HeldNotes::default();
+        for note in notes {
+            held.push(*note);
+        }
+        held
+    }
+
+    #[test]
+    fn held_notes_keep_their_order() {
+        let mut notes = held(&[60, 64, 62]);
+        notes.push(60);
+        assert_eq!(notes.as_slice(), &[64, 62, 60]);
+        assert!(notes.remove(64));
+        assert!(!notes.remove(64));
+        assert_eq!(notes.select(NotePriority::Last), Some(60));
+        assert_eq!(notes.select(NotePriority::Low), Some(60));
+        assert_eq!(notes.select(NotePriority::High), Some(62));
+    }
+
+    #[test]
+    fn sostenuto_only_holds_notes_caught_when_pressed() {
+        let mut sostenuto = Sostenuto::default();
+        assert!(sostenuto.set(true, &held(&[60])).is_empty());
+        assert!(sostenuto.defer(60));
+        assert!(!sostenuto.defer(62));
+        assert_eq!(sostenuto.set(false, &held(&[60])).as_slice(), &[60]);
+        // Once lifted nothing is held back anymore
+        assert!(!sostenuto.defer(60));
+    }
+
+    #[test]
+    fn sostenuto_lets_go_of_notes_played_again() {
+        let mut sostenuto = Sostenuto::default();
+        sostenuto.set(true, &held(&[60, 62]));
+        assert!(sostenuto.defer(60));
+        assert!(sostenuto.defer(62));
+        sostenuto.note_on(60);
+        assert_eq!(sostenuto.set(false, &held(&[60, 62])).as_slice(), &[62]);
+    }
+}
@@ -0,0 +1,163 @@
+use atomic_float::AtomicF32;
+use nih_plug::prelude::*;
+use std::sync::atomic::Ordering;
+
+/// The exponent of a response curve at either end of its range, in octaves
+const CURVE_RANGE: f32 = 2.0;
+
+/// Bend `value` with a response curve in `-1..=1`, keeping its sign. Negative curves push values
+/// in `-1..=1` outwards, positive curves pull them in so the response is exponential.
+pub fn apply_curve(value: f32, curve: f32) -> f32 {
+    if curve == 0f32 {
+        return value;
+    }
+    value
+        .abs()
+        .powf((curve * CURVE_RANGE).exp2())
+        .copysign(value)
+}
+
+/// The current value of every modulation source, normalized to `0..=1`. This is updated once
+/// per block on the audio thread.
+#[derive(Default, Clone, Copy)]
+pub struct ModSources {
+    /// Mod wheel (CC1)
+    pub mod_wheel: f32,
+    /// Expression (CC11)
+    pub expression: f32,
+}
+
+/// Parameters whose effective value gets modulated on the audio thread
+#[derive(Clone, Copy)]
+pub enum ModTarget {
+    /// Modulated by the mod wheel
+    VibratoDepth,
+    OpARatio,
+    OpBRatio,
+    RingMod,
+    Pluck,
+    Vowel,
+}
+
+pub const TARGET_COUNT: usize = 6;
+
+/// Where an envelope's or LFO's output goes
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum ModDestination {
+    #[name = "op a level"]
+    OpALevel,
+    #[name = "op b level"]
+    OpBLevel,
+    #[name = "noise level"]
+    NoiseLevel,
+    #[name = "amp"]
+    Amp,
+    #[name = "op a ratio"]
+    OpARatio,
+    #[name = "op b ratio"]
+    OpBRatio,
+    #[name = "ring mod"]
+    RingMod,
+    #[name = "pluck"]
+    Pluck,
+    #[name = "vowel"]
+    Vowel,
+}
+
+/// Which operators the filtered noise frequency modulates
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum NoiseFmTarget {
+    #[name = "op a"]
+    OpA,
+    #[name = "op b"]
+    OpB,
+    #[name = "both"]
+    Both,
+}
+
+impl NoiseFmTarget {
+    /// The noise FM depth of operator a and b
+    pub fn depths(self, depth: f32) -> (f32, f32) {
+        match self {
+            Self::OpA => (depth, 0f32),
+            Self::OpB => (0f32, depth),
+            Self::Both => (depth, depth),
+        }
+    }
+}
+
+/// Number of levels in the graph that can be driven by modulators
+pub const LEVEL_COUNT: usize = 4;
+
+/// How a modulator's output gets applied
+pub enum Routing {
+    /// Drives one of the graph's levels directly. The index matches the envelope that drives
+    /// it by default. Levels without anything routed to them stay silent.
+    Level(usize),
+    /// Offsets a parameter's normalized value
+    Param(ModTarget),
+}
+
+impl ModDestination {
+    pub fn routing(self) -> Routing {
+        match self {
+            Self::OpALevel => Routing::Level(0),
+            Self::OpBLevel => Routing::Level(1),
+            Self::NoiseLevel => Routing::Level(2),
+            Self::Amp => Routing::Level(3),
+            Self::OpARatio => Routing::Param(ModTarget::OpARatio),
+            Self::OpBRatio => Routing::Param(ModTarget::OpBRatio),
+            Self::RingMod => Routing::Param(ModTarget::RingMod),
+            Self::Pluck => Routing::Param(ModTarget::Pluck),
+            Self::Vowel => Routing::Param(ModTarget::Vowel),
+        }
+    }
+}
+
+/// The normalized modulated value of every [`ModTarget`], written by the audio thread once per
+/// block and read by the editor to draw modulation overlays. `NaN` means unmodulated.
+pub struct ModSnapshot {
+    values: [AtomicF32; TARGET_COUNT],
+}
+
+impl Default for ModSnapshot {
+    fn default() -> Self {
+        Self {
+            values: std::array::from_fn(|_| AtomicF32::new(f32::NAN)),
+        }
+    }
+}
+
+impl ModSnapshot {
+    pub fn set(&self, target: ModTarget, normalized: Option<f32>) {
+        self.values[target as usize].store(normalized.unwrap_or(f32::NAN), Ordering::Relaxed);
+    }
+
+    pub fn get(&self, target: ModTarget) -> Option<f32> {
+        let value = self.values[target as usize].load(Ordering::Relaxed);
+        (!value.is_nan()).then(|| value)
+    }
+}
+
+/// The summed output of every modulator for one block
+#[derive(Default)]
+pub struct ModBus {
+    pub levels: [f32; LEVEL_COUNT],
+    /// Offsets to the normalized value of every [`ModTarget`]
+    pub offsets: [f32; TARGET_COUNT],
+}
+
+impl ModBus {
+    pub fn add(&mut self, destination: ModDestination, value: f32) {
+        match destination.routing() {
+            Routing::Level(level) => self.levels[level] += value,
+            Routing::Param(target) => self.offsets[target as usize] += value,
+        }
+    }
+
+    /// Scale every parameter offset. The levels are left alone, they're what makes the voice
+    /// sound at all.
+    pub fn scale_offsets(&mut self, amount: f32) {
+        self.offsets.iter_mut().for_each(|offset| *offset *= amount);
+    }
+}
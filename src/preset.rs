@@ -0,0 +1,444 @@
+use crate::{
+    dsp::{
+        rng::{Rng, RngStream},
+        Adsr, EnvelopeTiming,
+    },
+    split_ratio, SynthyParams, MAX_RATIO,
+};
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+    pin::Pin,
+    sync::RwLock,
+};
+
+/// File extension for synthy presets
+pub const PRESET_EXTENSION: &str = "synthy";
+
+/// Single ratio parameters from before the ratios were split into coarse and fine parameters,
+/// along with the parameters that replaced them
+pub(crate) const LEGACY_RATIOS: [(&str, &str, &str); 2] = [
+    ("a_ratio", "a_ratio_coarse", "a_ratio_fine"),
+    ("b_ratio", "b_ratio_coarse", "b_ratio_fine"),
+];
+
+/// The persisted field holding the editor's state, which isn't part of a preset
+const UI_STATE_FIELD: &str = "ui_state";
+/// The host's bypass, which isn't part of a preset either
+const BYPASS_PARAM: &str = "bypass";
+/// The persisted field holding the layer patch
+const LAYER_FIELD: &str = "layer";
+/// How the patch plays along with its layer, which means nothing for the layer itself
+const LAYER_PARAMS: [&str; 3] = ["layer_mode", "split_key", "layer_balance"];
+/// The persisted fields of every envelope: its points, timing and ADSR settings
+const ENVELOPE_FIELDS: [(&str, &str, &str); 4] = [
+    ("a_env", "a_env_timing", "a_env_adsr"),
+    ("b_env", "b_env_timing", "b_env_adsr"),
+    ("noise_env", "noise_env_timing", "noise_env_adsr"),
+    ("env", "env_timing", "env_adsr"),
+];
+
+/// Parameters the randomizer never touches. These decide how the synth is played, monitored and
+/// kept safe rather than how it sounds.
+const NOT_RANDOMIZED: [&str; 30] = [
+    "quality",
+    "offline_quality",
+    "fx_bypass",
+    "safe_mode",
+    "comp_lookahead",
+    "out_trim_left",
+    "out_trim_right",
+    "out_invert_left",
+    "out_invert_right",
+    "midi_channel",
+    "key_low",
+    "key_high",
+    "velocity_low",
+    "velocity_high",
+    "velocity_curve",
+    "max_voices",
+    "seed",
+    "transport_restart",
+    "mod_wheel",
+    "expression",
+    "sostenuto",
+    "soft_pedal",
+    "mod_amount",
+    "a_env_mute",
+    "b_env_mute",
+    "noise_env_mute",
+    "env_mute",
+    "lfo_mute",
+    "layer_mode",
+    "split_key",
+];
+
+/// A snapshot of every parameter and envelope
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Preset {
+    /// Normalized parameter values by parameter ID
+    pub params: BTreeMap<String, f32>,
+    /// Persisted fields like the envelopes, serialized the same way nih-plug stores them
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Preset {
+    /// Capture the current state of the plugin
+    pub fn capture(params: Pin<&SynthyParams>) -> Self {
+        Self {
+            params: params
+                .param_map()
+                .into_iter()
+                .filter(|(id, _)| {
+                    *id != BYPASS_PARAM && !LEGACY_RATIOS.iter().any(|(legacy, ..)| legacy == id)
+                })
+                .map(|(id, ptr)| (id.to_string(), unsafe { ptr.normalized_value() }))
+                .collect(),
+            // The editor's own state stays with the plugin instance
+            fields: params
+                .serialize_fields()
+                .into_iter()
+                .filter(|(key, _)| key != UI_STATE_FIELD)
+                .collect(),
+        }
+    }
+
+    /// A snapshot of the patch to play as a layer. Layers don't have layers of their own, so
+    /// applying the snapshot to a patch leaves how that patch plays along with its layer alone.
+    pub fn capture_layer(params: Pin<&SynthyParams>) -> Self {
+        let mut preset = Self::capture(params);
+        preset.fields.remove(LAYER_FIELD);
+        preset
+            .params
+            .retain(|id, _| !LAYER_PARAMS.contains(&id.as_str()));
+        preset
+    }
+
+    /// The state of a freshly loaded plugin
+    pub fn init() -> Self {
+        Self::capture(Box::pin(SynthyParams::default()).as_ref())
+    }
+
+    /// Apply the preset through the setter so the host gets notified about every change.
+    /// Parameters missing from the preset keep their current value, persisted fields missing from
+    /// older presets get reset to their defaults so nothing carries over from the previous patch.
+    pub fn apply(&self, params: Pin<&SynthyParams>, setter: &ParamSetter) {
+        let values = self.migrated_params(&params);
+        for (id, ptr) in params.param_map() {
+            if let Some(normalized) = values.get(id) {
+                unsafe {
+                    setter.raw_context.raw_begin_set_parameter(ptr);
+                    setter
+                        .raw_context
+                        .raw_set_parameter_normalized(ptr, *normalized);
+                    setter.raw_context.raw_end_set_parameter(ptr);
+                }
+            }
+        }
+        let mut fields = Self::init().fields;
+        fields.extend(self.fields.clone());
+        params.deserialize_fields(&fields.into_iter().collect());
+    }
+
+    /// Apply the preset to parameters the host doesn't know about, like the layer's. Unlike
+    /// [`Self::apply`] everything missing from the preset is reset, so this always results in the
+    /// same patch.
+    pub fn restore(&self, params: Pin<&SynthyParams>) {
+        let init = Self::init();
+        let values = self.migrated_params(&params);
+        for (id, ptr) in params.param_map() {
+            if let Some(normalized) = values.get(id).or_else(|| init.params.get(id)) {
+                unsafe { ptr.set_normalized_value(*normalized) };
+            }
+        }
+        let mut fields = init.fields;
+        fields.extend(self.fields.clone());
+        params.deserialize_fields(&fields.into_iter().collect());
+    }
+
+    /// The preset's parameter values with parameters from older versions converted to their
+    /// replacements
+    fn migrated_params(&self, params: &SynthyParams) -> BTreeMap<String, f32> {
+        let mut values = self.params.clone();
+        let replacements = [
+            (&params.a_ratio_coarse, &params.a_ratio_fine),
+            (&params.b_ratio_coarse, &params.b_ratio_fine),
+        ];
+        for ((legacy_id, coarse_id, fine_id), (coarse, fine)) in
+            LEGACY_RATIOS.into_iter().zip(replacements)
+        {
+            if let Some(normalized) = values.remove(legacy_id) {
+                // The old ratios went linearly from 0 to the maximum ratio
+                let (coarse_value, fine_value) = split_ratio(normalized * MAX_RATIO);
+                values
+                    .entry(coarse_id.to_string())
+                    .or_insert_with(|| coarse.preview_normalized(coarse_value));
+                values
+                    .entry(fine_id.to_string())
+                    .or_insert_with(|| fine.preview_normalized(fine_value));
+            }
+        }
+        values
+    }
+
+    /// A copy of this preset with every parameter set to a random value, except for the ones in
+    /// `locked`. With `envelopes` every envelope gets replaced by a random ADSR envelope, the
+    /// other persisted fields are kept as they are.
+    pub fn randomized(&self, locked: &BTreeSet<String>, seed: u32, envelopes: bool) -> Self {
+        let mut rng = Rng::new(seed, RngStream::Randomize);
+        let mut fields = self.fields.clone();
+        if envelopes {
+            for (points_key, timing_key, adsr_key) in ENVELOPE_FIELDS {
+                let adsr = Adsr::random(&mut rng);
+                let mut points = Vec::new();
+                let mut timing = EnvelopeTiming::default();
+                adsr.apply(&mut points, &mut timing);
+                for (key, value) in [
+                    (points_key, serde_json::to_string(&points)),
+                    (timing_key, serde_json::to_string(&timing)),
+                    (adsr_key, serde_json::to_string(&adsr)),
+                ] {
+                    if let Ok(value) = value {
+                        fields.insert(key.to_string(), value);
+                    }
+                }
+            }
+        }
+        Self {
+            params: self
+                .params
+                .iter()
+                .map(|(id, normalized)| {
+                    let keep = locked.contains(id) || NOT_RANDOMIZED.contains(&id.as_str());
+                    let normalized = match keep {
+                        true => *normalized,
+                        false => rng.unipolar(),
+                    };
+                    (id.clone(), normalized)
+                })
+                .collect(),
+            fields,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+/// One of the two FM operators
+#[derive(Clone, Copy, PartialEq)]
+pub enum Operator {
+    A,
+    B,
+}
+
+impl Operator {
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::A => "op a",
+            Self::B => "op b",
+        }
+    }
+}
+
+/// The settings of a single operator, used to copy one operator over another
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperatorPreset {
+    pub ratio_coarse: i32,
+    pub ratio_fine: f32,
+    pub modulation: f32,
+    pub phase: f32,
+    pub retrigger: bool,
+    pub envelope: Vec<(f32, f32)>,
+    pub envelope_timing: EnvelopeTiming,
+    pub envelope_depth: f32,
+}
+
+impl OperatorPreset {
+    pub fn capture(params: &SynthyParams, operator: Operator) -> Self {
+        let op = OperatorParams::new(params, operator);
+        Self {
+            ratio_coarse: op.ratio_coarse.value,
+            ratio_fine: op.ratio_fine.value,
+            modulation: op.modulation.value,
+            phase: op.phase.value,
+            retrigger: op.retrigger.value,
+            envelope: op
+                .envelope
+                .read()
+                .map(|env| env.clone())
+                .unwrap_or_default(),
+            envelope_timing: op
+                .envelope_timing
+                .read()
+                .map(|timing| *timing)
+                .unwrap_or_default(),
+            envelope_depth: op.envelope_depth.value,
+        }
+    }
+
+    pub fn apply(&self, params: &SynthyParams, operator: Operator, setter: &ParamSetter) {
+        let op = OperatorParams::new(params, operator);
+        for (param, value) in [
+            (op.ratio_fine, self.ratio_fine),
+            (op.modulation, self.modulation),
+            (op.phase, self.phase),
+            (op.envelope_depth, self.envelope_depth),
+        ] {
+            setter.begin_set_parameter(param);
+            setter.set_parameter(param, value);
+            setter.end_set_parameter(param);
+        }
+        setter.begin_set_parameter(op.ratio_coarse);
+        setter.set_parameter(op.ratio_coarse, self.ratio_coarse);
+        setter.end_set_parameter(op.ratio_coarse);
+        setter.begin_set_parameter(op.retrigger);
+        setter.set_parameter(op.retrigger, self.retrigger);
+        setter.end_set_parameter(op.retrigger);
+        if let Ok(mut env) = op.envelope.write() {
+            *env = self.envelope.clone();
+        }
+        if let Ok(mut timing) = op.envelope_timing.write() {
+            *timing = self.envelope_timing;
+        }
+    }
+}
+
+/// The parameters belonging to one operator
+struct OperatorParams<'a> {
+    ratio_coarse: &'a IntParam,
+    ratio_fine: &'a FloatParam,
+    modulation: &'a FloatParam,
+    phase: &'a FloatParam,
+    retrigger: &'a BoolParam,
+    envelope: &'a RwLock<Vec<(f32, f32)>>,
+    envelope_timing: &'a RwLock<EnvelopeTiming>,
+    envelope_depth: &'a FloatParam,
+}
+
+impl<'a> OperatorParams<'a> {
+    fn new(params: &'a SynthyParams, operator: Operator) -> Self {
+        match operator {
+            Operator::A => Self {
+                ratio_coarse: &params.a_ratio_coarse,
+                ratio_fine: &params.a_ratio_fine,
+                modulation: &params.a_mod,
+                phase: &params.a_phase,
+                retrigger: &params.a_retrigger,
+                envelope: &params.a_env,
+                envelope_timing: &params.a_env_timing,
+                envelope_depth: &params.a_env_depth,
+            },
+            Operator::B => Self {
+                ratio_coarse: &params.b_ratio_coarse,
+                ratio_fine: &params.b_ratio_fine,
+                modulation: &params.b_mod,
+                phase: &params.b_phase,
+                retrigger: &params.b_retrigger,
+                envelope: &params.b_env,
+                envelope_timing: &params.b_env_timing,
+                envelope_depth: &params.b_env_depth,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(values: &[(&str, f32)]) -> Preset {
+        Preset {
+            params: values
+                .iter()
+                .map(|(id, normalized)| (id.to_string(), *normalized))
+                .collect(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn params(values: &[(&str, f32)]) -> Pin<Box<SynthyParams>> {
+        let params = Box::pin(SynthyParams::default());
+        preset(values).restore(params.as_ref());
+        params
+    }
+
+    fn assert_close(actual: Option<&f32>, expected: f32) {
+        let actual = actual.copied().unwrap_or(f32::NAN);
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn layer_snapshots_survive_a_round_trip() {
+        let source = params(&[("a_mod", 0.25), ("filter_freq", 0.75), ("layer_mode", 1.0)]);
+        let layer = Preset::capture_layer(source.as_ref());
+        assert!(LAYER_PARAMS
+            .iter()
+            .all(|id| !layer.params.contains_key(*id)));
+        assert!(!layer.fields.contains_key(LAYER_FIELD));
+
+        let target = params(&[("a_mod", 0.9), ("b_mod", 0.1)]);
+        layer.restore(target.as_ref());
+        let restored = Preset::capture_layer(target.as_ref());
+        assert!(restored.fields == layer.fields);
+        assert_eq!(
+            restored.params.keys().collect::<Vec<_>>(),
+            layer.params.keys().collect::<Vec<_>>()
+        );
+        for (id, normalized) in &layer.params {
+            assert_close(restored.params.get(id), *normalized);
+        }
+    }
+
+    #[test]
+    fn legacy_ratios_get_split_into_coarse_and_fine() {
+        let params = SynthyParams::default();
+        let values = preset(&[
+            ("a_ratio", 2.25 / MAX_RATIO),
+            ("b_ratio", 3.0 / MAX_RATIO),
+            (
+                "b_ratio_coarse",
+                params.b_ratio_coarse.preview_normalized(5),
+            ),
+        ])
+        .migrated_params(&params);
+        assert!(!values.contains_key("a_ratio") && !values.contains_key("b_ratio"));
+        assert_close(
+            values.get("a_ratio_coarse"),
+            params.a_ratio_coarse.preview_normalized(2),
+        );
+        assert_close(
+            values.get("a_ratio_fine"),
+            params.a_ratio_fine.preview_normalized(0.25),
+        );
+        // Replacements that are already in the preset win over the legacy ratio
+        assert_close(
+            values.get("b_ratio_coarse"),
+            params.b_ratio_coarse.preview_normalized(5),
+        );
+        assert_close(
+            values.get("b_ratio_fine"),
+            params.b_ratio_fine.preview_normalized(0.0),
+        );
+    }
+}
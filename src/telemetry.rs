@@ -0,0 +1,298 @@
+use crate::{modulation::ModSnapshot, Note, Velocity, ENV_COUNT};
+use atomic_float::AtomicF32;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Everything the audio thread reports back to the editor. The processor fills this in once per
+/// block and every visualization reads from it, neither side ever blocks.
+pub struct Telemetry {
+    /// The most recent output for the scope
+    pub scope: ScopeBuffer,
+    /// Modulated parameter values
+    pub modulation: ModSnapshot,
+    /// The compressor's gain reduction in decibels
+    pub gain_reduction: Meter,
+    /// Peak output level of each channel during the last block
+    pub peaks: [Meter; 2],
+    /// Number of notes currently sounding
+    active_voices: AtomicUsize,
+    /// Every envelope's position on its time axis in seconds, NaN while no note is playing
+    envelope_positions: [Meter; ENV_COUNT],
+    /// What every envelope actually put out during the last note
+    pub envelope_traces: [EnvelopeTrace; ENV_COUNT],
+    /// The LFO's output after its depth has been applied
+    pub lfo: Meter,
+    /// The host's tempo in beats per minute, NaN when the host doesn't report one
+    tempo: Meter,
+    /// The sounding pitch as a fractional MIDI note including glide, vibrato and drift, NaN
+    /// while no note is playing
+    pitch: Meter,
+    /// The note the glide is heading for, NaN while not gliding
+    glide_target: Meter,
+    /// The most recent MIDI events from the host
+    pub midi_log: MidiLog,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            scope: ScopeBuffer::default(),
+            modulation: ModSnapshot::default(),
+            gain_reduction: Meter::default(),
+            peaks: Default::default(),
+            active_voices: AtomicUsize::new(0),
+            envelope_positions: std::array::from_fn(|_| Meter::new(f32::NAN)),
+            envelope_traces: Default::default(),
+            lfo: Meter::default(),
+            tempo: Meter::new(f32::NAN),
+            pitch: Meter::new(f32::NAN),
+            glide_target: Meter::new(f32::NAN),
+            midi_log: MidiLog::default(),
+        }
+    }
+}
+
+impl Telemetry {
+    pub fn set_envelope_position(&self, index: usize, position: Option<f32>) {
+        self.envelope_positions[index].set(position.unwrap_or(f32::NAN));
+    }
+
+    pub fn envelope_position(&self, index: usize) -> Option<f32> {
+        let position = self.envelope_positions[index].get();
+        (!position.is_nan()).then(|| position)
+    }
+
+    pub fn set_tempo(&self, tempo: Option<f32>) {
+        self.tempo.set(tempo.unwrap_or(f32::NAN));
+    }
+
+    pub fn tempo(&self) -> Option<f32> {
+        let tempo = self.tempo.get();
+        (!tempo.is_nan()).then(|| tempo)
+    }
+
+    pub fn set_pitch(&self, pitch: Option<f32>, glide_target: Option<f32>) {
+        self.pitch.set(pitch.unwrap_or(f32::NAN));
+        self.glide_target.set(glide_target.unwrap_or(f32::NAN));
+    }
+
+    pub fn pitch(&self) -> Option<f32> {
+        let pitch = self.pitch.get();
+        (!pitch.is_nan()).then(|| pitch)
+    }
+
+    pub fn glide_target(&self) -> Option<f32> {
+        let target = self.glide_target.get();
+        (!target.is_nan()).then(|| target)
+    }
+
+    pub fn set_active_voices(&self, voices: usize) {
+        self.active_voices.store(voices, Ordering::Relaxed);
+    }
+
+    pub fn active_voices(&self) -> usize {
+        self.active_voices.load(Ordering::Relaxed)
+    }
+}
+
+/// Number of stereo frames kept for the scope
+pub const SCOPE_LEN: usize = 2048;
+
+/// A ring buffer of the most recent output samples. The audio thread writes into it and the
+/// editor reads a snapshot of it for the scope, neither side ever blocks.
+pub struct ScopeBuffer {
+    left: Vec<AtomicF32>,
+    right: Vec<AtomicF32>,
+    write_pos: AtomicUsize,
+}
+
+impl Default for ScopeBuffer {
+    fn default() -> Self {
+        Self {
+            left: (0..SCOPE_LEN).map(|_| AtomicF32::new(0f32)).collect(),
+            right: (0..SCOPE_LEN).map(|_| AtomicF32::new(0f32)).collect(),
+            write_pos: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl ScopeBuffer {
+    /// Append a block of output, called from the audio thread
+    pub fn push(&self, left: &[f32], right: &[f32]) {
+        let mut pos = self.write_pos.load(Ordering::Relaxed);
+        for (l, r) in left.iter().zip(right) {
+            self.left[pos].store(*l, Ordering::Relaxed);
+            self.right[pos].store(*r, Ordering::Relaxed);
+            pos = (pos + 1) % SCOPE_LEN;
+        }
+        self.write_pos.store(pos, Ordering::Release);
+    }
+
+    /// Copy out the most recent frames, oldest first
+    pub fn snapshot(&self) -> Vec<(f32, f32)> {
+        let start = self.write_pos.load(Ordering::Acquire);
+        (0..SCOPE_LEN)
+            .map(|i| {
+                let pos = (start + i) % SCOPE_LEN;
+                (
+                    self.left[pos].load(Ordering::Relaxed),
+                    self.right[pos].load(Ordering::Relaxed),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Number of MIDI events kept in the log
+pub const MIDI_LOG_LEN: usize = 64;
+
+/// A MIDI event as the synth received it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct MidiEvent {
+    pub on: bool,
+    /// Zero based MIDI channel
+    pub channel: u8,
+    pub note: Note,
+    pub velocity: Velocity,
+    /// Whether the event passed the channel, key and velocity filters
+    pub accepted: bool,
+}
+
+impl MidiEvent {
+    /// Pack the event into a single word so it can be stored in an atomic
+    fn to_bits(self) -> u32 {
+        (self.accepted as u32) << 25
+            | (self.on as u32) << 24
+            | (self.channel as u32) << 16
+            | (self.note as u32) << 8
+            | self.velocity as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        Self {
+            accepted: bits >> 25 & 1 == 1,
+            on: bits >> 24 & 1 == 1,
+            channel: (bits >> 16) as u8,
+            note: (bits >> 8) as Note,
+            velocity: bits as Velocity,
+        }
+    }
+}
+
+/// A ring buffer of the most recent MIDI events, for the editor's event log. Like the scope,
+/// neither side ever blocks. An editor that falls more than [`MIDI_LOG_LEN`] events behind
+/// misses the oldest ones.
+pub struct MidiLog {
+    events: Vec<AtomicU32>,
+    /// Total number of events written so far
+    written: AtomicUsize,
+}
+
+impl Default for MidiLog {
+    fn default() -> Self {
+        Self {
+            events: (0..MIDI_LOG_LEN).map(|_| AtomicU32::new(0)).collect(),
+            written: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl MidiLog {
+    /// Add an event, called from the audio thread
+    pub fn push(&self, event: MidiEvent) {
+        let written = self.written.load(Ordering::Relaxed);
+        self.events[written % MIDI_LOG_LEN].store(event.to_bits(), Ordering::Relaxed);
+        self.written
+            .store(written.wrapping_add(1), Ordering::Release);
+    }
+
+    /// The events written since `read` events had been read, moving `read` past them
+    pub fn read_since(&self, read: &mut usize) -> Vec<MidiEvent> {
+        let written = self.written.load(Ordering::Acquire);
+        let start = (*read).max(written.saturating_sub(MIDI_LOG_LEN));
+        *read = written;
+        (start..written)
+            .map(|index| {
+                MidiEvent::from_bits(self.events[index % MIDI_LOG_LEN].load(Ordering::Relaxed))
+            })
+            .collect()
+    }
+}
+
+/// Maximum number of points in an envelope trace
+pub const TRACE_LEN: usize = 1024;
+/// Minimum distance on the envelope's time axis between the points of a trace in seconds
+const TRACE_STEP: f32 = 0.01;
+
+/// An envelope's level over its time axis during the last note, so the editor can show what was
+/// actually played on top of the drawn points. Like the scope, neither side ever blocks.
+pub struct EnvelopeTrace {
+    points: Vec<(AtomicF32, AtomicF32)>,
+    len: AtomicUsize,
+}
+
+impl Default for EnvelopeTrace {
+    fn default() -> Self {
+        Self {
+            points: (0..TRACE_LEN)
+                .map(|_| (AtomicF32::new(0f32), AtomicF32::new(0f32)))
+                .collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl EnvelopeTrace {
+    /// Start over for a new note, called from the audio thread
+    pub fn clear(&self) {
+        self.len.store(0, Ordering::Release);
+    }
+
+    /// Record the level at a position on the envelope's time axis, called from the audio thread.
+    /// Positions too close to the previous one are skipped, so holding a point doesn't fill up
+    /// the trace.
+    pub fn record(&self, time: f32, level: f32) {
+        let len = self.len.load(Ordering::Relaxed);
+        if len >= TRACE_LEN {
+            return;
+        }
+        if let Some((last_time, _)) = len.checked_sub(1).map(|last| &self.points[last]) {
+            if (time - last_time.load(Ordering::Relaxed)).abs() < TRACE_STEP {
+                return;
+            }
+        }
+        self.points[len].0.store(time, Ordering::Relaxed);
+        self.points[len].1.store(level, Ordering::Relaxed);
+        self.len.store(len + 1, Ordering::Release);
+    }
+
+    /// Copy out the recorded points in the order they were played
+    pub fn snapshot(&self) -> Vec<(f32, f32)> {
+        let len = self.len.load(Ordering::Acquire);
+        self.points[..len]
+            .iter()
+            .map(|(time, level)| (time.load(Ordering::Relaxed), level.load(Ordering::Relaxed)))
+            .collect()
+    }
+}
+
+/// A single value written by the audio thread once per block and shown by the editor
+#[derive(Default)]
+pub struct Meter {
+    value: AtomicF32,
+}
+
+impl Meter {
+    pub fn new(value: f32) -> Self {
+        Self {
+            value: AtomicF32::new(value),
+        }
+    }
+
+    pub fn set(&self, value: f32) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> f32 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,35 @@
+use nih_plug::prelude::*;
+
+/// When modulators should be restarted based on the host's transport
+#[derive(Enum, Debug, PartialEq, Clone, Copy)]
+pub enum TransportRestart {
+    Off,
+    #[name = "playback start"]
+    PlaybackStart,
+    Bar,
+}
+
+/// Keeps track of the host's transport between process calls to detect playback starts and bar
+/// boundaries
+#[derive(Default)]
+pub struct TransportSync {
+    was_playing: bool,
+    last_bar: Option<i32>,
+}
+
+impl TransportSync {
+    /// Returns true if modulators should be restarted at the start of this process call
+    pub fn update(&mut self, transport: &Transport, mode: TransportRestart) -> bool {
+        let started = transport.playing && !self.was_playing;
+        let bar = transport.bar_number();
+        let new_bar = transport.playing && bar.is_some() && bar != self.last_bar;
+        self.was_playing = transport.playing;
+        self.last_bar = bar;
+
+        match mode {
+            TransportRestart::Off => false,
+            TransportRestart::PlaybackStart => started,
+            TransportRestart::Bar => started || new_bar,
+        }
+    }
+}
@@ -1,15 +1,477 @@
-use crate::{widgets::*, SynthyParams};
-use egui::{style::Margin, Context};
+use crate::{
+    autosave,
+    config::{Config, ThemeName},
+    dsp,
+    midi::{NoteQueue, QueuedNote},
+    modulation::ModTarget,
+    preset::{Operator, OperatorPreset, Preset, PRESET_EXTENSION},
+    telemetry::{MidiEvent, Telemetry},
+    widgets::*,
+    worker::Worker,
+    EnvelopeParams, Note, SynthyParams, Velocity,
+};
+use egui::{style::Margin, Align2, Color32, Context, FontId, Id, LayerId, Order, Ui};
 use nih_plug::prelude::*;
-use std::{pin::Pin, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Operator settings copied from any instance in this process
+static OPERATOR_CLIPBOARD: Mutex<Option<OperatorPreset>> = Mutex::new(None);
+
+/// State that lives as long as the editor window
+#[derive(Default)]
+pub(crate) struct EditorState {
+    /// The last preset that was saved or loaded, or the patch as of when the editor opened, used
+    /// to revert any changes made since
+    last_preset: Option<Preset>,
+    /// Computes the previews in the background
+    worker: Worker,
+    audition: Audition,
+    event_log: EventLog,
+    /// The user's config as of when the editor opened or the settings were last saved
+    config: Config,
+    /// The settings being edited while the settings window is open
+    settings: Option<Config>,
+    /// The name the current patch gets saved under
+    preset_name: String,
+    /// An existing preset file the user has to confirm overwriting before the patch gets saved
+    overwrite: Option<PathBuf>,
+    /// An autosave left behind by an instance that didn't shut down cleanly, until it's restored
+    /// or declined
+    orphan: Option<PathBuf>,
+}
+
+impl EditorState {
+    pub fn new() -> Self {
+        Self {
+            config: Config::current(),
+            orphan: autosave::find_orphan(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Editor settings that are saved with the plugin's state but don't affect the sound
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default)]
+pub struct UiState {
+    /// The zoom of every envelope editor, by envelope name
+    envelope_zoom: BTreeMap<String, f32>,
+    audition: AuditionSettings,
+    /// Show the host's beats behind the envelopes
+    beat_grid: bool,
+    /// IDs of the parameters the randomizer leaves alone
+    locked_params: BTreeSet<String>,
+    /// Whether the randomizer also generates new envelopes
+    randomize_envelopes: bool,
+}
+
+impl UiState {
+    fn envelope_zoom(&mut self, name: &str) -> &mut f32 {
+        self.envelope_zoom
+            .entry(name.to_owned())
+            .or_insert(crate::widgets::envelope::INITIAL_ZOOM)
+    }
+}
+
+/// What the editor plays when auditioning
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct AuditionSettings {
+    /// Play a note whenever a preset gets loaded
+    on_load: bool,
+    note: Note,
+    velocity: Velocity,
+    /// How long the note played after loading a preset lasts in seconds
+    length: f32,
+}
+
+impl Default for AuditionSettings {
+    fn default() -> Self {
+        Self {
+            on_load: false,
+            note: 60,
+            velocity: 100,
+            length: 0.5,
+        }
+    }
+}
+
+/// Playing notes from the editor, either with the audition button or automatically after loading
+/// a preset
+#[derive(Default)]
+struct Audition {
+    /// Whether the audition button is held down
+    held: bool,
+    /// The note that's currently sounding, so changing the settings can't leave it hanging
+    sounding: Option<Note>,
+    /// When the note played after loading a preset should be released
+    release_at: Option<Instant>,
+}
+
+impl Audition {
+    /// Queue a note on or off, returning whether it fit in the queue
+    fn play(&mut self, settings: &AuditionSettings, notes: &NoteQueue, on: bool) -> bool {
+        let velocity = settings.velocity;
+        let event = match on {
+            true => QueuedNote::On {
+                note: settings.note,
+                velocity,
+            },
+            false => QueuedNote::Off {
+                note: self.sounding.unwrap_or(settings.note),
+                velocity,
+            },
+        };
+        let queued = notes.push(event);
+        if queued {
+            self.sounding = on.then(|| settings.note);
+        }
+        queued
+    }
+
+    /// Play the note for the configured length, releasing the note that's still sounding first
+    /// so it can't get stuck
+    fn trigger(&mut self, settings: &AuditionSettings, notes: &NoteQueue) {
+        if self.sounding.is_some() && !self.play(settings, notes, false) {
+            return;
+        }
+        if self.play(settings, notes, true) {
+            self.release_at = Some(Instant::now() + Duration::from_secs_f32(settings.length));
+        }
+    }
+
+    /// Follow the audition button and release timed notes, called every frame. Anything that
+    /// doesn't fit in the queue gets retried on the next frame.
+    fn update(&mut self, settings: &AuditionSettings, notes: &NoteQueue, held: bool) {
+        if held != self.held && self.play(settings, notes, held) {
+            self.held = held;
+            self.release_at = None;
+        }
+        if let Some(release_at) = self.release_at {
+            if Instant::now() >= release_at && self.play(settings, notes, false) {
+                self.release_at = None;
+            }
+        }
+    }
+}
+
+/// Number of entries kept in the event log
+const EVENT_LOG_LEN: usize = 200;
+/// Meter readings below this don't visibly move anything, so they don't keep the editor redrawing
+const METER_FLOOR: f32 = 1e-4;
+
+/// A parameter change or MIDI event in the event log
+struct LogEntry {
+    /// When it happened, in seconds since the editor opened
+    time: f64,
+    /// The ID of the parameter that changed, for MIDI events this is none
+    param: Option<&'static str>,
+    text: String,
+}
+
+/// Recent parameter changes and MIDI events, to find out why a patch changed or to check a MIDI
+/// mapping. Parameter changes are found by comparing every parameter with the previous frame, so
+/// this catches changes from the host as well as from the editor.
+#[derive(Default)]
+struct EventLog {
+    /// Oldest first
+    entries: VecDeque<LogEntry>,
+    /// The normalized value of every parameter during the previous frame, in the order of the
+    /// parameter map
+    values: Vec<f32>,
+    /// Number of MIDI events read from the telemetry so far
+    midi_read: usize,
+}
+
+impl EventLog {
+    fn update(&mut self, params: Pin<&SynthyParams>, telemetry: &Telemetry, time: f64) {
+        let param_map = params.param_map();
+        let values: Vec<f32> = param_map
+            .iter()
+            .map(|(_, ptr)| unsafe { ptr.normalized_value() })
+            .collect();
+        if self.values.len() == values.len() {
+            for ((id, ptr), (old, new)) in param_map.iter().zip(self.values.iter().zip(&values)) {
+                if old == new {
+                    continue;
+                }
+                let text = unsafe {
+                    format!(
+                        "{}: {}",
+                        ptr.name(),
+                        ptr.normalized_value_to_string(*new, true)
+                    )
+                };
+                // Dragging changes a parameter every frame, that only needs a single entry
+                match self.entries.back_mut() {
+                    Some(last) if last.param == Some(*id) => {
+                        last.time = time;
+                        last.text = text;
+                    }
+                    _ => self.push(time, Some(*id), text),
+                }
+            }
+        }
+        self.values = values;
+
+        for event in telemetry.midi_log.read_since(&mut self.midi_read) {
+            let MidiEvent {
+                on,
+                channel,
+                note,
+                velocity,
+                accepted,
+            } = event;
+            let kind = match on {
+                true => "note on",
+                false => "note off",
+            };
+            let filtered = match accepted {
+                true => "",
+                false => " (filtered)",
+            };
+            let text = format!(
+                "{} {} velocity {} channel {}{}",
+                kind,
+                note,
+                velocity,
+                channel + 1,
+                filtered
+            );
+            self.push(time, None, text);
+        }
+    }
+
+    fn push(&mut self, time: f64, param: Option<&'static str>, text: String) {
+        if self.entries.len() == EVENT_LOG_LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LogEntry { time, param, text });
+    }
+}
 
 #[inline]
-pub(crate) fn ui(egui_ctx: &Context, params: Pin<Arc<SynthyParams>>, setter: &ParamSetter) {
+pub(crate) fn ui(
+    egui_ctx: &Context,
+    params: Pin<Arc<SynthyParams>>,
+    layer: Pin<&SynthyParams>,
+    telemetry: &Telemetry,
+    notes: &NoteQueue,
+    snap_params: &AtomicBool,
+    setter: &ParamSetter,
+    state: &mut EditorState,
+) {
     let margin = 16f32;
+    let envelopes = params.envelopes();
+    let mod_snapshot = &telemetry.modulation;
+    let vibrato_depth_mod = mod_snapshot.get(ModTarget::VibratoDepth);
+
+    // Edited on a copy so the plugin's state doesn't stay locked for the whole frame
+    let mut ui_state = params
+        .ui_state
+        .read()
+        .map(|ui_state| ui_state.clone())
+        .unwrap_or_default();
+    let old_ui_state = ui_state.clone();
+    ParamLocks::new(params.param_map(), ui_state.locked_params.clone()).store(egui_ctx);
+    let theme = Theme::named(state.config.theme);
+    theme.store(egui_ctx, state.config.theme);
+    // Set every frame since the window may pass its own scale along with its input
+    egui_ctx.set_pixels_per_point(state.config.ui_scale);
+
+    // Every time the editor opens it starts out with a fresh context
+    let opened_id = Id::new("editor opened");
+    if egui_ctx.memory().data.get_temp::<bool>(opened_id).is_none() {
+        egui_ctx.memory().data.insert_temp(opened_id, true);
+        state.last_preset = Some(Preset::capture(params.as_ref()));
+    }
+
+    handle_file_drops(
+        egui_ctx,
+        params.as_ref(),
+        layer,
+        notes,
+        snap_params,
+        setter,
+        state,
+        &ui_state.audition,
+    );
+
+    recovery_panel(
+        egui_ctx,
+        params.as_ref(),
+        layer,
+        snap_params,
+        setter,
+        state,
+        &theme,
+    );
+
+    egui::TopBottomPanel::top("header")
+        .frame(
+            egui::Frame::default()
+                .fill(theme.colors.background_light)
+                .margin(Margin::symmetric(margin, margin / 2f32)),
+        )
+        .show(egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.add(Knob::from_param(&params.fx_bypass, setter));
+                ui.add(Knob::from_param(&params.safe_mode, setter));
+                ui.add(Knob::from_param(&params.mod_amount, setter));
+                ui.add(Knob::from_param(&params.quality, setter));
+                ui.add(Knob::from_param(&params.offline_quality, setter));
+                ui.checkbox(&mut ui_state.beat_grid, "beat grid")
+                    .on_hover_text("show the host's beats behind the envelopes");
+                // The note plays for as long as the button is held
+                let held = ui.button("audition").is_pointer_button_down_on();
+                let audition = &mut ui_state.audition;
+                state.audition.update(audition, notes, held);
+                ui.checkbox(&mut audition.on_load, "on load")
+                    .on_hover_text("play the audition note whenever a preset gets loaded");
+                ui.add(
+                    egui::DragValue::new(&mut audition.note)
+                        .clamp_range(0..=127)
+                        .prefix("note "),
+                );
+                ui.add(
+                    egui::DragValue::new(&mut audition.velocity)
+                        .clamp_range(1..=127)
+                        .prefix("vel "),
+                );
+                ui.add_enabled(
+                    audition.on_load,
+                    egui::DragValue::new(&mut audition.length)
+                        .clamp_range(0.05..=5.0)
+                        .speed(0.01)
+                        .suffix("s"),
+                );
+                if ui.button("init patch").clicked() {
+                    let preset = Preset::init();
+                    load_preset(&preset, params.as_ref(), layer, snap_params, setter);
+                    state.last_preset = Some(preset);
+                }
+                if ui
+                    .button("randomize")
+                    .on_hover_text("randomize every parameter that isn't locked")
+                    .clicked()
+                {
+                    let seed = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|time| time.subsec_nanos())
+                        .unwrap_or_default();
+                    let preset = Preset::capture(params.as_ref()).randomized(
+                        &ui_state.locked_params,
+                        seed,
+                        ui_state.randomize_envelopes,
+                    );
+                    load_preset(&preset, params.as_ref(), layer, snap_params, setter);
+                }
+                ui.checkbox(&mut ui_state.randomize_envelopes, "envelopes")
+                    .on_hover_text("randomize the envelopes as well");
+                if ui
+                    .add_enabled(state.last_preset.is_some(), egui::Button::new("revert"))
+                    .on_hover_text("reload the last saved or loaded preset")
+                    .clicked()
+                {
+                    if let Some(preset) = &state.last_preset {
+                        load_preset(preset, params.as_ref(), layer, snap_params, setter);
+                    }
+                }
+                ui.add(
+                    egui::TextEdit::singleline(&mut state.preset_name)
+                        .hint_text("preset name")
+                        .desired_width(96f32),
+                );
+                let path = preset_path(&state.config.preset_dir, &state.preset_name);
+                if ui
+                    .add_enabled(path.is_some(), egui::Button::new("save"))
+                    .on_hover_text(format!("save to {}", state.config.preset_dir.display()))
+                    .clicked()
+                {
+                    if let Some(path) = path {
+                        match path.exists() {
+                            true => state.overwrite = Some(path),
+                            false => save_preset(params.as_ref(), &path, state),
+                        }
+                    }
+                }
+                if ui.button("settings").clicked() && state.settings.is_none() {
+                    state.settings = Some(state.config.clone());
+                }
+            });
+        });
+
+    settings_window(egui_ctx, state);
+    overwrite_window(egui_ctx, params.as_ref(), state);
+
+    // The scope, meters and modulation overlays only move while a note plays or rings out, and
+    // timed audition notes need new frames to get released
+    let animating = telemetry.active_voices() > 0
+        || telemetry.peaks.iter().any(|peak| peak.get() > METER_FLOOR)
+        || telemetry.gain_reduction.get() > METER_FLOOR
+        || state.audition.release_at.is_some();
+    if animating {
+        egui_ctx.request_repaint();
+    }
+    let scope_frames = telemetry.scope.snapshot();
+
+    let time = egui_ctx.input().time;
+    state.event_log.update(params.as_ref(), telemetry, time);
+    egui::TopBottomPanel::bottom("footer")
+        .frame(
+            egui::Frame::default()
+                .fill(theme.colors.background_light)
+                .margin(Margin::symmetric(margin, margin / 2f32)),
+        )
+        .show(egui_ctx, |ui| {
+            egui::CollapsingHeader::new("patch notes").show(ui, |ui| {
+                // Edited on a copy like the editor's state, and only written back when changed
+                let mut notes = params
+                    .notes
+                    .read()
+                    .map(|notes| notes.clone())
+                    .unwrap_or_default();
+                let response = ui.add(
+                    egui::TextEdit::multiline(&mut notes)
+                        .hint_text("how to play this patch, what the mod wheel does, ...")
+                        .desired_width(f32::INFINITY)
+                        .desired_rows(4),
+                );
+                if response.changed() {
+                    if let Ok(mut stored) = params.notes.try_write() {
+                        *stored = notes;
+                    }
+                }
+            });
+            egui::CollapsingHeader::new("event log").show(ui, |ui| {
+                if ui.button("clear").clicked() {
+                    state.event_log.entries.clear();
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(120f32)
+                    .show(ui, |ui| {
+                        // Newest first
+                        for entry in state.event_log.entries.iter().rev() {
+                            ui.monospace(format!("{:>8.2}s {}", entry.time, entry.text));
+                        }
+                    });
+            });
+        });
+
     egui::CentralPanel::default()
         .frame(
             egui::Frame::default()
-                .fill(crate::widgets::Theme::default().colors.background)
+                .fill(theme.colors.background)
                 .margin(Margin::symmetric(margin, margin)),
         )
         .show(egui_ctx, |ui| {
@@ -18,14 +480,39 @@ pub(crate) fn ui(egui_ctx: &Context, params: Pin<Arc<SynthyParams>>, setter: &Pa
                     ui.horizontal(|ui| {
                         ui.add(Knob::from_param(&params.a_mod, setter));
                         ui.add_space(margin);
-                        ui.add(Knob::from_param(&params.a_ratio, setter));
+                        ui.add(
+                            Knob::from_param(&params.a_ratio_coarse, setter)
+                                .modulation(mod_snapshot.get(ModTarget::OpARatio)),
+                        );
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.a_ratio_fine, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.a_phase, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.a_retrigger, setter));
+                        ui.add_space(margin);
+                        let cycle = operator_preview(
+                            &mut state.worker,
+                            "op a preview",
+                            params.a_ratio(),
+                            params.a_mod.value,
+                        );
+                        ui.add(Waveform::new(cycle));
                     });
                     ui.add_space(margin);
-                    ui.add(
-                        Envelope::from_param(&params.a_env, "op a envelope")
-                            .size(ui.available_size()),
+                    envelope_editor(
+                        ui,
+                        &envelopes[0],
+                        0,
+                        setter,
+                        margin,
+                        &mut ui_state,
+                        telemetry,
                     );
-                });
+                })
+                .response
+                .interact(egui::Sense::click())
+                .context_menu(|ui| operator_menu(ui, &params, Operator::A, setter));
 
                 ui.add_space(margin);
                 ui.separator();
@@ -35,12 +522,87 @@ pub(crate) fn ui(egui_ctx: &Context, params: Pin<Arc<SynthyParams>>, setter: &Pa
                     ui.horizontal(|ui| {
                         ui.add(Knob::from_param(&params.b_mod, setter));
                         ui.add_space(margin);
-                        ui.add(Knob::from_param(&params.b_ratio, setter));
+                        ui.add(
+                            Knob::from_param(&params.b_ratio_coarse, setter)
+                                .modulation(mod_snapshot.get(ModTarget::OpBRatio)),
+                        );
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.b_ratio_fine, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.b_phase, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.b_retrigger, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.ext_fm, setter));
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.ext_level, setter));
+                        ui.add_space(margin);
+                        ui.add(
+                            Knob::from_param(&params.ring_mod, setter)
+                                .modulation(mod_snapshot.get(ModTarget::RingMod)),
+                        );
+                        ui.add_space(margin);
+                        ui.add(Knob::from_param(&params.sync, setter));
+                        ui.add_space(margin);
+                        let cycle = operator_preview(
+                            &mut state.worker,
+                            "op b preview",
+                            params.b_ratio(),
+                            params.b_mod.value,
+                        );
+                        ui.add(Waveform::new(cycle));
                     });
                     ui.add_space(margin);
+                    envelope_editor(
+                        ui,
+                        &envelopes[1],
+                        1,
+                        setter,
+                        margin,
+                        &mut ui_state,
+                        telemetry,
+                    );
+                })
+                .response
+                .interact(egui::Sense::click())
+                .context_menu(|ui| operator_menu(ui, &params, Operator::B, setter));
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Slider::from_param(&params.noise_amp, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_width, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_cutoff, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_key_track, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_filter_env, setter).bipolar(true));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_fm_depth, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.noise_fm_target, setter));
+                    ui.add_space(margin);
                     ui.add(
-                        Envelope::from_param(&params.b_env, "op b envelope")
-                            .size(ui.available_size()),
+                        Knob::from_param(&params.pluck, setter)
+                            .modulation(mod_snapshot.get(ModTarget::Pluck)),
+                    );
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.pluck_damping, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.pluck_decay, setter));
+                    ui.add_space(margin);
+                    envelope_editor(
+                        ui,
+                        &envelopes[2],
+                        2,
+                        setter,
+                        margin,
+                        &mut ui_state,
+                        telemetry,
                     );
                 });
 
@@ -49,19 +611,749 @@ pub(crate) fn ui(egui_ctx: &Context, params: Pin<Arc<SynthyParams>>, setter: &Pa
                 ui.add_space(margin);
 
                 ui.horizontal(|ui| {
-                    ui.add(Slider::from_param(&params.noise_amp, setter));
+                    ui.add(Knob::from_param(&params.vibrato_rate, setter));
                     ui.add_space(margin);
                     ui.add(
-                        Envelope::from_param(&params.noise_env, "noise envelope")
-                            .size(ui.available_size()),
+                        Knob::from_param(&params.vibrato_depth, setter)
+                            .modulation(vibrato_depth_mod),
                     );
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.vibrato_delay, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.vibrato_mod_wheel, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.transport_restart, setter));
+                    ui.add_space(margin);
+                    ui.add(Slider::from_param(&params.mod_wheel, setter).vertical());
+                    ui.add_space(margin);
+                    ui.add(Slider::from_param(&params.expression, setter).vertical());
                 });
 
                 ui.add_space(margin);
                 ui.separator();
                 ui.add_space(margin);
 
-                ui.add(Envelope::from_param(&params.env, "envelope").size(ui.available_size()));
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.filter_freq, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter_q, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter_drive, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter_routing, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter_placement, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter_balance, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter2_freq, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter2_q, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.filter2_mode, setter));
+                    ui.add_space(margin);
+                    ui.add(
+                        Knob::from_param(&params.filter2_vowel, setter)
+                            .modulation(mod_snapshot.get(ModTarget::Vowel)),
+                    );
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.eq_low_freq, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_low_q, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_low_gain, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_mid_freq, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_mid_q, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_mid_gain, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_high_freq, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_high_q, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_high_gain, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.eq_mix, setter));
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.comp_threshold, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_ratio, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_attack, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_release, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_makeup, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_mix, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.comp_lookahead, setter));
+                    ui.add_space(margin);
+                    ui.add(LevelMeter::new(telemetry.gain_reduction.get(), 24f32, "gr"));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.width, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.haas, setter));
+                    ui.add(Knob::from_param(&params.haas_time, setter));
+                    ui.add(Knob::from_param(&params.haas_amount, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.out_trim_left, setter));
+                    ui.add(Knob::from_param(&params.out_invert_left, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.out_trim_right, setter));
+                    ui.add(Knob::from_param(&params.out_invert_right, setter));
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.lfo_rate, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_shape, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_pulse_width, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_phase, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_unipolar, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_one_shot, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_fade_in, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_depth, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_curve, setter).bipolar(true));
+                    ui.add(Knob::from_param(&params.lfo_mute, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.lfo_destination, setter));
+                    ui.add_space(margin);
+                    let shape = params.lfo_shape.value();
+                    let pulse_width = params.lfo_pulse_width.value;
+                    let unipolar = params.lfo_unipolar.value;
+                    let inputs = [shape as usize as f32, pulse_width, unipolar as u8 as f32];
+                    let cycle = state.worker.get(Id::new("lfo preview"), &inputs, move || {
+                        dsp::preview::lfo_cycle(shape, pulse_width, unipolar, PREVIEW_LEN)
+                    });
+                    ui.add(Waveform::new(cycle));
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.midi_channel, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.key_low, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.key_high, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.velocity_low, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.velocity_high, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.velocity_curve, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.velocity_amp, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.release_velocity_scale, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.soft_pedal_amount, setter));
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.layer_mode, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.split_key, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.layer_balance, setter).bipolar(true));
+                    ui.add_space(margin);
+                    ui.vertical(|ui| {
+                        if ui
+                            .button("copy to layer")
+                            .on_hover_text("play a copy of this patch as the layer")
+                            .clicked()
+                        {
+                            store_layer(
+                                Preset::capture_layer(params.as_ref()),
+                                params.as_ref(),
+                                layer,
+                            );
+                            snap_params.store(true, Ordering::Relaxed);
+                        }
+                        if ui
+                            .button("swap layers")
+                            .on_hover_text("edit the layer patch, swapping it with this one")
+                            .clicked()
+                        {
+                            let main = Preset::capture_layer(params.as_ref());
+                            // Applying the layer patch leaves this patch without a layer until
+                            // the old main patch gets stored as the layer
+                            Preset::capture_layer(layer).apply(params.as_ref(), setter);
+                            store_layer(main, params.as_ref(), layer);
+                            snap_params.store(true, Ordering::Relaxed);
+                        }
+                    });
+                });
+
+                ui.add_space(margin);
+                ui.separator();
+                ui.add_space(margin);
+
+                ui.horizontal(|ui| {
+                    ui.add(Knob::from_param(&params.drift, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.variation, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.seed, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.max_voices, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.note_priority, setter));
+                    ui.add_space(margin);
+                    ui.add(Goniometer::new(&scope_frames));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.glide_time, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.glide_mode, setter));
+                    ui.add_space(margin);
+                    ui.add(Knob::from_param(&params.legato_glide, setter));
+                    ui.add_space(margin);
+                    ui.add(PitchDisplay::new(
+                        telemetry.pitch(),
+                        telemetry.glide_target(),
+                    ));
+                    ui.add_space(margin);
+                    envelope_editor(
+                        ui,
+                        &envelopes[3],
+                        3,
+                        setter,
+                        margin,
+                        &mut ui_state,
+                        telemetry,
+                    );
+                });
+            });
+        });
+
+    // Previews that were handed to the worker this frame or earlier only show up once the
+    // editor draws again
+    if state.worker.has_pending() {
+        egui_ctx.request_repaint();
+    }
+    if let Some(locks) = ParamLocks::load(egui_ctx) {
+        ui_state.locked_params = locks.locked().clone();
+    }
+    if ui_state != old_ui_state {
+        if let Ok(mut stored) = params.ui_state.write() {
+            *stored = ui_state;
+        }
+    }
+}
+
+/// An envelope's depth and destination knobs followed by its editor, which takes up the rest of
+/// the row. `index` is the envelope's index in [`SynthyParams::envelopes`].
+fn envelope_editor(
+    ui: &mut Ui,
+    envelope: &EnvelopeParams,
+    index: usize,
+    setter: &ParamSetter,
+    margin: f32,
+    ui_state: &mut UiState,
+    telemetry: &Telemetry,
+) {
+    if let Some(depth) = envelope.depth {
+        let bipolar = depth.preview_plain(0.0) < 0.0;
+        ui.add(Knob::from_param(depth, setter).bipolar(bipolar));
+        ui.add_space(margin);
+    }
+    ui.add(Knob::from_param(envelope.destination, setter));
+    ui.add_space(margin);
+    ui.add(Knob::from_param(envelope.curve, setter).bipolar(true));
+    ui.add(Knob::from_param(envelope.mute, setter));
+    ui.add_space(margin);
+    // The points can't be edited directly while the ADSR settings generate them
+    let adsr = ui
+        .vertical(|ui| {
+            template_menu(ui, envelope);
+            adsr_controls(ui, envelope)
+        })
+        .inner;
+    ui.add_space(margin);
+    let trace = telemetry.envelope_traces[index].snapshot();
+    let beat_grid = telemetry.tempo().filter(|_| ui_state.beat_grid);
+    let mut editor = Envelope::from_param(envelope.points, envelope.name)
+        .length(envelope.length)
+        .timing(envelope.timing)
+        .locks(envelope.locks)
+        .persist_zoom(ui_state.envelope_zoom(envelope.name))
+        .trace(&trace)
+        .playhead(telemetry.envelope_position(index))
+        .size(ui.available_size());
+    if let Some(tempo) = beat_grid {
+        editor = editor.beat_grid(tempo);
+    }
+    ui.add_enabled(!adsr, editor);
+}
+
+/// A menu that resets an envelope to one of the templates, turning off its ADSR mode
+fn template_menu(ui: &mut Ui, envelope: &EnvelopeParams) {
+    ui.menu_button("template", |ui| {
+        for template in dsp::EnvelopeTemplate::ALL {
+            if !ui.button(template.name()).clicked() {
+                continue;
+            }
+            if let (Ok(mut points), Ok(mut timing), Ok(mut adsr)) = (
+                envelope.points.try_write(),
+                envelope.timing.try_write(),
+                envelope.adsr.try_write(),
+            ) {
+                template.apply(&mut points, &mut timing);
+                adsr.enabled = false;
+            }
+            ui.close_menu();
+        }
+    });
+}
+
+/// The toggle and settings of an envelope's ADSR mode, returns whether the mode is enabled
+fn adsr_controls(ui: &mut Ui, envelope: &EnvelopeParams) -> bool {
+    let stored = match envelope.adsr.read() {
+        Ok(adsr) => *adsr,
+        Err(_) => return false,
+    };
+    // An edit that couldn't be applied yet because the envelope was locked
+    let pending_id = Id::new(envelope.name).with("pending adsr");
+    let pending = ui.memory().data.get_temp::<dsp::Adsr>(pending_id);
+    let mut adsr = pending.unwrap_or(stored);
+    ui.vertical(|ui| {
+        ui.checkbox(&mut adsr.enabled, "adsr");
+        if adsr.enabled {
+            for (value, range, prefix, suffix) in [
+                (&mut adsr.attack, 0.001..=10.0, "a ", "s"),
+                (&mut adsr.decay, 0.001..=10.0, "d ", "s"),
+                (&mut adsr.sustain, 0.0..=1.0, "s ", ""),
+                (&mut adsr.release, 0.001..=30.0, "r ", "s"),
+            ] {
+                ui.add(
+                    egui::DragValue::new(value)
+                        .clamp_range(range)
+                        .speed(0.01)
+                        .prefix(prefix)
+                        .suffix(suffix),
+                );
+            }
+        }
+    });
+
+    if adsr != stored {
+        // Only keep the new settings once the points could be updated, otherwise the edit stays
+        // pending and gets retried on the next frame
+        let applied = (!adsr.enabled
+            || match (envelope.points.try_write(), envelope.timing.try_write()) {
+                (Ok(mut points), Ok(mut timing)) => {
+                    adsr.apply(&mut points, &mut timing);
+                    crate::widgets::envelope::clamp_points(&mut points, envelope.max_length());
+                    true
+                }
+                _ => false,
+            })
+            && match envelope.adsr.try_write() {
+                Ok(mut stored) => {
+                    *stored = adsr;
+                    true
+                }
+                Err(_) => false,
+            };
+        match applied {
+            true => ui.memory().data.remove::<dsp::Adsr>(pending_id),
+            false => {
+                ui.memory().data.insert_temp(pending_id, adsr);
+                ui.ctx().request_repaint();
+            }
+        }
+    } else if pending.is_some() {
+        ui.memory().data.remove::<dsp::Adsr>(pending_id);
+    }
+    adsr.enabled
+}
+
+/// Number of points in the operator previews
+const PREVIEW_LEN: usize = 128;
+
+/// A single cycle of the carrier as modulated by one operator. This is only recomputed when the
+/// operator's settings change.
+fn operator_preview<'a>(
+    worker: &'a mut Worker,
+    name: &str,
+    ratio: f32,
+    modulation: f32,
+) -> &'a [f32] {
+    worker.get(Id::new(name), &[ratio, modulation], move || {
+        dsp::preview::fm_cycle(ratio, modulation, PREVIEW_LEN)
+    })
+}
+
+/// Context menu for copying settings between operators
+fn operator_menu(ui: &mut Ui, params: &SynthyParams, operator: Operator, setter: &ParamSetter) {
+    let other = operator.other();
+    if ui.button(format!("copy to {}", other.name())).clicked() {
+        OperatorPreset::capture(params, operator).apply(params, other, setter);
+        ui.close_menu();
+    }
+    if ui.button("copy").clicked() {
+        if let Ok(mut clipboard) = OPERATOR_CLIPBOARD.lock() {
+            *clipboard = Some(OperatorPreset::capture(params, operator));
+        }
+        ui.close_menu();
+    }
+    let copied = OPERATOR_CLIPBOARD
+        .lock()
+        .ok()
+        .and_then(|clipboard| clipboard.clone());
+    if ui
+        .add_enabled(copied.is_some(), egui::Button::new("paste"))
+        .clicked()
+    {
+        if let Some(copied) = copied {
+            copied.apply(params, operator, setter);
+        }
+        ui.close_menu();
+    }
+}
+
+/// What happens when a file gets dropped onto the editor
+enum DropAction {
+    LoadPreset(PathBuf),
+    Unsupported(String),
+}
+
+impl DropAction {
+    fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_lowercase());
+        match extension.as_deref() {
+            Some(PRESET_EXTENSION) => Self::LoadPreset(path.to_path_buf()),
+            Some("scl") => Self::Unsupported(String::from("tunings aren't supported yet")),
+            Some(extension) => Self::Unsupported(format!("can't open .{} files", extension)),
+            None => Self::Unsupported(String::from("can't open this file")),
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            Self::LoadPreset(path) => format!(
+                "load preset {}",
+                path.file_stem().unwrap_or_default().to_string_lossy()
+            ),
+            Self::Unsupported(reason) => reason.clone(),
+        }
+    }
+}
+
+/// Offer to restore the autosave of an instance that didn't shut down cleanly, above the header
+fn recovery_panel(
+    egui_ctx: &Context,
+    params: Pin<&SynthyParams>,
+    layer: Pin<&SynthyParams>,
+    snap_params: &AtomicBool,
+    setter: &ParamSetter,
+    state: &mut EditorState,
+    theme: &Theme,
+) {
+    let path = match &state.orphan {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let mut done = false;
+    egui::TopBottomPanel::top("recovery")
+        .frame(
+            egui::Frame::default()
+                .fill(theme.colors.modulation)
+                .margin(Margin::symmetric(16f32, 4f32)),
+        )
+        .show(egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    theme.colors.background,
+                    "a previous session ended unexpectedly",
+                );
+                if ui.button("restore").clicked() {
+                    match Preset::load(&path) {
+                        Ok(preset) => {
+                            load_preset(&preset, params, layer, snap_params, setter);
+                            state.last_preset = Some(preset);
+                        }
+                        Err(err) => {
+                            log::error!("failed to restore {}: {}", path.display(), err)
+                        }
+                    }
+                    done = true;
+                }
+                done |= ui.button("discard").clicked();
+            });
+        });
+    if done {
+        autosave::remove_orphan(&path);
+        // Other instances might have gone down along with this one
+        state.orphan = autosave::find_orphan();
+    }
+}
+
+/// Where a preset called `name` gets saved in `dir`. Characters that can't be part of a file
+/// name are replaced and leading or trailing dots are dropped, so the name can't point outside of
+/// `dir`. `None` when nothing of the name is left.
+fn preset_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_matches('.');
+    (!name.is_empty()).then(|| dir.join(format!("{}.{}", name, PRESET_EXTENSION)))
+}
+
+/// Save the current patch to `path`, creating its directory if needed
+fn save_preset(params: Pin<&SynthyParams>, path: &Path, state: &mut EditorState) {
+    let preset = Preset::capture(params);
+    let saved = match path.parent() {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+    .and_then(|_| preset.save(path));
+    match saved {
+        Ok(()) => state.last_preset = Some(preset),
+        Err(err) => log::error!("failed to save preset {}: {}", path.display(), err),
+    }
+}
+
+/// Ask before a save replaces an existing preset
+fn overwrite_window(egui_ctx: &Context, params: Pin<&SynthyParams>, state: &mut EditorState) {
+    let path = match &state.overwrite {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let mut open = true;
+    let mut overwrite = false;
+    let mut cancel = false;
+    egui::Window::new("overwrite preset")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx, |ui| {
+            ui.label(format!("{} already exists", path.display()));
+            ui.horizontal(|ui| {
+                overwrite = ui.button("overwrite").clicked();
+                cancel = ui.button("cancel").clicked();
+            });
+        });
+
+    if overwrite {
+        save_preset(params, &path, state);
+    }
+    if overwrite || cancel || !open {
+        state.overwrite = None;
+    }
+}
+
+/// Edit the user's config while the settings are open, writing it to the config file on save
+fn settings_window(egui_ctx: &Context, state: &mut EditorState) {
+    let settings = match &mut state.settings {
+        Some(settings) => settings,
+        None => return,
+    };
+    let mut open = true;
+    let mut save = false;
+    egui::Window::new("settings")
+        .open(&mut open)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx, |ui| {
+            egui::Grid::new("settings grid").show(ui, |ui| {
+                ui.label("ui scale");
+                ui.add(
+                    egui::DragValue::new(&mut settings.ui_scale)
+                        .clamp_range(0.5..=3.0)
+                        .speed(0.01),
+                );
+                ui.end_row();
+
+                ui.label("theme");
+                egui::ComboBox::from_id_source("theme")
+                    .selected_text(settings.theme.name())
+                    .show_ui(ui, |ui| {
+                        for theme in ThemeName::ALL {
+                            ui.selectable_value(&mut settings.theme, theme, theme.name());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("preset folder");
+                let mut preset_dir = settings.preset_dir.display().to_string();
+                if ui.text_edit_singleline(&mut preset_dir).changed() {
+                    settings.preset_dir = PathBuf::from(preset_dir);
+                }
+                ui.end_row();
+
+                ui.label("envelope length");
+                ui.add(
+                    egui::DragValue::new(&mut settings.env_length)
+                        .clamp_range(crate::ENV_LENGTH_RANGE)
+                        .speed(0.1)
+                        .suffix("s"),
+                )
+                .on_hover_text("the maximum length of the envelopes in new instances");
+                ui.end_row();
+
+                ui.label("log level");
+                egui::ComboBox::from_id_source("log level")
+                    .selected_text(settings.log_level.as_str())
+                    .show_ui(ui, |ui| {
+                        for level in [
+                            log::LevelFilter::Off,
+                            log::LevelFilter::Error,
+                            log::LevelFilter::Warn,
+                            log::LevelFilter::Info,
+                            log::LevelFilter::Debug,
+                            log::LevelFilter::Trace,
+                        ] {
+                            ui.selectable_value(&mut settings.log_level, level, level.as_str());
+                        }
+                    })
+                    .response
+                    .on_hover_text("takes effect the next time the plugin is loaded");
+                ui.end_row();
+            });
+            ui.horizontal(|ui| {
+                save = ui.button("save").clicked();
+                if ui.button("defaults").clicked() {
+                    *settings = Config::default();
+                }
             });
         });
+
+    if save {
+        if let Err(err) = settings.store() {
+            log::error!("failed to save the config: {}", err);
+        }
+        state.config = settings.clone();
+    }
+    if save || !open {
+        state.settings = None;
+    }
+}
+
+/// Apply a preset and have the audio thread jump to its values instead of smoothing towards them
+fn load_preset(
+    preset: &Preset,
+    params: Pin<&SynthyParams>,
+    layer: Pin<&SynthyParams>,
+    snap_params: &AtomicBool,
+    setter: &ParamSetter,
+) {
+    preset.apply(params, setter);
+    restore_layer(params, layer);
+    snap_params.store(true, Ordering::Relaxed);
+}
+
+/// Bring the layer's parameters in line with the layer patch stored in `params`
+fn restore_layer(params: Pin<&SynthyParams>, layer: Pin<&SynthyParams>) {
+    if let Ok(patch) = params.layer.read() {
+        patch.restore(layer);
+    }
+}
+
+/// Store `patch` as the layer patch and start playing it
+fn store_layer(patch: Preset, params: Pin<&SynthyParams>, layer: Pin<&SynthyParams>) {
+    if let Ok(mut stored) = params.layer.write() {
+        *stored = patch;
+    }
+    restore_layer(params, layer);
+}
+
+/// Show an overlay while files are dragged over the editor and act on dropped files
+fn handle_file_drops(
+    egui_ctx: &Context,
+    params: Pin<&SynthyParams>,
+    layer: Pin<&SynthyParams>,
+    notes: &NoteQueue,
+    snap_params: &AtomicBool,
+    setter: &ParamSetter,
+    state: &mut EditorState,
+    audition: &AuditionSettings,
+) {
+    let (hovered, dropped): (Vec<_>, Vec<_>) = {
+        let input = egui_ctx.input();
+        (
+            input
+                .raw
+                .hovered_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect(),
+            input
+                .raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect(),
+        )
+    };
+
+    if let Some(path) = hovered.first() {
+        let theme = Theme::current(egui_ctx);
+        let screen = egui_ctx.input().screen_rect();
+        let painter = egui_ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("drop")));
+        painter.rect_filled(screen, 0f32, Color32::from_rgba_unmultiplied(0, 0, 0, 180));
+        painter.text(
+            screen.center(),
+            Align2::CENTER_CENTER,
+            DropAction::from_path(path).description(),
+            FontId::proportional(20f32),
+            theme.colors.primary,
+        );
+    }
+
+    for path in dropped {
+        match DropAction::from_path(&path) {
+            DropAction::LoadPreset(path) => match Preset::load(&path) {
+                Ok(preset) => {
+                    load_preset(&preset, params, layer, snap_params, setter);
+                    state.last_preset = Some(preset);
+                    if audition.on_load {
+                        state.audition.trigger(audition, notes);
+                    }
+                }
+                Err(err) => log::error!("failed to load preset {}: {}", path.display(), err),
+            },
+            DropAction::Unsupported(reason) => log::warn!("{}: {}", path.display(), reason),
+        }
+    }
 }
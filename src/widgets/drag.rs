@@ -4,6 +4,12 @@ use nih_plug::param::*;
 use super::{Orientation, ParamControl};
 
 const GRANULAR_DRAG_MULTIPLIER: f32 = 0.0015;
+/// How far the arrow keys move a focused control, as a normalized value
+const KEY_STEP: f32 = 0.01;
+/// The step while holding shift
+const FINE_KEY_STEP: f32 = 0.001;
+/// The step for page up and page down
+const LARGE_KEY_STEP: f32 = 0.1;
 
 fundsp::lazy_static::lazy_static! {
     static ref DRAG_NORMALIZED_START_VALUE_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
@@ -39,6 +45,25 @@ where
         self.setter().end_set_parameter(self.param());
     }
 
+    /// Move the value by `delta` as a single gesture. Stepped parameters snap back to where they
+    /// were on small steps, so the step grows until it reaches the next value.
+    fn nudge(&'a self, delta: f32) {
+        let param = self.param();
+        let current = param.normalized_value();
+        let mut delta = delta;
+        let target = loop {
+            let target = (current + delta).clamp(0.0, 1.0);
+            let snapped = param.preview_normalized(param.preview_plain(target));
+            if snapped != current || target == 0.0 || target == 1.0 || delta.abs() >= 1.0 {
+                break target;
+            }
+            delta *= 2.0;
+        };
+        self.begin_drag();
+        self.set_normalized_value(target);
+        self.end_drag();
+    }
+
     fn get_drag_normalized_start_value_memory(ui: &Ui) -> f32 {
         ui.memory()
             .data
@@ -96,12 +121,96 @@ where
         self.set_normalized_value((start_value + delta).clamp(0.0, 1.0));
     }
 
+    /// Keyboard control for a focused control. The arrow keys and page up and down adjust the
+    /// value, home and end go to the ends of the range, delete resets it and enter opens a field
+    /// to type a value into. The name and value are also reported for screen readers.
+    fn respond_to_keys(&'a self, ui: &mut Ui, response: &Response) {
+        let param = self.param();
+        response.widget_info(|| {
+            let mut info = WidgetInfo::slider(param.normalized_value() as f64, param.name());
+            info.current_text_value = Some(param.to_string());
+            info
+        });
+        if response.clicked() || response.drag_started() {
+            response.request_focus();
+        }
+
+        let entry_id = response.id.with("text entry");
+        let mut entry: Option<String> = ui.memory().data.get_temp(entry_id);
+        let mut open_entry = false;
+        if response.has_focus() && entry.is_none() {
+            ui.painter()
+                .rect_stroke(response.rect, 2f32, ui.visuals().selection.stroke);
+
+            let input = ui.input().clone();
+            let step = match input.modifiers.shift {
+                true => FINE_KEY_STEP,
+                false => KEY_STEP,
+            };
+            let delta = [
+                (Key::ArrowUp, step),
+                (Key::ArrowRight, step),
+                (Key::ArrowDown, -step),
+                (Key::ArrowLeft, -step),
+                (Key::PageUp, LARGE_KEY_STEP),
+                (Key::PageDown, -LARGE_KEY_STEP),
+                (Key::Home, -1.0),
+                (Key::End, 1.0),
+            ]
+            .into_iter()
+            .filter(|(key, _)| input.key_pressed(*key))
+            .map(|(_, delta)| delta)
+            .sum::<f32>();
+            if delta != 0.0 {
+                self.nudge(delta);
+            }
+            if input.key_pressed(Key::Delete) || input.key_pressed(Key::Backspace) {
+                self.begin_drag();
+                self.reset_param();
+                self.end_drag();
+            }
+            if input.key_pressed(Key::Enter) {
+                entry = Some(param.to_string());
+                open_entry = true;
+            }
+        }
+
+        // Typing in a value, enter applies it and escape or clicking elsewhere cancels
+        if let Some(mut text) = entry {
+            let edit = Area::new(entry_id)
+                .order(Order::Foreground)
+                .fixed_pos(response.rect.left_bottom())
+                .show(ui.ctx(), |ui| {
+                    ui.add(TextEdit::singleline(&mut text).desired_width(response.rect.width()))
+                })
+                .inner;
+            if open_entry {
+                edit.request_focus();
+            }
+            if edit.lost_focus() {
+                if ui.input().key_pressed(Key::Enter) {
+                    if let Some(normalized) = param.string_to_normalized_value(&text) {
+                        self.begin_drag();
+                        self.set_normalized_value(normalized);
+                        self.end_drag();
+                    }
+                }
+                ui.memory().data.remove::<String>(entry_id);
+                response.request_focus();
+            } else {
+                ui.memory().data.insert_temp(entry_id, text);
+            }
+        }
+    }
+
     fn respond_to_drags(
         &'a self,
         ui: &mut Ui,
         response: Response,
         orientation: Option<Orientation>,
     ) -> egui::Response {
+        self.respond_to_keys(ui, &response);
+
         if response.drag_started() {
             // When beginning a drag or dragging normally, reset the memory used to keep track of
             // our granular drag
@@ -124,6 +233,20 @@ where
 
             self.granular_drag(ui, response.drag_delta(), Some(speed), orientation);
 
+            // Show the exact value while fine dragging, along with where the drag started
+            if ui.input().modifiers.shift && response.dragged() {
+                let start = Self::get_drag_normalized_start_value_memory(ui);
+                let delta = (self.normalized_value() - start) * 100f32;
+                egui::show_tooltip_at_pointer(ui.ctx(), response.id.with("fine drag"), |ui| {
+                    ui.label(self.param().to_string());
+                    ui.small(format!(
+                        "from {} ({:+.1}%)",
+                        self.param().normalized_value_to_string(start, true),
+                        delta
+                    ));
+                });
+            }
+
             if response.double_clicked() {
                 self.reset_param();
             }
@@ -1,24 +1,120 @@
 use std::{
     ops::{AddAssign, RangeInclusive},
     rc::Rc,
-    sync::RwLock,
+    sync::{Arc, RwLock},
 };
 
 use super::theme::Theme;
+use crate::dsp::{EndpointLocks, EnvelopeShape, EnvelopeTiming};
 use egui::*;
 use nih_plug::prelude::*;
 
 const HINT_SIZE: f32 = 8f32;
+/// Fingers cover a lot more than a cursor, so points can be grabbed from further away by touch
+const TOUCH_HINT_SIZE: f32 = 20f32;
+/// New points snap onto the curve when the cursor is within this many pixels of it
+const SNAP_DISTANCE: f32 = 8f32;
 const BUMP_AMOUNT: f32 = 0.1f32;
+/// How much slower points move while holding alt
+const FINE_DRAG_FACTOR: f32 = 0.1f32;
+/// The arrow keys move the selected point by the visible area divided by this, shift nudges by
+/// a tenth of that
+const NUDGE_DIVISIONS: f32 = 20f32;
 const SCROLL_ZOOM_MULTIPLIER: f32 = 0.1f32;
-const INITIAL_ZOOM: f32 = 0.2f32;
+/// How fast scrolling zooms and pans the levels, per point scrolled
+const LEVEL_SCROLL_SPEED: f32 = 0.005f32;
+/// The smallest part of the level range that can be zoomed in on
+const MIN_LEVEL_VIEW: f32 = 0.05f32;
+/// Width of the bar showing which levels are in view while zoomed in on them
+const LEVEL_BAR_WIDTH: f32 = 3f32;
+/// Stiffness of the spring that animates jumps in the zoom
+const ZOOM_STIFFNESS: f32 = 300f32;
+/// Fraction of critical damping for that spring, just under 1 so it settles quickly without
+/// visibly bouncing
+const ZOOM_DAMPING: f32 = 0.9f32;
+/// Longest frame time the zoom animation steps by, so a stalled frame can't destabilize it
+const MAX_ANIMATION_DT: f32 = 1f32 / 30f32;
+pub const INITIAL_ZOOM: f32 = 0.2f32;
+/// Range of the maximum envelope length in seconds
+const LENGTH_RANGE: RangeInclusive<f32> = crate::ENV_LENGTH_RANGE;
+/// Range of the onset delay in seconds
+const DELAY_RANGE: RangeInclusive<f32> = 0f32..=10f32;
+/// Width of the maximum length field next to the zoom bar
+const LENGTH_FIELD_WIDTH: f32 = 48f32;
+/// Width of the zoom to fit and follow buttons in front of the zoom bar
+const BUTTON_WIDTH: f32 = 36f32;
+/// While following the playhead, the view zooms out once the playhead passes this fraction of it
+const FOLLOW_MARGIN: f32 = 0.9f32;
+/// Beat grid lines are spread out to at least this many pixels by leaving out subdivisions
+const MIN_GRID_SPACING: f32 = 8f32;
+/// The finest subdivision of a beat shown by the beat grid
+const GRID_SUBDIVISIONS: usize = 4;
 
 fundsp::lazy_static::lazy_static! {
     static ref CURRENT_ACTIVE_ID_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
 }
 
+/// The displayed zoom chasing the stored zoom on a damped spring, so jumps like resetting the zoom
+/// animate instead of snapping
+#[derive(Clone, Copy)]
+struct ZoomAnimation {
+    zoom: f32,
+    velocity: f32,
+}
+
+impl ZoomAnimation {
+    fn settled(zoom: f32) -> Self {
+        Self {
+            zoom,
+            velocity: 0f32,
+        }
+    }
+
+    /// Advance the animation by `dt` seconds towards `target`, returns whether it's still moving
+    fn step(&mut self, target: f32, dt: f32) -> bool {
+        let damping = 2f32 * ZOOM_STIFFNESS.sqrt() * ZOOM_DAMPING;
+        let acceleration = (target - self.zoom) * ZOOM_STIFFNESS - self.velocity * damping;
+        self.velocity += acceleration * dt;
+        self.zoom += self.velocity * dt;
+        let moving = (target - self.zoom).abs() > 1e-4 || self.velocity.abs() > 1e-3;
+        if !moving {
+            *self = Self::settled(target);
+        }
+        moving
+    }
+}
+
+/// An envelope's points and curve in screen coordinates along with everything they were mapped
+/// from. This is kept between frames behind an `Arc`, so reading it back doesn't copy it.
+#[derive(Default)]
+struct MappedEnvelope {
+    points: Vec<(f32, f32)>,
+    timing: EnvelopeTiming,
+    max_length: f32,
+    zoom: f32,
+    range: (f32, f32),
+    rect: Rect,
+    screen_points: Vec<Pos2>,
+    curve: Vec<Pos2>,
+}
+
 pub struct Envelope<'a> {
     param: &'a RwLock<Vec<(f32, f32)>>,
+    /// Maximum length of the envelope in seconds
+    length: Option<&'a RwLock<f32>>,
+    /// Onset delay and hold point
+    timing: Option<&'a RwLock<EnvelopeTiming>>,
+    /// Where the zoom is kept when it shouldn't live in the editor's memory
+    zoom_state: Option<&'a mut f32>,
+    /// Levels the envelope actually put out, drawn faintly behind the points
+    trace: &'a [(f32, f32)],
+    /// The tempo in beats per minute to draw a beat grid for
+    beat_grid: Option<f32>,
+    /// Where the playing note currently is on the envelope's time axis
+    playhead: Option<f32>,
+    pub locks: EndpointLocks,
+    /// The levels from the bottom to the top of the editor
+    pub range: RangeInclusive<f32>,
     pub size: Vec2,
     pub node_size: f32,
     pub stroke_width: f32,
@@ -32,65 +128,162 @@ pub struct Envelope<'a> {
 }
 
 impl<'a> Widget for Envelope<'a> {
-    fn ui(self, ui: &mut Ui) -> Response {
+    fn ui(mut self, ui: &mut Ui) -> Response {
         ui.vertical(|ui| {
             let theme = match self.theme.as_ref() {
                 Some(theme) => *theme.clone(),
-                None => Theme::default(),
+                None => Theme::current(ui.ctx()),
+            };
+            let max_length = self
+                .length
+                .and_then(|length| length.read().ok().map(|length| *length));
+            // Never zoom out further than the maximum length
+            let min_zoom = max_length
+                .map(|length| 1f32 / length)
+                .unwrap_or(*self.zoom_range.start());
+            let stored_zoom = match &self.zoom_state {
+                Some(zoom) => Some(**zoom),
+                None => ui.memory().data.get_temp::<f32>(self.id),
             };
-            let zoom = ui
+            let target_zoom = stored_zoom.unwrap_or(self.initial_zoom).max(min_zoom);
+            let mut new_zoom = None;
+            // Scrolling already zooms in small steps, so it skips the animation
+            let mut snap_zoom = false;
+
+            // Everything is drawn at the animated zoom, while changes apply to the target
+            let animation_id = self.id.with("zoom animation");
+            let mut animation = ui
                 .memory()
                 .data
-                .get_temp::<f32>(self.id)
-                .unwrap_or(self.initial_zoom);
+                .get_temp(animation_id)
+                .unwrap_or_else(|| ZoomAnimation::settled(target_zoom));
+            if animation.step(target_zoom, ui.input().stable_dt.min(MAX_ANIMATION_DT)) {
+                ui.ctx().request_repaint();
+            }
+            ui.memory().data.insert_temp(animation_id, animation);
+            let zoom = animation.zoom;
             let current_node_id: Option<usize> =
                 ui.memory().data.get_temp(*CURRENT_ACTIVE_ID_MEMORY_ID);
+            let selected_id = self.id.with("selected");
             let paint_node = |pos, painter: &Painter, color| {
                 let r = Rect::from_center_size(pos, Vec2::splat(self.node_size));
                 painter.rect_filled(r, 0f32, color);
             };
 
-            // Convert param point coordinates to absolute UI coordinates for use in egui
-            let to_screen_point = |(x, y): &(f32, f32), rect: Rect| -> Pos2 {
-                let x = ((x * zoom) * rect.width()) + rect.left();
-                let y = (-y * rect.height()) + rect.bottom();
-                Pos2::new(x, y)
-            };
+            // The levels in view, which can be zoomed in on separately from the time axis
+            let level_view_id = self.id.with("level view");
+            let (min_level, max_level) = clamp_level_view(
+                ui.memory()
+                    .data
+                    .get_temp(level_view_id)
+                    .unwrap_or((*self.range.start(), *self.range.end())),
+                &self.range,
+            );
+            let level_span = max_level - min_level;
 
-            // Convert absolute egui coordinates into param point coordinates
-            let from_screen_point = |pos: Pos2, rect: Rect| {
-                let relative = pos - rect.left_top();
-                let x = (relative.x / zoom) / rect.width();
-                let y = (-relative.y / rect.height()) + 1f32;
-                (x, y)
-            };
+            let levels = (min_level, max_level);
+            let to_screen_point =
+                |point: &(f32, f32), rect: Rect| to_screen(*point, rect, zoom, levels);
+            let from_screen_point = |pos: Pos2, rect: Rect| from_screen(pos, rect, zoom, levels);
 
             let (response, paint) =
                 ui.allocate_painter(self.size - Vec2::new(0f32, 16f32), Sense::click_and_drag());
 
             let rect = response.rect;
 
-            // Get the on-screen coordinates of every point
-            let points: Vec<Pos2> = if let Ok(param) = self.param.read() {
-                param.iter().map(|pos| to_screen_point(pos, rect)).collect()
-            } else {
-                Vec::default()
+            // Map the points and the curve to the screen. The curve is evaluated the same way the
+            // audio thread does, at every pixel and at every visible point so corners stay sharp.
+            // That adds up for long envelopes, so this is only redone when the points or the view
+            // change.
+            let mapped_id = self.id.with("mapped");
+            let mapped = match self.param.read() {
+                Ok(param) => {
+                    let shape = self.shape(&param, max_length);
+                    let cached = ui
+                        .memory()
+                        .data
+                        .get_temp::<Arc<MappedEnvelope>>(mapped_id)
+                        .filter(|mapped| {
+                            mapped.points == *param
+                                && mapped.timing == shape.timing
+                                && mapped.max_length == shape.max_length
+                                && mapped.zoom == zoom
+                                && mapped.range == (min_level, max_level)
+                                && mapped.rect == rect
+                        });
+                    match cached {
+                        Some(mapped) => mapped,
+                        None => {
+                            let visible = from_screen_point(rect.right_top(), rect).0;
+                            let mut times: Vec<f32> = (0..=rect.width() as usize)
+                                .map(|x| {
+                                    from_screen_point(
+                                        rect.left_top() + Vec2::new(x as f32, 0f32),
+                                        rect,
+                                    )
+                                    .0
+                                })
+                                .chain(
+                                    param
+                                        .iter()
+                                        .map(|(time, _)| time.min(shape.max_length))
+                                        .filter(|time| *time <= visible),
+                                )
+                                .collect();
+                            times.sort_by(|a, b| {
+                                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+                            });
+                            let mapped = Arc::new(MappedEnvelope {
+                                points: param.clone(),
+                                timing: shape.timing,
+                                max_length: shape.max_length,
+                                zoom,
+                                range: (min_level, max_level),
+                                rect,
+                                screen_points: param
+                                    .iter()
+                                    .map(|point| to_screen_point(point, rect))
+                                    .collect(),
+                                curve: times
+                                    .into_iter()
+                                    .filter_map(|time| {
+                                        shape
+                                            .level(time, None)
+                                            .map(|level| to_screen_point(&(time, level), rect))
+                                    })
+                                    .collect(),
+                            });
+                            ui.memory().data.insert_temp(mapped_id, mapped.clone());
+                            mapped
+                        }
+                    }
+                }
+                Err(_) => Arc::default(),
             };
+            // The on-screen coordinates of every point
+            let points = mapped.screen_points.as_slice();
+            let selected: Option<usize> = ui
+                .memory()
+                .data
+                .get_temp(selected_id)
+                .filter(|selected| *selected < points.len());
 
+            let hint_size = match ui.input().any_touches() {
+                true => TOUCH_HINT_SIZE,
+                false => HINT_SIZE,
+            };
             let hovered_point: Option<(usize, Pos2)> =
                 if let Some(pos) = ui.input().pointer.interact_pos() {
-                    let mut closest: Vec<(usize, Pos2)> = points
+                    points
                         .iter()
+                        .copied()
                         .enumerate()
-                        .filter(|(_, p)| pos.distance_sq(**p) <= f32::powi(HINT_SIZE, 2))
-                        .map(|x| (x.0, *x.1))
-                        .collect::<Vec<(usize, Pos2)>>();
-                    closest.sort_by(|(_, a), (_, b)| {
-                        pos.distance_sq(*a)
-                            .partial_cmp(&pos.distance_sq(*b))
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                    });
-                    closest.first().cloned()
+                        .filter(|(_, p)| pos.distance_sq(*p) <= f32::powi(hint_size, 2))
+                        .min_by(|(_, a), (_, b)| {
+                            pos.distance_sq(*a)
+                                .partial_cmp(&pos.distance_sq(*b))
+                                .unwrap_or(std::cmp::Ordering::Equal)
+                        })
                 } else {
                     None
                 };
@@ -100,6 +293,68 @@ impl<'a> Widget for Envelope<'a> {
 
             // TODO: Paint tickmarks
 
+            // Show which part of the levels is in view while zoomed in on them
+            let range_span = self.range.end() - self.range.start();
+            if level_span < range_span {
+                let to_bar = |level: f32| {
+                    rect.bottom() - (level - self.range.start()) / range_span * rect.height()
+                };
+                paint.rect_filled(
+                    Rect::from_x_y_ranges(
+                        rect.right() - LEVEL_BAR_WIDTH..=rect.right(),
+                        to_bar(max_level)..=to_bar(min_level),
+                    ),
+                    0f32,
+                    theme.colors.border,
+                );
+            }
+
+            // Bipolar envelopes get a line at zero to tell up from down
+            if min_level < 0f32 && max_level > 0f32 {
+                let y = to_screen_point(&(0f32, 0f32), rect).y;
+                paint.line_segment(
+                    [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+                    Stroke::new(1f32, theme.colors.border),
+                );
+            }
+
+            // Paint a grid of beats and their subdivisions, leaving out subdivisions until the
+            // lines are far enough apart
+            if let Some(tempo) = self.beat_grid.filter(|tempo| *tempo > 0f32) {
+                let subdivision = 60f32 / tempo / GRID_SUBDIVISIONS as f32;
+                let pixels_per_second = zoom * rect.width();
+                let mut step = 1usize;
+                while subdivision * step as f32 * pixels_per_second < MIN_GRID_SPACING {
+                    step *= 2;
+                }
+                let visible = rect.width() / pixels_per_second;
+                let lines = (visible / (subdivision * step as f32)).ceil() as usize;
+                for position in (0..=lines).map(|line| line * step) {
+                    let x = to_screen_point(&(position as f32 * subdivision, 0f32), rect).x;
+                    let alpha = match position % GRID_SUBDIVISIONS {
+                        0 => 0.5,
+                        _ => 0.2,
+                    };
+                    paint.line_segment(
+                        [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                        Stroke::new(1f32, theme.colors.border.linear_multiply(alpha)),
+                    );
+                }
+            }
+
+            // Paint what was actually played behind the drawn envelope
+            if self.trace.len() > 1 {
+                let trace = self
+                    .trace
+                    .iter()
+                    .map(|point| to_screen_point(point, rect))
+                    .collect();
+                paint.add(Shape::line(
+                    trace,
+                    Stroke::new(1f32, theme.colors.primary.linear_multiply(0.3)),
+                ));
+            }
+
             // Paint crosshairs
             if let Some(pos) = response.hover_pos() {
                 let stroke = Stroke::new(1f32, theme.colors.border);
@@ -119,20 +374,61 @@ impl<'a> Widget for Envelope<'a> {
                 )
             }
 
-            // Loop through points
-            let mut last_point = rect.left_bottom();
-            for point in &points {
+            // Paint the curve
+            if mapped.curve.len() > 1 {
+                paint.add(Shape::line(
+                    mapped.curve.clone(),
+                    Stroke::new(self.stroke_width, theme.colors.primary),
+                ));
+            }
+
+            if let Some(position) = self.playhead {
+                let x = to_screen_point(&(position, 0f32), rect).x;
+                if x <= rect.right() {
+                    paint.line_segment(
+                        [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                        Stroke::new(1f32, theme.colors.primary.linear_multiply(0.6)),
+                    );
+                }
+            }
+
+            // Mark the hold point with a line through the whole envelope
+            let hold = self
+                .timing
+                .and_then(|timing| timing.read().ok().and_then(|timing| timing.hold));
+            if let Some(point) = hold.and_then(|hold| points.get(hold)) {
                 paint.line_segment(
-                    [last_point, *point],
-                    Stroke::new(2f32, theme.colors.primary),
+                    [
+                        Pos2::new(point.x, rect.top()),
+                        Pos2::new(point.x, rect.bottom()),
+                    ],
+                    Stroke::new(1f32, theme.colors.primary),
                 );
-                last_point = *point;
             }
 
+            // Double click a point to toggle holding on it
+            if response.double_clicked() {
+                if let (Some(timing), Some((index, _))) = (self.timing, hovered_point) {
+                    if let Ok(mut timing) = timing.try_write() {
+                        timing.hold = match timing.hold {
+                            Some(hold) if hold == index => None,
+                            _ => Some(index),
+                        };
+                    }
+                }
+            }
+
+            // Points past the visible range aren't painted, and neither are points crowding the
+            // previous one unless they're being interacted with
+            let mut last_painted: Option<f32> = None;
             for (i, point) in points.iter().enumerate() {
                 let hovered = current_node_id
                     .map(|x| i == x)
                     .unwrap_or_else(|| hovered_point.map(|x| i == x.0).unwrap_or_default());
+                let visible = point.x <= rect.right() + self.node_size;
+                let crowded = last_painted
+                    .map(|x| point.x - x < self.node_size)
+                    .unwrap_or_default();
 
                 let color = match hovered {
                     true => Color32::RED,
@@ -143,55 +439,84 @@ impl<'a> Widget for Envelope<'a> {
                     ui.memory()
                         .data
                         .insert_temp(*CURRENT_ACTIVE_ID_MEMORY_ID, i);
+                    ui.memory().data.insert_temp(selected_id, i);
+                    response.request_focus();
+                }
+                if !visible || (crowded && !hovered && selected != Some(i)) {
+                    continue;
                 }
                 paint_node(*point, &paint, color);
+                last_painted = Some(point.x);
+                if selected == Some(i) && response.has_focus() {
+                    paint.rect_stroke(
+                        Rect::from_center_size(*point, Vec2::splat(self.node_size + 4f32)),
+                        0f32,
+                        Stroke::new(1f32, Color32::RED),
+                    );
+                }
+            }
+
+            // Read out the data coordinates under the cursor along with the hovered or dragged
+            // point
+            let cursor = response
+                .hover_pos()
+                .or_else(|| current_node_id.and_then(|_| ui.input().pointer.interact_pos()));
+            if let Some(pos) = cursor {
+                let (time, level) = from_screen_point(pos, rect);
+                let mut lines = vec![format!("cursor {:.2}s {:.2}", time, level)];
+                if let Ok(param) = self.param.read() {
+                    if let Some(value) = self.shape(&param, max_length).level(time, None) {
+                        lines.push(format!("curve {:.2}", value));
+                    }
+                    let point = current_node_id.or_else(|| hovered_point.map(|(i, _)| i));
+                    if let Some((x, y)) = point.and_then(|i| param.get(i)) {
+                        lines.push(format!("point {:.2}s {:.2}", x, y));
+                    }
+                }
+                paint.text(
+                    rect.left_top() + Vec2::splat(4f32),
+                    Align2::LEFT_TOP,
+                    lines.join("\n"),
+                    FontId::monospace(10f32),
+                    theme.colors.primary,
+                );
             }
 
             // Perform a drag on the node
             if let Some(saved_id) = current_node_id {
-                // First point always has coordinates of 0,0
-                if saved_id != 0 {
-                    // First, we'll need the coordinates of the previous and next node so we can ensure we do not generate an invalid envelope
-                    let (prev, next) = if let Ok(param) = self.param.read() {
-                        let prev = param.get(saved_id - 1).cloned();
-                        let next = param.get(saved_id + 1).cloned();
-                        (prev, next)
-                    } else {
-                        (None, None)
-                    };
-
-                    if let Ok(mut param) = self.param.try_write() {
-                        if let Some((x, y)) = param.get_mut(saved_id) {
-                            let dt = response.drag_delta() * Vec2::new(1.0 / zoom, -1.0);
-                            *x += dt.x / rect.width();
-                            *y += dt.y / rect.height();
-
-                            // if dragging past the x of a previous or next node... don't!
-                            if let Some(prev) = prev {
-                                if *x <= (prev.0 + BUMP_AMOUNT) {
-                                    *x = prev.0 + BUMP_AMOUNT;
-                                }
-                            }
-                            if let Some(next) = next {
-                                if *x >= (next.0 - BUMP_AMOUNT) {
-                                    *x = next.0 - BUMP_AMOUNT;
-                                }
-                            }
-
-                            // If the last node, ensure Y is 0
-                            if saved_id == points.len() - 1 {
-                                *y = 0f32;
-                            }
-
-                            *y = y.clamp(0f32, 1f32);
-                        }
-                    }
+                let mut dt = response.drag_delta() * Vec2::new(1.0 / zoom, -1.0);
+                // Shift locks the drag to the time axis, ctrl to the level axis, and alt slows
+                // it down for fine adjustments
+                let modifiers = ui.input().modifiers;
+                if modifiers.shift {
+                    dt.y = 0f32;
+                } else if modifiers.ctrl {
+                    dt.x = 0f32;
+                }
+                if modifiers.alt {
+                    dt *= FINE_DRAG_FACTOR;
                 }
+                self.move_point(
+                    saved_id,
+                    (dt.x / rect.width(), dt.y / rect.height() * level_span),
+                    max_length,
+                );
             } else if hovered_point.is_none() {
+                // Where a new point would go, snapped onto the curve when the cursor is close to it
+                let insert_pos = |pos: Pos2| -> Pos2 {
+                    let (time, _) = from_screen_point(pos, rect);
+                    self.param
+                        .read()
+                        .ok()
+                        .and_then(|param| self.shape(&param, max_length).level(time, None))
+                        .map(|level| to_screen_point(&(time, level), rect))
+                        .filter(|snapped| (snapped.y - pos.y).abs() <= SNAP_DISTANCE)
+                        .unwrap_or(pos)
+                };
+
                 // Hover style
                 if let Some(pos) = response.hover_pos() {
-                    // TODO: snap close to the line
-                    paint_node(pos, &paint, theme.colors.border);
+                    paint_node(insert_pos(pos), &paint, theme.colors.border);
                 }
 
                 // Click to add a point
@@ -221,9 +546,16 @@ impl<'a> Widget for Envelope<'a> {
                         if let (Some(left_point), Some(_right_point)) =
                             (left_egui_point, right_egui_point)
                         {
+                            let point = from_screen_point(insert_pos(pos), rect);
                             if let Ok(mut param) = self.param.try_write() {
-                                param.insert(left_point.0, from_screen_point(pos, response.rect));
-                                ui.memory().data.insert_temp(self.id, left_point.0);
+                                param.insert(left_point.0, point);
+                                self.update_timing(|timing| timing.point_inserted(left_point.0));
+                                // Keep dragging the new point until the button is released
+                                ui.memory()
+                                    .data
+                                    .insert_temp(*CURRENT_ACTIVE_ID_MEMORY_ID, left_point.0);
+                                ui.memory().data.insert_temp(selected_id, left_point.0);
+                                response.request_focus();
                             }
                         }
                     }
@@ -240,27 +572,168 @@ impl<'a> Widget for Envelope<'a> {
             if response.secondary_clicked() {
                 if let Some(current_node_id) = current_node_id {
                     if current_node_id != 0 && current_node_id != points.len() - 1 {
-                        if let Ok(mut param) = self.param.try_write() {
-                            param.remove(current_node_id);
-                        }
+                        self.remove_point(current_node_id);
+                        ui.memory().data.remove::<usize>(selected_id);
+                    }
+                }
+            }
+
+            // Keyboard editing of the selected point: the arrow keys nudge it, delete removes it
+            // and tab cycles through the points. Escape hands the focus back so tab moves on to
+            // the next widget again.
+            if response.has_focus() && ui.input().key_pressed(Key::Escape) {
+                response.surrender_focus();
+            } else if response.has_focus() {
+                // Keep tab from moving the focus to the next widget
+                ui.memory().lock_focus(response.id, true);
+                let input = ui.input().clone();
+                let step = match input.modifiers.shift {
+                    true => FINE_DRAG_FACTOR / NUDGE_DIVISIONS,
+                    false => 1f32 / NUDGE_DIVISIONS,
+                };
+                let nudge = [
+                    (Key::ArrowLeft, (-step / zoom, 0f32)),
+                    (Key::ArrowRight, (step / zoom, 0f32)),
+                    (Key::ArrowUp, (0f32, step * level_span)),
+                    (Key::ArrowDown, (0f32, -step * level_span)),
+                ]
+                .into_iter()
+                .filter(|(key, _)| input.key_pressed(*key))
+                .fold((0f32, 0f32), |(x, y), (_, (dx, dy))| (x + dx, y + dy));
+
+                if let Some(selected) = selected {
+                    if nudge != (0f32, 0f32) {
+                        self.move_point(selected, nudge, max_length);
+                    }
+                    let removable = selected != 0 && selected != points.len() - 1;
+                    if removable
+                        && (input.key_pressed(Key::Delete) || input.key_pressed(Key::Backspace))
+                    {
+                        self.remove_point(selected);
+                        ui.memory().data.insert_temp(selected_id, selected - 1);
                     }
                 }
+                if input.key_pressed(Key::Tab) && !points.is_empty() {
+                    let next = match (selected, input.modifiers.shift) {
+                        (Some(selected), false) => (selected + 1) % points.len(),
+                        (Some(selected), true) => {
+                            selected.checked_sub(1).unwrap_or(points.len() - 1)
+                        }
+                        (None, _) => 0,
+                    };
+                    ui.memory().data.insert_temp(selected_id, next);
+                }
             }
 
             // Respond to zooming
             if response.hovered() {
-                // Scroll zooming
-                let zoom_dt = (ui.input().zoom_delta() - 1f32) * SCROLL_ZOOM_MULTIPLIER;
-                if zoom_dt != 0f32 {
-                    let new_value =
-                        (zoom + zoom_dt).clamp(*self.zoom_range.start(), *self.zoom_range.end());
-                    ui.memory().data.insert_temp(self.id, new_value);
+                // Pinching zooms along with the fingers, scrolling zooms in small steps
+                let pinch = ui.input().multi_touch().map(|touch| touch.zoom_delta);
+                let zoomed = match pinch {
+                    Some(pinch) => target_zoom * pinch,
+                    None => target_zoom + (ui.input().zoom_delta() - 1f32) * SCROLL_ZOOM_MULTIPLIER,
+                };
+                if zoomed != target_zoom {
+                    new_zoom = Some(zoomed.clamp(min_zoom, *self.zoom_range.end()));
+                    snap_zoom = true;
+                }
+
+                // Scrolling with alt zooms the levels around the cursor, adding shift pans them
+                let (modifiers, scroll) = {
+                    let input = ui.input();
+                    (input.modifiers, input.scroll_delta.x + input.scroll_delta.y)
+                };
+                if let (true, Some(pos)) = (modifiers.alt && scroll != 0f32, response.hover_pos()) {
+                    let view = match modifiers.shift {
+                        true => {
+                            let offset = scroll * LEVEL_SCROLL_SPEED * level_span;
+                            (min_level + offset, max_level + offset)
+                        }
+                        false => {
+                            let anchor = from_screen_point(pos, rect).1;
+                            let scale = (-scroll * LEVEL_SCROLL_SPEED).exp();
+                            (
+                                anchor - (anchor - min_level) * scale,
+                                anchor + (max_level - anchor) * scale,
+                            )
+                        }
+                    };
+                    ui.memory()
+                        .data
+                        .insert_temp(level_view_id, clamp_level_view(view, &self.range));
                 }
             }
 
-            // Zoom bar interface
-            let (z_resp, z_paint) =
-                ui.allocate_painter(egui::Vec2::new(self.size.x, 16f32), Sense::click_and_drag());
+            // The longest zoom that still shows every point
+            let fit_zoom = self
+                .param
+                .read()
+                .ok()
+                .and_then(|param| param.last().map(|(time, _)| *time))
+                .map(|end| end.min(max_length.unwrap_or(f32::INFINITY)))
+                .filter(|end| *end > 0f32)
+                .map(|end| (1f32 / end).clamp(min_zoom, *self.zoom_range.end()));
+            let follow_id = self.id.with("follow");
+            let mut follow = ui.memory().data.get_temp(follow_id).unwrap_or(false);
+
+            // Zoom bar interface with the zoom buttons in front of it, and the maximum length and
+            // delay fields after it if there are any
+            let (z_resp, z_paint) = ui
+                .horizontal(|ui| {
+                    let spacing = ui.spacing().item_spacing.x;
+                    let fields = self.length.is_some() as usize + self.timing.is_some() as usize;
+                    let bar_width = self.size.x
+                        - fields as f32 * (LENGTH_FIELD_WIDTH + spacing)
+                        - 2f32 * (BUTTON_WIDTH + spacing);
+                    let button_size = Vec2::new(BUTTON_WIDTH, 16f32);
+                    if ui
+                        .add_sized(button_size, Button::new("fit"))
+                        .on_hover_text("zoom to show every point at every level")
+                        .clicked()
+                    {
+                        new_zoom = fit_zoom;
+                        ui.memory().data.remove::<(f32, f32)>(level_view_id);
+                    }
+                    if ui
+                        .add_sized(button_size, SelectableLabel::new(follow, "follow"))
+                        .on_hover_text("zoom out to keep the playhead in view")
+                        .clicked()
+                    {
+                        follow = !follow;
+                    }
+                    let zoom_bar = ui.allocate_painter(
+                        egui::Vec2::new(bar_width, 16f32),
+                        Sense::click_and_drag(),
+                    );
+                    if let (Some(length), Some(mut new_length)) = (self.length, max_length) {
+                        let changed = ui
+                            .add_sized(
+                                Vec2::new(LENGTH_FIELD_WIDTH, 16f32),
+                                DragValue::new(&mut new_length)
+                                    .clamp_range(LENGTH_RANGE)
+                                    .speed(0.1)
+                                    .suffix("s"),
+                            )
+                            .changed();
+                        if changed {
+                            self.set_length(length, new_length);
+                        }
+                    }
+                    if let Some(timing) = self.timing {
+                        if let Ok(mut timing) = timing.try_write() {
+                            ui.add_sized(
+                                Vec2::new(LENGTH_FIELD_WIDTH, 16f32),
+                                DragValue::new(&mut timing.delay)
+                                    .clamp_range(DELAY_RANGE)
+                                    .speed(0.01)
+                                    .suffix("s"),
+                            )
+                            .on_hover_text("delay before the envelope starts");
+                        }
+                    }
+                    zoom_bar
+                })
+                .inner;
 
             // zoom bar bg
             z_paint.rect_filled(z_resp.rect, 0f32, theme.colors.background_light);
@@ -273,7 +746,30 @@ impl<'a> Widget for Envelope<'a> {
             // click to zoom
             if let Some(click_pos) = z_resp.interact_pointer_pos() {
                 let ratio = 1f32 - (click_pos.x - bar_rect.left()) / bar_rect.width();
-                ui.memory().data.insert_temp(self.id, ratio);
+                new_zoom = Some(ratio.max(min_zoom));
+            }
+            // Zoom out when the playhead is about to leave the view
+            ui.memory().data.insert_temp(follow_id, follow);
+            if let (true, Some(position)) = (follow, self.playhead) {
+                if position * target_zoom > FOLLOW_MARGIN {
+                    new_zoom = Some((FOLLOW_MARGIN / position).clamp(min_zoom, target_zoom));
+                }
+            }
+
+            // double click to reset the zoom
+            if z_resp.double_clicked() {
+                new_zoom = Some(self.initial_zoom.max(min_zoom));
+            }
+            if let Some(new_zoom) = new_zoom {
+                match &mut self.zoom_state {
+                    Some(zoom) => **zoom = new_zoom,
+                    None => ui.memory().data.insert_temp(self.id, new_zoom),
+                }
+                if snap_zoom {
+                    ui.memory()
+                        .data
+                        .insert_temp(animation_id, ZoomAnimation::settled(new_zoom));
+                }
             }
 
             bar_rect.set_width(bar_rect.width() * (1f32 - normalized_zoom));
@@ -298,12 +794,187 @@ impl<'a> Envelope<'a> {
         self.initial_zoom = zoom;
         self
     }
+    /// Limit the envelope to a maximum length that can be edited next to the zoom bar
+    pub fn length(mut self, length: &'a RwLock<f32>) -> Self {
+        self.length = Some(length);
+        self
+    }
+
+    /// Show and edit the envelope's delay and hold point. Double clicking a point toggles the
+    /// hold on it.
+    pub fn timing(mut self, timing: &'a RwLock<EnvelopeTiming>) -> Self {
+        self.timing = Some(timing);
+        self
+    }
+
+    /// Keep the zoom in `zoom` instead of the editor's memory, so it can be saved along with the
+    /// plugin's state
+    pub fn persist_zoom(mut self, zoom: &'a mut f32) -> Self {
+        self.zoom_state = Some(zoom);
+        self
+    }
+
+    /// Draw the levels the envelope put out during the last note behind the points
+    pub fn trace(mut self, trace: &'a [(f32, f32)]) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Draw a grid of beats at `tempo` beats per minute, so times can be lined up with notes
+    pub fn beat_grid(mut self, tempo: f32) -> Self {
+        self.beat_grid = Some(tempo);
+        self
+    }
+
+    /// Mark where the playing note is on the envelope's time axis, if there is one
+    pub fn playhead(mut self, position: Option<f32>) -> Self {
+        self.playhead = position;
+        self
+    }
+
+    /// The levels the envelope can take, `0..=1` by default. Ranges going below zero draw a line
+    /// at zero, so bipolar envelopes like pitch envelopes can be edited.
+    pub fn range(mut self, range: RangeInclusive<f32>) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn locks(mut self, locks: EndpointLocks) -> Self {
+        self.locks = locks;
+        self
+    }
+
+    /// Move a point by `(time, level)`, keeping it between its neighbours and within the locks
+    fn move_point(&self, index: usize, (dx, dy): (f32, f32), max_length: Option<f32>) {
+        // The first point always starts at 0 seconds and is pinned to 0,0 when locked
+        if index == 0 && self.locks.start {
+            return;
+        }
+        if let Ok(mut param) = self.param.try_write() {
+            // We'll need the coordinates of the previous and next node so we can ensure we do
+            // not generate an invalid envelope
+            let prev = index.checked_sub(1).and_then(|i| param.get(i)).cloned();
+            let next = param.get(index + 1).cloned();
+            let last = param.len() - 1;
+            if let Some((x, y)) = param.get_mut(index) {
+                *x += dx;
+                *y += dy;
+
+                // if moving past the x of a previous or next node... don't!
+                if let Some(prev) = prev {
+                    if *x <= (prev.0 + BUMP_AMOUNT) {
+                        *x = prev.0 + BUMP_AMOUNT;
+                    }
+                }
+                if let Some(next) = next {
+                    if *x >= (next.0 - BUMP_AMOUNT) {
+                        *x = next.0 - BUMP_AMOUNT;
+                    }
+                }
+                if let Some(max_length) = max_length {
+                    *x = x.min(max_length);
+                }
+
+                if index == 0 {
+                    *x = 0f32;
+                }
+
+                // A locked last node always ends at 0
+                if index == last && self.locks.end {
+                    *y = 0f32;
+                }
+
+                *y = y.clamp(*self.range.start(), *self.range.end());
+            }
+        }
+    }
+
+    fn remove_point(&self, index: usize) {
+        if let Ok(mut param) = self.param.try_write() {
+            param.remove(index);
+            self.update_timing(|timing| timing.point_removed(index));
+        }
+    }
+
+    /// The envelope as the audio thread evaluates it
+    fn shape<'p>(&self, points: &'p [(f32, f32)], max_length: Option<f32>) -> EnvelopeShape<'p> {
+        EnvelopeShape {
+            points,
+            max_length: max_length.unwrap_or(f32::INFINITY),
+            timing: self
+                .timing
+                .and_then(|timing| timing.read().ok().map(|timing| *timing))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn update_timing(&self, update: impl FnOnce(&mut EnvelopeTiming)) {
+        if let Some(Ok(mut timing)) = self.timing.map(|timing| timing.try_write()) {
+            update(&mut timing);
+        }
+    }
+
+    /// Change the maximum length, pulling the points past the new length back inside it. Both
+    /// are changed under the same locks so the audio thread never sees points past the length.
+    /// The audio thread only holds them for short reads, so waiting for the locks is fine here.
+    fn set_length(&self, length: &RwLock<f32>, new: f32) {
+        if let (Ok(mut param), Ok(mut length)) = (self.param.write(), length.write()) {
+            clamp_points(&mut param, new);
+            *length = new;
+        }
+    }
+}
+
+/// Pull the points past `length` back inside it, keeping them in order and apart. Points within
+/// the length stay where they are unless they need to make room.
+pub fn clamp_points(points: &mut [(f32, f32)], length: f32) {
+    let mut end = length;
+    for (x, _) in points.iter_mut().rev() {
+        *x = x.min(end).max(0.0);
+        end = (*x - BUMP_AMOUNT).max(0.0);
+    }
+}
+
+/// Convert a point in seconds and level to absolute UI coordinates within `rect`. `zoom` is the
+/// part of the width a second takes up and `levels` are the levels in view from bottom to top.
+fn to_screen((time, level): (f32, f32), rect: Rect, zoom: f32, (bottom, top): (f32, f32)) -> Pos2 {
+    let level_span = (top - bottom).max(f32::EPSILON);
+    let x = time * zoom * rect.width() + rect.left();
+    let y = rect.bottom() - (level - bottom) / level_span * rect.height();
+    Pos2::new(x, y)
+}
+
+/// Convert absolute UI coordinates within `rect` to a point in seconds and level, the inverse of
+/// [`to_screen`]. A view without any width or levels maps everything onto its start.
+fn from_screen(pos: Pos2, rect: Rect, zoom: f32, (bottom, top): (f32, f32)) -> (f32, f32) {
+    let time_span = (zoom * rect.width()).max(f32::EPSILON);
+    let height = rect.height().max(f32::EPSILON);
+    let time = (pos.x - rect.left()) / time_span;
+    let level = (rect.bottom() - pos.y) / height * (top - bottom) + bottom;
+    (time, level)
+}
+
+/// Keep a view of the levels within `range` and no smaller than the smallest allowed zoom,
+/// moving it back inside rather than shrinking it
+fn clamp_level_view((bottom, top): (f32, f32), range: &RangeInclusive<f32>) -> (f32, f32) {
+    let range_span = range.end() - range.start();
+    let span = (top - bottom).clamp(range_span * MIN_LEVEL_VIEW, range_span);
+    let bottom = bottom.min(range.end() - span).max(*range.start());
+    (bottom, bottom + span)
 }
 
 impl<'a> Envelope<'a> {
     pub fn from_param(param: &'a RwLock<Vec<(f32, f32)>>, name: &'a str) -> Self {
         Self {
             param,
+            length: None,
+            timing: None,
+            zoom_state: None,
+            trace: &[],
+            beat_grid: None,
+            playhead: None,
+            locks: EndpointLocks::default(),
+            range: 0f32..=1f32,
             size: Vec2::new(100f32, 60f32),
             theme: None,
             initial_zoom: INITIAL_ZOOM,
@@ -315,3 +986,71 @@ impl<'a> Envelope<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect() -> Rect {
+        Rect::from_min_size(pos2(10.0, 20.0), vec2(200.0, 100.0))
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn maps_points_onto_the_rect() {
+        let pos = to_screen((1.0, 0.5), rect(), 0.25, (0.0, 1.0));
+        assert_close((pos.x, pos.y), (60.0, 70.0));
+        // The bottom of the view is at the bottom of the rect
+        let pos = to_screen((0.0, -1.0), rect(), 0.25, (-1.0, 1.0));
+        assert_close((pos.x, pos.y), (10.0, 120.0));
+        let pos = to_screen((0.0, 0.0), rect(), 0.25, (-1.0, 1.0));
+        assert_close((pos.x, pos.y), (10.0, 70.0));
+    }
+
+    #[test]
+    fn round_trips() {
+        for levels in [(0.0, 1.0), (-1.0, 1.0), (0.25, 0.4)] {
+            for zoom in [0.05, 0.2, 1.0] {
+                for point in [(0.0, levels.0), (0.5, 0.3), (3.0, levels.1), (7.5, -0.2)] {
+                    let pos = to_screen(point, rect(), zoom, levels);
+                    assert_close(from_screen(pos, rect(), zoom, levels), point);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_views_stay_finite() {
+        let pos = to_screen((1.0, 0.5), rect(), 0.2, (0.5, 0.5));
+        assert!(pos.x.is_finite() && pos.y.is_finite());
+        let (time, level) = from_screen(pos, rect(), 0.2, (0.5, 0.5));
+        assert!(time.is_finite());
+        assert_eq!(level, 0.5);
+
+        let empty = Rect::from_min_size(pos2(10.0, 20.0), Vec2::ZERO);
+        let (time, level) = from_screen(pos2(30.0, 10.0), empty, 0.0, (0.0, 1.0));
+        assert!(time.is_finite() && level.is_finite());
+    }
+
+    #[test]
+    fn shortening_only_moves_points_past_the_length() {
+        let mut points = vec![(0.0, 0.0), (0.5, 1.0), (2.0, 0.5), (4.0, 0.5), (6.0, 0.0)];
+        clamp_points(&mut points, 3.0);
+        assert_eq!(points[..3], [(0.0, 0.0), (0.5, 1.0), (2.0, 0.5)]);
+        assert_close(points[3], (2.9, 0.5));
+        assert_close(points[4], (3.0, 0.0));
+
+        // Lengthening leaves every point where it was
+        let mut longer = points.clone();
+        clamp_points(&mut longer, 10.0);
+        assert_eq!(longer, points);
+    }
+}
@@ -0,0 +1,60 @@
+use std::{f32::consts::FRAC_1_SQRT_2, rc::Rc};
+
+use super::theme::Theme;
+use egui::*;
+
+/// A Lissajous-style stereo scope. Mono signals show up as a vertical line, out of phase content
+/// spreads out horizontally.
+pub struct Goniometer<'a> {
+    frames: &'a [(f32, f32)],
+    pub size: f32,
+    pub theme: Option<Rc<Theme>>,
+}
+
+impl<'a> Goniometer<'a> {
+    pub fn new(frames: &'a [(f32, f32)]) -> Self {
+        Self {
+            frames,
+            size: 96f32,
+            theme: None,
+        }
+    }
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn theme(mut self, theme: Rc<Theme>) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+}
+
+impl<'a> Widget for Goniometer<'a> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let theme = match self.theme.as_ref() {
+            Some(theme) => *theme.clone(),
+            None => Theme::current(ui.ctx()),
+        };
+
+        let (response, paint) = ui.allocate_painter(Vec2::splat(self.size), Sense::hover());
+        let rect = response.rect;
+        let center = rect.center();
+        let radius = rect.width() * 0.5;
+
+        paint.rect_filled(rect, 0f32, theme.colors.background_light);
+        let stroke = Stroke::new(1f32, theme.colors.border);
+        paint.line_segment([rect.center_top(), rect.center_bottom()], stroke);
+        paint.line_segment([rect.left_center(), rect.right_center()], stroke);
+
+        // Rotate by 45 degrees so mid is vertical and side is horizontal
+        for (left, right) in self.frames {
+            let side = (left - right) * FRAC_1_SQRT_2;
+            let mid = (left + right) * FRAC_1_SQRT_2;
+            let pos = center
+                + Vec2::new(side, -mid).clamp(Vec2::splat(-1f32), Vec2::splat(1f32)) * radius;
+            paint.circle_filled(pos, 0.75f32, theme.colors.primary);
+        }
+
+        response
+    }
+}
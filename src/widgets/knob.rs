@@ -1,6 +1,8 @@
 use std::rc::Rc;
 
-use super::{drag::ParamDragWidget, theme::Theme, FloatParamControl, ParamControl};
+use super::{
+    drag::ParamDragWidget, lock::ParamLocks, theme::Theme, FloatParamControl, ParamControl,
+};
 use egui::{epaint::PathShape, *};
 use lyon_geom::{vector, Angle, Arc, Point};
 use nih_plug::prelude::*;
@@ -15,6 +17,11 @@ pub struct Knob<'a, P: Param> {
     pub show_label: bool,
     pub show_value: bool,
     pub show_value_normalized: bool,
+    /// Fill the arc from the center instead of the start. Detected from the parameter's default
+    /// value when not set.
+    pub bipolar: Option<bool>,
+    /// The normalized modulated value, drawn as a secondary arc
+    pub modulation: Option<f32>,
 }
 
 impl<'a, P> Knob<'a, P>
@@ -42,6 +49,22 @@ where
         self.show_value_normalized = normalized;
         self
     }
+    pub fn bipolar(mut self, bipolar: bool) -> Self {
+        self.bipolar = Some(bipolar);
+        self
+    }
+    pub fn modulation(mut self, normalized: Option<f32>) -> Self {
+        self.modulation = normalized;
+        self
+    }
+
+    /// Parameters that default to the middle of their range are treated as bipolar
+    fn is_bipolar(&self) -> bool {
+        self.bipolar.unwrap_or_else(|| {
+            let default = self.setter.default_normalized_param_value(self.param);
+            (default - 0.5).abs() < f32::EPSILON
+        })
+    }
 }
 
 impl<'a, P> Widget for Knob<'a, P>
@@ -51,7 +74,7 @@ where
     fn ui(self, ui: &mut Ui) -> Response {
         let theme = match self.theme.as_ref() {
             Some(theme) => *theme.clone(),
-            None => Theme::default(),
+            None => Theme::current(ui.ctx()),
         };
 
         let (response, paint) =
@@ -74,16 +97,30 @@ where
         .map(|p| egui::Pos2::new(p.x as f32, p.y as f32))
         .collect();
 
-        let mut offset_angle =
-            Angle::radians(std::f32::consts::TAU * self.param.normalized_value()) - (offset * 2f32);
-        if offset_angle < Angle::zero() {
-            offset_angle = Angle::zero()
-        }
+        let (control_start, control_sweep) = if self.is_bipolar() {
+            // Fill from 12 o'clock towards the current value
+            let track_sweep = Angle::two_pi() - (offset * 2f32);
+            let center_angle = track_sweep * 0.5;
+            let value_angle = track_sweep * self.param.normalized_value();
+            if value_angle < center_angle {
+                (start_angle + value_angle, center_angle - value_angle)
+            } else {
+                (start_angle + center_angle, value_angle - center_angle)
+            }
+        } else {
+            let mut offset_angle =
+                Angle::radians(std::f32::consts::TAU * self.param.normalized_value())
+                    - (offset * 2f32);
+            if offset_angle < Angle::zero() {
+                offset_angle = Angle::zero()
+            }
+            (start_angle, offset_angle)
+        };
         let control_arc: Vec<_> = Arc {
             center,
             radii,
-            start_angle,
-            sweep_angle: offset_angle,
+            start_angle: control_start,
+            sweep_angle: control_sweep,
             x_rotation: Angle::radians(0.),
         }
         .flattened(0.01)
@@ -99,6 +136,40 @@ where
             Stroke::new(self.track_width * 0.9, theme.colors.primary),
         ));
 
+        // Draw the modulation range between the set value and the modulated value
+        if let Some(modulation) = self.modulation {
+            let track_sweep = Angle::two_pi() - (offset * 2f32);
+            let value_angle = track_sweep * self.param.normalized_value();
+            let modulated_angle = track_sweep * modulation.clamp(0f32, 1f32);
+            let (from, to) = if modulated_angle < value_angle {
+                (modulated_angle, value_angle)
+            } else {
+                (value_angle, modulated_angle)
+            };
+            let modulation_arc: Vec<_> = Arc {
+                center,
+                radii: radii * 0.7,
+                start_angle: start_angle + from,
+                sweep_angle: to - from,
+                x_rotation: Angle::zero(),
+            }
+            .flattened(0.01)
+            .map(|p| egui::Pos2::new(p.x as f32, p.y as f32))
+            .collect();
+            paint.add(PathShape::line(
+                modulation_arc,
+                Stroke::new(self.track_width * 0.4, theme.colors.modulation),
+            ));
+        }
+
+        ParamLocks::show(
+            ui,
+            response.rect,
+            self.param.name(),
+            response.hovered(),
+            theme.colors.primary,
+        );
+
         ui.allocate_ui(Vec2::new(self.width, 0f32), |ui| {
             ui.vertical_centered(|ui| {
                 if self.show_value {
@@ -129,6 +200,8 @@ where
             show_value: true,
             show_label: true,
             show_value_normalized: false,
+            bipolar: None,
+            modulation: None,
         }
     }
 
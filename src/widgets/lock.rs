@@ -0,0 +1,104 @@
+use egui::*;
+use nih_plug::prelude::*;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+/// Size of the padlock in the corner of a control
+pub const LOCK_SIZE: f32 = 10f32;
+
+fundsp::lazy_static::lazy_static! {
+    static ref PARAM_LOCKS_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
+}
+
+/// The parameters that are excluded from randomizing. The editor puts these in egui's memory at
+/// the start of a frame so every control can show and toggle its own lock, and takes them back
+/// out at the end of the frame.
+#[derive(Clone, Default)]
+pub struct ParamLocks {
+    /// Parameter IDs by the parameter's name, since that's all a control knows about its
+    /// parameter. Every control gets its own copy of the locks, so this is shared.
+    ids: Arc<HashMap<String, String>>,
+    /// IDs of the locked parameters
+    locked: BTreeSet<String>,
+}
+
+impl ParamLocks {
+    pub fn new(
+        param_map: impl IntoIterator<Item = (&'static str, ParamPtr)>,
+        locked: BTreeSet<String>,
+    ) -> Self {
+        Self {
+            ids: Arc::new(
+                param_map
+                    .into_iter()
+                    .map(|(id, ptr)| (unsafe { ptr.name() }.to_owned(), id.to_owned()))
+                    .collect(),
+            ),
+            locked,
+        }
+    }
+
+    pub fn store(self, ctx: &Context) {
+        ctx.memory().data.insert_temp(*PARAM_LOCKS_MEMORY_ID, self);
+    }
+
+    pub fn load(ctx: &Context) -> Option<Self> {
+        ctx.memory().data.get_temp(*PARAM_LOCKS_MEMORY_ID)
+    }
+
+    /// The IDs of the locked parameters
+    pub fn locked(&self) -> &BTreeSet<String> {
+        &self.locked
+    }
+
+    /// Show the lock of the parameter called `name` in the top right corner of `rect` and toggle
+    /// it when clicked. The lock is only shown while `hovered` unless it's locked. Does nothing
+    /// when the editor didn't set up any locks.
+    pub fn show(ui: &mut Ui, rect: Rect, name: &str, hovered: bool, color: Color32) {
+        let mut locks = match Self::load(ui.ctx()) {
+            Some(locks) => locks,
+            None => return,
+        };
+        let id = match locks.ids.get(name) {
+            Some(id) => id.clone(),
+            None => return,
+        };
+
+        let lock_rect = Rect::from_min_size(
+            rect.right_top() - vec2(LOCK_SIZE, 0f32),
+            Vec2::splat(LOCK_SIZE),
+        );
+        let response = ui
+            .interact(lock_rect, ui.id().with(&id).with("lock"), Sense::click())
+            .on_hover_text("lock to keep this parameter when randomizing");
+        let clicked = response.clicked();
+        if clicked && !locks.locked.remove(&id) {
+            locks.locked.insert(id.clone());
+        }
+        let locked = locks.locked.contains(&id);
+        if clicked {
+            locks.store(ui.ctx());
+        }
+
+        if locked || hovered || response.hovered() {
+            let color = match locked {
+                true => color,
+                false => color.linear_multiply(0.4),
+            };
+            paint_lock(ui.painter(), lock_rect, color);
+        }
+    }
+}
+
+/// A padlock filling `rect`
+fn paint_lock(painter: &Painter, rect: Rect, color: Color32) {
+    let body = Rect::from_min_max(rect.left_center(), rect.right_bottom());
+    painter.circle_stroke(
+        pos2(rect.center().x, body.top()),
+        rect.width() * 0.3,
+        Stroke::new(1.5f32, color),
+    );
+    painter.rect_filled(body, 1f32, color);
+}
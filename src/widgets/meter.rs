@@ -0,0 +1,58 @@
+use std::rc::Rc;
+
+use super::theme::Theme;
+use egui::*;
+
+/// A vertical bar showing a value in `0..=max`, like gain reduction in decibels
+pub struct LevelMeter<'a> {
+    value: f32,
+    max: f32,
+    label: &'a str,
+    pub size: Vec2,
+    pub theme: Option<Rc<Theme>>,
+}
+
+impl<'a> LevelMeter<'a> {
+    pub fn new(value: f32, max: f32, label: &'a str) -> Self {
+        Self {
+            value,
+            max,
+            label,
+            size: Vec2::new(12f32, 64f32),
+            theme: None,
+        }
+    }
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn theme(mut self, theme: Rc<Theme>) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+}
+
+impl<'a> Widget for LevelMeter<'a> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let theme = match self.theme.as_ref() {
+            Some(theme) => *theme.clone(),
+            None => Theme::current(ui.ctx()),
+        };
+
+        ui.vertical(|ui| {
+            let (response, paint) = ui.allocate_painter(self.size, Sense::hover());
+            let rect = response.rect;
+            paint.rect_filled(rect, 0f32, theme.colors.background_light);
+
+            // Gain reduction grows down from the top
+            let amount = (self.value / self.max).clamp(0f32, 1f32);
+            let mut bar = rect;
+            bar.set_height(rect.height() * amount);
+            paint.rect_filled(bar, 0f32, theme.colors.modulation);
+
+            ui.label(self.label);
+            response.on_hover_text(format!("{:.1} dB", self.value))
+        })
+        .inner
+    }
+}
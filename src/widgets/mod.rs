@@ -3,11 +3,19 @@ use nih_plug::prelude::{Param, ParamSetter};
 
 pub(crate) mod drag;
 pub mod envelope;
+pub mod goniometer;
 pub mod knob;
+pub mod lock;
+pub mod meter;
+pub mod pitch;
 pub mod slider;
 pub mod theme;
+pub mod waveform;
 
-pub use {envelope::Envelope, knob::Knob, slider::Slider, theme::*};
+pub use {
+    envelope::Envelope, goniometer::Goniometer, knob::Knob, lock::ParamLocks, meter::LevelMeter,
+    pitch::PitchDisplay, slider::Slider, theme::*, waveform::Waveform,
+};
 
 pub trait ParamControl<'a, P: Param>: Widget {
     fn from_param(param: &'a P, setter: &'a ParamSetter<'a>) -> Self;
@@ -0,0 +1,106 @@
+use std::rc::Rc;
+
+use super::theme::Theme;
+use crate::dsp::pitch_to_freq;
+use egui::*;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// The sounding pitch as a note name with its offset in cents and its frequency, with an arrow to
+/// the target note while gliding
+pub struct PitchDisplay {
+    /// Fractional MIDI note, none while no note is playing
+    pitch: Option<f32>,
+    /// The note the glide is heading for
+    glide_target: Option<f32>,
+    pub size: Vec2,
+    pub theme: Option<Rc<Theme>>,
+}
+
+impl PitchDisplay {
+    pub fn new(pitch: Option<f32>, glide_target: Option<f32>) -> Self {
+        Self {
+            pitch,
+            glide_target,
+            size: Vec2::new(72f32, 40f32),
+            theme: None,
+        }
+    }
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn theme(mut self, theme: Rc<Theme>) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+}
+
+impl Widget for PitchDisplay {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let theme = match self.theme.as_ref() {
+            Some(theme) => *theme.clone(),
+            None => Theme::current(ui.ctx()),
+        };
+
+        let (response, paint) = ui.allocate_painter(self.size, Sense::hover());
+        let rect = response.rect;
+        paint.rect_filled(rect, 0f32, theme.colors.background_light);
+
+        let pitch = match self.pitch {
+            Some(pitch) => pitch,
+            None => {
+                paint.text(
+                    rect.center(),
+                    Align2::CENTER_CENTER,
+                    "-",
+                    FontId::monospace(14f32),
+                    theme.colors.border,
+                );
+                return response;
+            }
+        };
+
+        let (name, cents) = note_name(pitch);
+        paint.text(
+            rect.center_top() + vec2(0f32, 2f32),
+            Align2::CENTER_TOP,
+            format!("{} {:+.0}c", name, cents),
+            FontId::monospace(14f32),
+            theme.colors.primary,
+        );
+        paint.text(
+            rect.center_bottom() - vec2(0f32, 2f32),
+            Align2::CENTER_BOTTOM,
+            format!("{:.1} Hz", pitch_to_freq(pitch)),
+            FontId::monospace(10f32),
+            theme.colors.primary,
+        );
+        if let Some(target) = self.glide_target {
+            let (target_name, _) = note_name(target);
+            let arrow = match target > pitch {
+                true => '↗',
+                false => '↘',
+            };
+            paint.text(
+                rect.right_top() + vec2(-2f32, 2f32),
+                Align2::RIGHT_TOP,
+                arrow,
+                FontId::monospace(10f32),
+                theme.colors.modulation,
+            );
+            return response.on_hover_text(format!("gliding to {}", target_name));
+        }
+        response
+    }
+}
+
+/// The nearest note's name with its octave, and how many cents `pitch` is off from it
+fn note_name(pitch: f32) -> (String, f32) {
+    let note = pitch.round();
+    let octave = (note / 12f32).floor() as i32 - 1;
+    let name = NOTE_NAMES[note.rem_euclid(12f32) as usize];
+    (format!("{}{}", name, octave), (pitch - note) * 100f32)
+}
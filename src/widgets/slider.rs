@@ -24,7 +24,7 @@ where
     fn ui(self, ui: &mut Ui) -> Response {
         let theme = match self.theme.as_ref() {
             Some(theme) => *theme.clone(),
-            None => Theme::default(),
+            None => Theme::current(ui.ctx()),
         };
 
         let (response, paint) = ui.allocate_painter(self.size, Sense::click_and_drag());
@@ -1,4 +1,9 @@
-use egui::Color32;
+use crate::config::ThemeName;
+use egui::{Color32, Context};
+
+fundsp::lazy_static::lazy_static! {
+    static ref THEME_MEMORY_ID: egui::Id = egui::Id::new((file!(), 0));
+}
 
 #[derive(Copy, Clone)]
 pub struct Theme {
@@ -12,6 +17,7 @@ pub struct ThemeColors {
     pub background: Color32,
     pub background_light: Color32,
     pub border: Color32,
+    pub modulation: Color32,
 }
 
 #[derive(Copy, Clone)]
@@ -25,8 +31,48 @@ impl Default for Theme {
                 background: Color32::from_rgb(27, 25, 32),
                 background_light: Color32::from_rgb(46, 45, 91),
                 border: Color32::from_rgba_unmultiplied(255, 255, 255, 50),
+                modulation: Color32::from_rgb(233, 181, 73),
             },
             spacing: ThemeSpacing {},
         }
     }
 }
+
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            colors: ThemeColors {
+                primary: Color32::from_rgb(22, 140, 72),
+                background: Color32::from_rgb(236, 234, 240),
+                background_light: Color32::from_rgb(206, 204, 236),
+                border: Color32::from_rgba_unmultiplied(0, 0, 0, 50),
+                modulation: Color32::from_rgb(186, 124, 16),
+            },
+            spacing: ThemeSpacing {},
+        }
+    }
+
+    pub fn named(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Dark => Self::default(),
+            ThemeName::Light => Self::light(),
+        }
+    }
+
+    /// Make this the theme of every widget that isn't given one, along with egui's own widgets
+    pub fn store(self, ctx: &Context, name: ThemeName) {
+        ctx.memory().data.insert_temp(*THEME_MEMORY_ID, self);
+        ctx.set_visuals(match name {
+            ThemeName::Dark => egui::Visuals::dark(),
+            ThemeName::Light => egui::Visuals::light(),
+        });
+    }
+
+    /// The theme the editor stored, or the default theme
+    pub fn current(ctx: &Context) -> Self {
+        ctx.memory()
+            .data
+            .get_temp(*THEME_MEMORY_ID)
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,68 @@
+use std::rc::Rc;
+
+use super::theme::Theme;
+use egui::*;
+
+/// Draws a single cycle of a waveform with values in `-1..=1`
+pub struct Waveform<'a> {
+    samples: &'a [f32],
+    pub size: Vec2,
+    pub theme: Option<Rc<Theme>>,
+}
+
+impl<'a> Waveform<'a> {
+    pub fn new(samples: &'a [f32]) -> Self {
+        Self {
+            samples,
+            size: Vec2::new(64f32, 48f32),
+            theme: None,
+        }
+    }
+    pub fn size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+    pub fn theme(mut self, theme: Rc<Theme>) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+}
+
+impl<'a> Widget for Waveform<'a> {
+    fn ui(self, ui: &mut Ui) -> Response {
+        let theme = match self.theme.as_ref() {
+            Some(theme) => *theme.clone(),
+            None => Theme::current(ui.ctx()),
+        };
+
+        let (response, paint) = ui.allocate_painter(self.size, Sense::hover());
+        let rect = response.rect;
+
+        paint.rect_filled(rect, 0f32, theme.colors.background_light);
+        paint.line_segment(
+            [rect.left_center(), rect.right_center()],
+            Stroke::new(1f32, theme.colors.border),
+        );
+
+        if self.samples.len() > 1 {
+            let last = (self.samples.len() - 1) as f32;
+            let points: Vec<Pos2> = self
+                .samples
+                .iter()
+                .enumerate()
+                .map(|(i, sample)| {
+                    Pos2::new(
+                        rect.left() + (i as f32 / last) * rect.width(),
+                        rect.center().y - sample.clamp(-1f32, 1f32) * rect.height() * 0.5,
+                    )
+                })
+                .collect();
+            paint.add(epaint::PathShape::line(
+                points,
+                Stroke::new(1.5f32, theme.colors.primary),
+            ));
+        }
+
+        response
+    }
+}
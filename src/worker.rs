@@ -0,0 +1,116 @@
+use egui::Id;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::JoinHandle,
+};
+
+/// A computation that produces a buffer for the editor to draw
+type Job = Box<dyn FnOnce() -> Vec<f32> + Send>;
+
+/// Runs expensive editor computations like previews on a background thread so they never stall
+/// the UI. Results are keyed by an id and the inputs they were computed from, and the last
+/// finished result keeps being shown until a newer one arrives.
+pub struct Worker {
+    jobs: Option<Sender<(Id, Vec<f32>, Job)>>,
+    results: Receiver<(Id, Vec<f32>, Vec<f32>)>,
+    thread: Option<JoinHandle<()>>,
+    /// The latest result for every id along with the inputs it was computed from
+    finished: HashMap<Id, (Vec<f32>, Vec<f32>)>,
+    /// The inputs of jobs that have been sent off but haven't finished yet
+    pending: HashMap<Id, Vec<f32>>,
+}
+
+impl Default for Worker {
+    fn default() -> Self {
+        let (jobs, job_receiver) = channel::<(Id, Vec<f32>, Job)>();
+        let (result_sender, results) = channel();
+        let thread = std::thread::Builder::new()
+            .name(String::from("synthy editor worker"))
+            .spawn(move || {
+                // Runs until the editor drops its end of the job channel
+                for (id, inputs, job) in job_receiver {
+                    if result_sender.send((id, inputs, job())).is_err() {
+                        break;
+                    }
+                }
+            })
+            .map_err(|err| log::error!("failed to spawn the editor worker: {}", err))
+            .ok();
+
+        Self {
+            jobs: Some(jobs),
+            results,
+            thread,
+            finished: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl Worker {
+    /// Get the result for `id` computed from `inputs`. If there's no result for these inputs yet
+    /// `job` gets queued and the previous result is returned in the meantime.
+    pub fn get(
+        &mut self,
+        id: Id,
+        inputs: &[f32],
+        job: impl FnOnce() -> Vec<f32> + Send + 'static,
+    ) -> &[f32] {
+        self.poll();
+
+        let up_to_date = self
+            .finished
+            .get(&id)
+            .map(|(finished_inputs, _)| finished_inputs.as_slice() == inputs)
+            .unwrap_or_default();
+        let queued = self
+            .pending
+            .get(&id)
+            .map(|pending_inputs| pending_inputs.as_slice() == inputs)
+            .unwrap_or_default();
+        if !up_to_date && !queued {
+            match (&self.jobs, &self.thread) {
+                (Some(jobs), Some(_)) => {
+                    if jobs.send((id, inputs.to_vec(), Box::new(job))).is_ok() {
+                        self.pending.insert(id, inputs.to_vec());
+                    }
+                }
+                // Without a worker thread the job has to run right here
+                _ => {
+                    self.finished.insert(id, (inputs.to_vec(), job()));
+                }
+            }
+        }
+
+        self.finished
+            .get(&id)
+            .map(|(_, result)| result.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Whether any job is still running or finished without being collected yet
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Collect everything the worker has finished since the last call
+    fn poll(&mut self) {
+        for (id, inputs, result) in self.results.try_iter() {
+            if self.pending.get(&id) == Some(&inputs) {
+                self.pending.remove(&id);
+            }
+            self.finished.insert(id, (inputs, result));
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the job channel stops the thread once it's done with its current job
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}